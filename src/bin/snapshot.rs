@@ -0,0 +1,134 @@
+//! Render the plot offscreen and save it as a PNG, without a window or browser
+//!
+//! Usage: `snapshot [--size WIDTHxHEIGHT] [--view BOOKMARK] [--samples N] [--points FILE]
+//! [--interpolate idw[:POWER[:RADIUS]]|rbf:RADIUS] [--fallback] OUTPUT.png`
+//!
+//! Images may be larger than the GPU's texture limit. `--samples` above 4 supersamples.
+//!
+//! `--points` plots an XYZ or CSV file of scattered samples instead of the function, framed to
+//! fit unless a view is given. They are triangulated unless `--interpolate` resamples them onto
+//! the grid.
+//!
+//! `--fallback` renders on a software adapter such as lavapipe, for machines without a GPU.
+
+use std::{error::Error, fs, process::ExitCode};
+
+use wasmgpu::{State, bookmark::Bookmark, scatter};
+
+struct Options {
+    output: String,
+    width: u32,
+    height: u32,
+    view: Option<String>,
+    samples: u32,
+    points: Option<String>,
+    interpolation: Option<scatter::Interpolation>,
+    force_fallback_adapter: bool,
+}
+
+impl Options {
+    const USAGE: &str = "Usage: snapshot [--size WIDTHxHEIGHT] [--view BOOKMARK] [--samples N] [--points FILE] [--interpolate idw[:POWER[:RADIUS]]|rbf:RADIUS] [--fallback] OUTPUT.png";
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut output = None;
+        let mut options = Self {
+            output: String::new(),
+            width: 1024,
+            height: 768,
+            view: None,
+            samples: 1,
+            points: None,
+            interpolation: None,
+            force_fallback_adapter: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--size" => {
+                    let size = args.next()?;
+                    let (width, height) = size.split_once('x')?;
+                    options.width = width.parse().ok().filter(|&width| width > 0)?;
+                    options.height = height.parse().ok().filter(|&height| height > 0)?;
+                }
+                "--view" => options.view = Some(args.next()?),
+                "--samples" => options.samples = args.next()?.parse().ok()?,
+                "--points" => options.points = Some(args.next()?),
+                "--interpolate" => {
+                    options.interpolation = Some(Self::parse_interpolation(&args.next()?)?)
+                }
+                "--fallback" => options.force_fallback_adapter = true,
+                _ if arg.starts_with("--") || output.is_some() => return None,
+                _ => output = Some(arg),
+            }
+        }
+
+        options.output = output?;
+        Some(options)
+    }
+
+    fn parse_interpolation(spec: &str) -> Option<scatter::Interpolation> {
+        let mut parts = spec.split(':');
+        let method = parts.next()?;
+        let mut parameters = parts.map(|part| part.parse::<f32>().ok());
+        let interpolation = match method {
+            "idw" => scatter::Interpolation::InverseDistance {
+                power: parameters.next().unwrap_or(Some(2.0))?,
+                radius: parameters.next().unwrap_or(Some(0.0))?,
+            },
+            "rbf" => scatter::Interpolation::GaussianRbf {
+                radius: parameters.next()??,
+            },
+            _ => return None,
+        };
+        parameters.next().is_none().then_some(interpolation)
+    }
+}
+
+fn snapshot(options: &Options) -> Result<(), Box<dyn Error>> {
+    // Backends can be picked with WGPU_BACKEND
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    // Captures bring their own targets, so the state's own one can be tiny
+    let mut state = pollster::block_on(State::new_offscreen(
+        1,
+        1,
+        instance,
+        options.force_fallback_adapter,
+    ))?;
+
+    if let Some(path) = &options.points {
+        let points = scatter::parse_points(&fs::read_to_string(path)?)?;
+        match options.interpolation {
+            Some(interpolation) => state.interpolate_scattered_points(&points, interpolation)?,
+            None => state.set_scattered_points(&points)?,
+        }
+        let bounds = state.surface_bounds();
+        state.fit_to_bounds(&bounds);
+    }
+
+    if let Some(view) = &options.view {
+        let bookmark = Bookmark::parse_with_defaults(view, state.bookmark())?;
+        state.apply_bookmark(&bookmark)?;
+    }
+
+    let capture = state.capture(options.width, options.height, options.samples)?;
+    let png = pollster::block_on(capture)?;
+    fs::write(&options.output, png)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let Some(options) = Options::parse(std::env::args().skip(1)) else {
+        eprintln!("{}", Options::USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    match snapshot(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Could not render {}: {err}", options.output);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Desktop viewer rendering the plot in a winit window
+//!
+//! Mouse, touch and keyboard input go through the same `Gestures` as the browser canvas.
+
+use std::{sync::Arc, time::Instant};
+
+use glam::Vec2;
+use wasmgpu::{
+    State,
+    input::{Action, Button, Command, Gestures, PointerInput, PointerKind},
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{Window, WindowId},
+};
+
+/// Pointer id used for the mouse, touches are numbered from one
+const MOUSE_ID: i32 = 0;
+
+/// Longest step fed to `State::update` so a stalled window does not jump
+const MAX_STEP_SECONDS: f32 = 0.1;
+
+struct Viewer {
+    window: Arc<Window>,
+    state: State,
+    gestures: Gestures,
+    cursor: Vec2,
+    modifiers: ModifiersState,
+    last_frame: Option<Instant>,
+}
+
+#[derive(Default)]
+struct App {
+    viewer: Option<Viewer>,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.viewer.is_some() {
+            return;
+        }
+
+        let attributes = Window::default_attributes()
+            .with_title("wasmgpu")
+            .with_inner_size(PhysicalSize::new(1024, 768));
+        let window = match event_loop.create_window(attributes) {
+            Ok(window) => Arc::new(window),
+            Err(err) => {
+                log::error!("Could not create window: {err}");
+                event_loop.exit();
+                return;
+            }
+        };
+
+        match Viewer::new(window) {
+            Ok(viewer) => self.viewer = Some(viewer),
+            Err(err) => {
+                log::error!("{err}");
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let Some(viewer) = self.viewer.as_mut() else {
+            return;
+        };
+
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                viewer.state.resize(size.width.max(1), size.height.max(1));
+                viewer.window.request_redraw();
+            }
+            WindowEvent::RedrawRequested => viewer.frame(),
+            WindowEvent::ModifiersChanged(modifiers) => viewer.modifiers = modifiers.state(),
+            WindowEvent::CursorMoved { position, .. } => {
+                viewer.cursor = Vec2::new(position.x as f32, position.y as f32);
+                let input = viewer.pointer_input(MOUSE_ID, PointerKind::Mouse, Button::Primary);
+                if let Some(command) = viewer.gestures.pointer_move(input) {
+                    viewer.apply(command);
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } => {
+                let button = match button {
+                    MouseButton::Left => Button::Primary,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Secondary,
+                    _ => Button::Other,
+                };
+                let input = viewer.pointer_input(MOUSE_ID, PointerKind::Mouse, button);
+                viewer.focus_viewport();
+                viewer.gestures.pointer_down(input);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                ..
+            } => viewer.gestures.pointer_up(MOUSE_ID),
+            WindowEvent::MouseWheel { delta, .. } => {
+                // Positive deltas scroll up here but down in the browser
+                let delta_y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => -y * Gestures::LINE_HEIGHT,
+                    MouseScrollDelta::PixelDelta(position) => -position.y as f32,
+                };
+                if let Some(command) = viewer.gestures.wheel(delta_y) {
+                    viewer.state.focus_viewport_at(viewer.cursor);
+                    viewer.apply(command);
+                }
+            }
+            WindowEvent::Touch(touch) => {
+                viewer.cursor = Vec2::new(touch.location.x as f32, touch.location.y as f32);
+                let id = touch.id as i32 + 1;
+                let input = viewer.pointer_input(id, PointerKind::Touch, Button::Primary);
+                match touch.phase {
+                    TouchPhase::Started => {
+                        viewer.focus_viewport();
+                        viewer.gestures.pointer_down(input);
+                    }
+                    TouchPhase::Moved => {
+                        if let Some(command) = viewer.gestures.pointer_move(input) {
+                            viewer.apply(command);
+                        }
+                    }
+                    TouchPhase::Ended | TouchPhase::Cancelled => viewer.gestures.pointer_up(id),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                // Leave shortcuts alone
+                if viewer.modifiers.control_key()
+                    || viewer.modifiers.alt_key()
+                    || viewer.modifiers.super_key()
+                {
+                    return;
+                }
+                match logical_key {
+                    Key::Named(NamedKey::Escape) => event_loop.exit(),
+                    Key::Character(key) => {
+                        if let Some(command) = viewer.gestures.key(&key) {
+                            viewer.apply(command);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Viewer {
+    fn new(window: Arc<Window>) -> Result<Self, wasmgpu::Error> {
+        let size = window.inner_size();
+        let (width, height) = (size.width.max(1), size.height.max(1));
+
+        log::info!("New viewer at resolution {width}, {height}");
+
+        // Backends can be picked with WGPU_BACKEND
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let surface = instance.create_surface(window.clone())?;
+        let state = pollster::block_on(State::new(width, height, instance, surface))?;
+
+        Ok(Self {
+            window,
+            state,
+            gestures: Gestures::default(),
+            cursor: Vec2::ZERO,
+            modifiers: ModifiersState::empty(),
+            last_frame: None,
+        })
+    }
+
+    /// Pointer input at the last known cursor position, in physical pixels like the surface
+    fn pointer_input(&self, id: i32, kind: PointerKind, button: Button) -> PointerInput {
+        PointerInput {
+            id,
+            kind,
+            button,
+            position: self.cursor,
+            shift: self.modifiers.shift_key(),
+        }
+    }
+
+    /// Apply the gesture about to start to the viewport under the cursor
+    fn focus_viewport(&mut self) {
+        if self.gestures.is_idle() {
+            self.state.focus_viewport_at(self.cursor);
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        if command == Command::Action(Action::SaveState) {
+            // There is no page URL to store the view in, print it so it can be restored later
+            println!("{}", self.state.bookmark());
+        } else if let Err(err) = self.state.apply_command(command) {
+            log::warn!("{err}");
+        }
+        self.window.request_redraw();
+    }
+
+    fn frame(&mut self) {
+        let now = Instant::now();
+        let dt = self.last_frame.map_or(0.0, |last| {
+            (now - last).as_secs_f32().clamp(0.0, MAX_STEP_SECONDS)
+        });
+
+        let animating = self.state.update(dt);
+        if let Err(err) = self.state.render() {
+            log::error!("{err}");
+        }
+
+        if animating {
+            self.last_frame = Some(now);
+            self.window.request_redraw();
+        } else {
+            self.last_frame = None;
+        }
+    }
+}
+
+fn main() -> Result<(), winit::error::EventLoopError> {
+    env_logger::init();
+
+    let event_loop = EventLoop::new()?;
+    event_loop.run_app(&mut App::default())
+}
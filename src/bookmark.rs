@@ -0,0 +1,380 @@
+//! Compact, URL-safe serialization of the camera and plot configuration
+//!
+//! A bookmark is a `~` separated list of fields, each a single letter key followed by its value,
+//! with `_` separating the components of multi-valued fields:
+//!
+//! ```text
+//! v2~t0_0_0~d12~z0.8410687~a1.1071488~nt~q0.34727675_0.21462885_0.47992468_0.77653437~f1.5707964~pp~ssinc~x-5_5~y-5_5~r255_255~m0~cgrey
+//! ```
+//!
+//! Only unreserved URL characters are used so bookmarks can be put in a query string or fragment
+//! without escaping. The version field always comes first. Missing fields keep their defaults
+//! and unknown fields are ignored. Bump `VERSION` whenever fields are added or change meaning, and
+//! keep parsing every older version so existing links keep working.
+//!
+//! Version 2 added the camera mode `n` (`t`urntable or trackball`b`all) and trackball orientation
+//! `q`. Version 1 bookmarks always open in turntable mode.
+
+use alloc::string::String;
+use core::{fmt, ops::RangeInclusive, str::FromStr};
+
+use glam::{Quat, Vec3};
+
+use crate::camera::{CameraMode, Projection};
+
+/// Version written by `Bookmark`'s `Display` implementation
+pub const VERSION: u32 = 2;
+
+/// Name of the function evaluated by evaluator.wgsl
+pub const FUNCTION: &str = "sinc";
+
+/// Name of the colour mapping applied by evaluator.wgsl
+pub const COLORMAP: &str = "grey";
+
+const FIELD_SEPARATOR: char = '~';
+const VALUE_SEPARATOR: char = '_';
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub target: Vec3,
+    pub distance: f32,
+    pub zenith: f32,
+    pub azimuth: f32,
+    pub mode: CameraMode,
+    /// Only used in `CameraMode::Trackball`
+    pub orientation: Quat,
+    pub fovy: f32,
+    pub projection: Projection,
+    pub function: String,
+    pub x_range: RangeInclusive<f32>,
+    pub y_range: RangeInclusive<f32>,
+    pub resolution: (u32, u32),
+    pub multisampling: bool,
+    pub colormap: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseBookmarkError {
+    MissingVersion,
+    UnsupportedVersion(u32),
+    InvalidField(char),
+    UnknownFunction(String),
+    UnknownColormap(String),
+}
+
+impl fmt::Display for ParseBookmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingVersion => write!(f, "Bookmark has no version"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Bookmark version {version} is not supported")
+            }
+            Self::InvalidField(key) => write!(f, "Bookmark field '{key}' is invalid"),
+            Self::UnknownFunction(name) => write!(f, "Unknown function '{name}'"),
+            Self::UnknownColormap(name) => write!(f, "Unknown colormap '{name}'"),
+        }
+    }
+}
+
+impl core::error::Error for ParseBookmarkError {}
+
+impl fmt::Display for Bookmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = FIELD_SEPARATOR;
+        let v = VALUE_SEPARATOR;
+        let mode = match self.mode {
+            CameraMode::Turntable => 't',
+            CameraMode::Trackball => 'b',
+        };
+        let projection = match self.projection {
+            Projection::Perspective => 'p',
+            Projection::Orthographic => 'o',
+        };
+
+        write!(f, "v{VERSION}")?;
+        write!(
+            f,
+            "{s}t{}{v}{}{v}{}",
+            self.target.x, self.target.y, self.target.z
+        )?;
+        write!(f, "{s}d{}", self.distance)?;
+        write!(f, "{s}z{}", self.zenith)?;
+        write!(f, "{s}a{}", self.azimuth)?;
+        write!(f, "{s}n{mode}")?;
+        let [x, y, z, w] = self.orientation.to_array();
+        write!(f, "{s}q{x}{v}{y}{v}{z}{v}{w}")?;
+        write!(f, "{s}f{}", self.fovy)?;
+        write!(f, "{s}p{projection}")?;
+        write!(f, "{s}s{}", self.function)?;
+        write!(f, "{s}x{}{v}{}", self.x_range.start(), self.x_range.end())?;
+        write!(f, "{s}y{}{v}{}", self.y_range.start(), self.y_range.end())?;
+        write!(f, "{s}r{}{v}{}", self.resolution.0, self.resolution.1)?;
+        write!(f, "{s}m{}", u8::from(self.multisampling))?;
+        write!(f, "{s}c{}", self.colormap)
+    }
+}
+
+impl Bookmark {
+    /// Parse `s` on top of the fields already in `self`
+    fn apply_str(&mut self, s: &str) -> Result<(), ParseBookmarkError> {
+        let mut fields = s.split(FIELD_SEPARATOR);
+
+        let version = fields
+            .next()
+            .and_then(|field| field.strip_prefix('v'))
+            .ok_or(ParseBookmarkError::MissingVersion)?
+            .parse::<u32>()
+            .map_err(|_| ParseBookmarkError::MissingVersion)?;
+        if version == 0 || version > VERSION {
+            return Err(ParseBookmarkError::UnsupportedVersion(version));
+        }
+        if version < 2 {
+            self.mode = CameraMode::Turntable;
+        }
+
+        for field in fields {
+            let mut chars = field.chars();
+            let Some(key) = chars.next() else {
+                continue;
+            };
+            let value = chars.as_str();
+            let invalid = || ParseBookmarkError::InvalidField(key);
+
+            match key {
+                't' => {
+                    let [x, y, z] = parse_values(value).ok_or_else(invalid)?;
+                    self.target = Vec3::new(x, y, z);
+                }
+                'd' => [self.distance] = parse_values(value).ok_or_else(invalid)?,
+                'z' => [self.zenith] = parse_values(value).ok_or_else(invalid)?,
+                'a' => [self.azimuth] = parse_values(value).ok_or_else(invalid)?,
+                'n' => {
+                    self.mode = match value {
+                        "t" => CameraMode::Turntable,
+                        "b" => CameraMode::Trackball,
+                        _ => return Err(invalid()),
+                    }
+                }
+                'q' => {
+                    let [x, y, z, w] = parse_values(value).ok_or_else(invalid)?;
+                    let orientation = Quat::from_xyzw(x, y, z, w);
+                    if orientation.length_squared() == 0.0 {
+                        return Err(invalid());
+                    }
+                    self.orientation = orientation.normalize();
+                }
+                'f' => [self.fovy] = parse_values(value).ok_or_else(invalid)?,
+                'p' => {
+                    self.projection = match value {
+                        "p" => Projection::Perspective,
+                        "o" => Projection::Orthographic,
+                        _ => return Err(invalid()),
+                    }
+                }
+                's' => {
+                    if value != FUNCTION {
+                        return Err(ParseBookmarkError::UnknownFunction(value.into()));
+                    }
+                    self.function = value.into();
+                }
+                'x' => {
+                    let [start, end] = parse_values(value).ok_or_else(invalid)?;
+                    self.x_range = start..=end;
+                }
+                'y' => {
+                    let [start, end] = parse_values(value).ok_or_else(invalid)?;
+                    self.y_range = start..=end;
+                }
+                'r' => {
+                    let [width, height] = parse_values(value).ok_or_else(invalid)?;
+                    self.resolution = (width, height);
+                }
+                'm' => {
+                    self.multisampling = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(invalid()),
+                    }
+                }
+                'c' => {
+                    if value != COLORMAP {
+                        return Err(ParseBookmarkError::UnknownColormap(value.into()));
+                    }
+                    self.colormap = value.into();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a bookmark, taking any fields it does not contain from `defaults`
+    pub fn parse_with_defaults(s: &str, defaults: Bookmark) -> Result<Self, ParseBookmarkError> {
+        let mut bookmark = defaults;
+        bookmark.apply_str(s)?;
+        Ok(bookmark)
+    }
+}
+
+/// Number stored in a bookmark field
+trait Value: FromStr + Copy + Default {
+    /// Whether a parsed value can be used, `str::parse` also accepts `inf` and `NaN` floats
+    fn is_valid(self) -> bool {
+        true
+    }
+}
+
+impl Value for u32 {}
+
+impl Value for f32 {
+    fn is_valid(self) -> bool {
+        self.is_finite()
+    }
+}
+
+/// Parse exactly `N` valid values separated by `VALUE_SEPARATOR`
+fn parse_values<T: Value, const N: usize>(s: &str) -> Option<[T; N]> {
+    let mut parsed = [T::default(); N];
+    let mut values = s.split(VALUE_SEPARATOR);
+    for slot in &mut parsed {
+        *slot = values
+            .next()?
+            .parse()
+            .ok()
+            .filter(|value: &T| value.is_valid())?;
+    }
+    values.next().is_none().then_some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    fn defaults() -> Bookmark {
+        Bookmark {
+            target: Vec3::ZERO,
+            distance: 12.0,
+            zenith: 0.8,
+            azimuth: 1.1,
+            mode: CameraMode::Trackball,
+            orientation: Quat::IDENTITY,
+            fovy: 1.5,
+            projection: Projection::Perspective,
+            function: FUNCTION.into(),
+            x_range: -5.0..=5.0,
+            y_range: -5.0..=5.0,
+            resolution: (255, 255),
+            multisampling: false,
+            colormap: COLORMAP.into(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let bookmark = Bookmark {
+            target: Vec3::new(1.5, -2.0, 0.25),
+            distance: 3.75,
+            mode: CameraMode::Trackball,
+            orientation: Quat::from_rotation_x(0.5),
+            projection: Projection::Orthographic,
+            x_range: -1.0..=2.5,
+            resolution: (40, 10),
+            multisampling: true,
+            ..defaults()
+        };
+        let s = bookmark.to_string();
+        assert!(s.starts_with("v2~"));
+        assert_eq!(Bookmark::parse_with_defaults(&s, defaults()), Ok(bookmark));
+    }
+
+    #[test]
+    fn reads_version_1_links() {
+        let v1 = "v1~t0_0_1~d8~z0.5~a0.25~f1~po~ssinc~x-2_2~y-3_3~r64_32~m1~cgrey";
+        let bookmark = Bookmark::parse_with_defaults(v1, defaults()).unwrap();
+        assert_eq!(
+            bookmark,
+            Bookmark {
+                target: Vec3::Z,
+                distance: 8.0,
+                zenith: 0.5,
+                azimuth: 0.25,
+                // Version 1 had no trackball
+                mode: CameraMode::Turntable,
+                fovy: 1.0,
+                projection: Projection::Orthographic,
+                x_range: -2.0..=2.0,
+                y_range: -3.0..=3.0,
+                resolution: (64, 32),
+                multisampling: true,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_and_unknown_fields() {
+        let bookmark = Bookmark::parse_with_defaults("v2~d4~~w9", defaults()).unwrap();
+        assert_eq!(
+            bookmark,
+            Bookmark {
+                distance: 4.0,
+                ..defaults()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_bookmarks() {
+        let parse = |s| Bookmark::parse_with_defaults(s, defaults());
+        assert_eq!(parse(""), Err(ParseBookmarkError::MissingVersion));
+        assert_eq!(parse("d4~v2"), Err(ParseBookmarkError::MissingVersion));
+        assert_eq!(parse("v0"), Err(ParseBookmarkError::UnsupportedVersion(0)));
+        assert_eq!(parse("v3"), Err(ParseBookmarkError::UnsupportedVersion(3)));
+        assert_eq!(parse("v2~t1_2"), Err(ParseBookmarkError::InvalidField('t')));
+        assert_eq!(
+            parse("v2~r1_2_3"),
+            Err(ParseBookmarkError::InvalidField('r'))
+        );
+        assert_eq!(
+            parse("v2~r-1_2"),
+            Err(ParseBookmarkError::InvalidField('r'))
+        );
+        assert_eq!(parse("v2~nx"), Err(ParseBookmarkError::InvalidField('n')));
+        assert_eq!(
+            parse("v2~q0_0_0_0"),
+            Err(ParseBookmarkError::InvalidField('q'))
+        );
+        assert_eq!(parse("v2~m2"), Err(ParseBookmarkError::InvalidField('m')));
+        assert_eq!(
+            parse("v2~scos"),
+            Err(ParseBookmarkError::UnknownFunction("cos".into()))
+        );
+        assert_eq!(
+            parse("v2~cjet"),
+            Err(ParseBookmarkError::UnknownColormap("jet".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_non_finite_numbers() {
+        for s in [
+            "v2~dNaN",
+            "v2~finf",
+            "v2~z-inf",
+            "v2~t0_NaN_0",
+            "v2~x-inf_5",
+            "v2~q0_0_0_inf",
+        ] {
+            let key = s.chars().nth(3).unwrap();
+            assert_eq!(
+                Bookmark::parse_with_defaults(s, defaults()),
+                Err(ParseBookmarkError::InvalidField(key))
+            );
+        }
+    }
+}
@@ -0,0 +1,750 @@
+use glam::{EulerRot, Mat4, Quat, Vec2, Vec3};
+
+use core::{
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, PI, TAU},
+    str::FromStr,
+};
+
+#[inline(always)]
+fn float_modulo(a: f32, b: f32) -> f32 {
+    let r = a % b;
+    if r < 0.0 { r + b.abs() } else { r }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel projection sized to match the perspective view at the focus center
+    Orthographic,
+}
+
+impl FromStr for Projection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Self::Perspective),
+            "ortho" | "orthographic" => Ok(Self::Orthographic),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How rotation gestures orient the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Orbit by `zenith` and `azimuth` keeping Z up
+    Turntable,
+    /// Free rotation on a virtual sphere, allowing roll and views past the poles
+    Trackball,
+}
+
+impl FromStr for CameraMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "turntable" => Ok(Self::Turntable),
+            "trackball" | "arcball" => Ok(Self::Trackball),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Standard view directions, named after the side of the plot the camera looks from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum View {
+    Top,
+    Bottom,
+    /// Looking along +Y
+    Front,
+    Back,
+    /// Looking along +X
+    Left,
+    Right,
+    Isometric,
+}
+
+impl View {
+    /// Zenith and azimuth angles of the view
+    fn angles(self) -> (f32, f32) {
+        match self {
+            Self::Top => (0.0, 0.0),
+            Self::Bottom => (PI, 0.0),
+            Self::Front => (FRAC_PI_2, 0.0),
+            Self::Back => (FRAC_PI_2, PI),
+            Self::Left => (FRAC_PI_2, 3.0 * FRAC_PI_2),
+            Self::Right => (FRAC_PI_2, FRAC_PI_2),
+            // Equal angles to all three axes
+            Self::Isometric => (libm::acosf(1.0 / libm::sqrtf(3.0)), FRAC_PI_4),
+        }
+    }
+}
+
+impl FromStr for View {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top" => Ok(Self::Top),
+            "bottom" => Ok(Self::Bottom),
+            "front" => Ok(Self::Front),
+            "back" => Ok(Self::Back),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "isometric" | "iso" => Ok(Self::Isometric),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x, a.y, a.z),
+            Vec3::new(b.x, a.y, a.z),
+            Vec3::new(a.x, b.y, a.z),
+            Vec3::new(b.x, b.y, a.z),
+            Vec3::new(a.x, a.y, b.z),
+            Vec3::new(b.x, a.y, b.z),
+            Vec3::new(a.x, b.y, b.z),
+            Vec3::new(b.x, b.y, b.z),
+        ]
+    }
+}
+
+#[derive(Clone)]
+pub struct Camera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub zenith: f32,
+    pub azimuth: f32,
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    /// Only bounds the orthographic projection when `reverse_z` is set, perspective then has an
+    /// infinite far plane
+    pub zfar: f32,
+    /// Map depth so the near plane is 1 and the far plane 0, see `State::REVERSE_Z`
+    pub reverse_z: bool,
+    pub projection: Projection,
+    pub mode: CameraMode,
+    /// Orientation used in `CameraMode::Trackball`, `zenith` and `azimuth` are only used by
+    /// `CameraMode::Turntable`
+    trackball_orientation: Quat,
+    /// Current mix between perspective (0.0) and orthographic (1.0), animated towards `projection`
+    ortho_blend: f32,
+}
+
+#[allow(unused)]
+impl Camera {
+    const CLOSEST: f32 = 0.1;
+    const FARTHEST: f32 = 15.0;
+    /// Narrowest and widest vertical fields of view, a degree short of nothing and a half turn
+    const MIN_FOVY: f32 = PI / 180.0;
+    const MAX_FOVY: f32 = PI - Self::MIN_FOVY;
+    const ZENITH_CLAMP: f32 = 0.0;
+    const PROJECTION_TRANSITION_SECONDS: f32 = 0.35;
+
+    const INITIAL_DISTANCE: f32 = 12.0;
+    const INITIAL_ZENITH: f32 = 0.841_068_7;
+    const INITIAL_AZIMUTH: f32 = 1.107_148_8;
+
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            target: Vec3::ZERO,
+            distance: Self::INITIAL_DISTANCE,
+            zenith: Self::INITIAL_ZENITH,
+            azimuth: Self::INITIAL_AZIMUTH,
+            aspect,
+            fovy: f32::to_radians(90.0),
+            znear: 0.1,
+            zfar: 100.0,
+            reverse_z: false,
+            projection: Projection::Perspective,
+            mode: CameraMode::Turntable,
+            trackball_orientation: turntable_orientation(
+                Self::INITIAL_ZENITH,
+                Self::INITIAL_AZIMUTH,
+            ),
+            ortho_blend: 0.0,
+        }
+    }
+
+    /// Rotation from camera space into world space
+    pub fn orientation(&self) -> Quat {
+        match self.mode {
+            CameraMode::Turntable => turntable_orientation(self.zenith, self.azimuth),
+            CameraMode::Trackball => self.trackball_orientation,
+        }
+    }
+
+    /// Switch rotation modes, carrying the view direction over
+    ///
+    /// Roll is lost when switching from the trackball to the turntable.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == self.mode {
+            return;
+        }
+        match mode {
+            CameraMode::Turntable => {
+                (self.zenith, self.azimuth) = turntable_angles(self.trackball_orientation);
+            }
+            CameraMode::Trackball => {
+                self.trackball_orientation = turntable_orientation(self.zenith, self.azimuth);
+            }
+        }
+        self.mode = mode;
+    }
+
+    /// Zenith and azimuth of the current view direction in either mode
+    pub fn angles(&self) -> (f32, f32) {
+        match self.mode {
+            CameraMode::Turntable => (self.zenith, self.azimuth),
+            CameraMode::Trackball => turntable_angles(self.trackball_orientation),
+        }
+    }
+
+    /// Point the camera along `zenith` and `azimuth` with Z up in either mode
+    pub fn set_angles(&mut self, zenith: f32, azimuth: f32) {
+        self.zenith = zenith.clamp(Self::ZENITH_CLAMP, PI - Self::ZENITH_CLAMP);
+        self.azimuth = float_modulo(azimuth, TAU);
+        self.trackball_orientation = turntable_orientation(self.zenith, self.azimuth);
+    }
+
+    pub fn set_trackball_orientation(&mut self, orientation: Quat) {
+        self.trackball_orientation = orientation.normalize();
+    }
+
+    /// Rotate the camera about its own axes in `CameraMode::Trackball`
+    pub fn rotate_local(&mut self, rotation: Quat) {
+        self.trackball_orientation = (self.trackball_orientation * rotation).normalize();
+    }
+
+    /// Camera space rotation that makes the scene follow a drag between two points in NDC
+    ///
+    /// Points are projected onto a virtual sphere filling the smaller viewport dimension, blended
+    /// into a hyperbolic sheet outside it so drags beyond the sphere still rotate smoothly.
+    pub fn trackball_rotation(&self, from: Vec2, to: Vec2) -> Quat {
+        let scale = if self.aspect > 1.0 {
+            Vec2::new(self.aspect, 1.0)
+        } else {
+            Vec2::new(1.0, 1.0 / self.aspect)
+        };
+
+        let project = |p: Vec2| {
+            let p = p * scale;
+            let d2 = p.length_squared();
+            let z = if d2 <= 0.5 {
+                libm::sqrtf(1.0 - d2)
+            } else {
+                0.5 / libm::sqrtf(d2)
+            };
+            p.extend(z).normalize()
+        };
+
+        // The scene turns from `from` to `to` so the camera turns the opposite way
+        Quat::from_rotation_arc(project(to), project(from))
+    }
+
+    pub fn view_proj(&self) -> Mat4 {
+        self.proj() * self.view()
+    }
+
+    /// World to view space, looking down -Z with Y up
+    pub fn view(&self) -> Mat4 {
+        let center = Mat4::from_translation(-self.target);
+        let quat = self.orientation();
+        let view =
+            Mat4::from_rotation_translation(quat.inverse(), Vec3::new(0.0, 0.0, -self.distance));
+        view * center
+    }
+
+    /// View to clip space
+    pub fn proj(&self) -> Mat4 {
+        let perspective = if self.reverse_z {
+            Mat4::perspective_infinite_reverse_rh(self.fovy, self.aspect, self.znear)
+        } else {
+            Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar)
+        };
+        if self.ortho_blend <= 0.0 {
+            return perspective;
+        }
+
+        // Match the perspective frustum's extents at the focus center so dollying still zooms
+        let half_height = self.distance * libm::tanf(self.fovy * 0.5);
+        let half_width = half_height * self.aspect;
+
+        // There is no eye in a parallel projection so do not clip what lies behind it
+        let (near, far) = if self.reverse_z {
+            (self.zfar, -self.zfar)
+        } else {
+            (-self.zfar, self.zfar)
+        };
+        let orthographic = Mat4::orthographic_rh(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            near,
+            far,
+        );
+        if self.ortho_blend >= 1.0 {
+            return orthographic;
+        }
+
+        let t = self.ortho_blend;
+        let t = t * t * (3.0 - 2.0 * t);
+        perspective * (1.0 - t) + orthographic * t
+    }
+
+    /// Switch projections, blending between them over `Self::PROJECTION_TRANSITION_SECONDS`
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Switch projections without a transition
+    pub fn jump_to_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.ortho_blend = match projection {
+            Projection::Perspective => 0.0,
+            Projection::Orthographic => 1.0,
+        };
+    }
+
+    /// Look where `leader` looks, keeping this camera's aspect ratio
+    pub fn follow(&mut self, leader: &Camera) {
+        let aspect = self.aspect;
+        self.clone_from(leader);
+        self.aspect = aspect;
+    }
+
+    /// Advance animations by `dt` seconds, returning whether any are still in progress
+    pub fn update(&mut self, dt: f32) -> bool {
+        let goal = match self.projection {
+            Projection::Perspective => 0.0,
+            Projection::Orthographic => 1.0,
+        };
+        let step = dt / Self::PROJECTION_TRANSITION_SECONDS;
+        self.ortho_blend = if self.ortho_blend < goal {
+            (self.ortho_blend + step).min(goal)
+        } else {
+            (self.ortho_blend - step).max(goal)
+        };
+        self.ortho_blend != goal
+    }
+
+    /// World space ray through a point in normalized device coordinates
+    ///
+    /// Returns the ray's origin on the near plane and its normalized direction.
+    pub fn ray(&self, ndc: Vec2) -> (Vec3, Vec3) {
+        // The far plane is at infinity with reverse-Z so take a second point part way there
+        let (near_depth, far_depth) = if self.reverse_z {
+            (1.0, 0.5)
+        } else {
+            (0.0, 1.0)
+        };
+        let inverse = self.view_proj().inverse();
+        let near = inverse.project_point3(ndc.extend(near_depth));
+        let far = inverse.project_point3(ndc.extend(far_depth));
+        (near, (far - near).normalize())
+    }
+
+    /// Rotate from the Z axis in radians relative to its current rotation
+    ///
+    /// In `CameraMode::Trackball` this tilts about the camera's horizontal axis without clamping.
+    pub fn rotate_zenith(&mut self, angle: f32) {
+        match self.mode {
+            CameraMode::Turntable => {
+                self.zenith =
+                    (self.zenith + angle).clamp(Self::ZENITH_CLAMP, PI - Self::ZENITH_CLAMP);
+            }
+            CameraMode::Trackball => self.rotate_local(Quat::from_rotation_x(angle)),
+        }
+    }
+
+    /// Rotate about the XY plane in radians relative to its current rotation
+    ///
+    /// In `CameraMode::Trackball` this turns about the camera's vertical axis.
+    pub fn rotate_azimuth(&mut self, angle: f32) {
+        match self.mode {
+            CameraMode::Turntable => self.azimuth = float_modulo(self.azimuth + angle, TAU),
+            CameraMode::Trackball => self.rotate_local(Quat::from_rotation_y(angle)),
+        }
+    }
+
+    /// Dolly into or out of the focus center relative to its current position
+    ///
+    /// This is multiplicative. Values < 1.0 dolly in and values > 1.0 dolly out.
+    /// Values are clamped between `Self::CLOSEST` and `SELF::FARTHEST`.
+    pub fn move_distance(&mut self, distance: f32) {
+        self.distance *= 1.0 - distance;
+        self.distance = self.distance.clamp(Self::CLOSEST, Self::FARTHEST);
+    }
+
+    /// Set the distance from the focus center, clamped between `Self::CLOSEST` and
+    /// `Self::FARTHEST`
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.clamp(Self::CLOSEST, Self::FARTHEST);
+    }
+
+    /// Set the vertical field of view in radians, clamped to more than nothing and less than a
+    /// half turn
+    pub fn set_fovy(&mut self, fovy: f32) {
+        self.fovy = fovy.clamp(Self::MIN_FOVY, Self::MAX_FOVY);
+    }
+
+    /// Translate the focus center parallel to the screen
+    ///
+    /// `dx` and `dy` are fractions of the viewport's width and height, positive to the right and
+    /// down. The scene follows the cursor at the focus center's depth, so panning is faster when
+    /// dollied out.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let view_height = 2.0 * self.distance * libm::tanf(self.fovy * 0.5);
+        let view_width = view_height * self.aspect;
+
+        let orientation = self.orientation();
+        let right = orientation * Vec3::X;
+        let up = orientation * Vec3::Y;
+
+        self.target += up * (dy * view_height) - right * (dx * view_width);
+    }
+
+    /// Look from one of the standard directions, keeping the target and distance
+    pub fn set_view(&mut self, view: View) {
+        let (zenith, azimuth) = view.angles();
+        self.set_angles(zenith, azimuth);
+    }
+
+    /// Return to the initial target, distance and angles, keeping the projection
+    pub fn reset(&mut self) {
+        self.target = Vec3::ZERO;
+        self.distance = Self::INITIAL_DISTANCE;
+        self.set_angles(Self::INITIAL_ZENITH, Self::INITIAL_AZIMUTH);
+    }
+
+    /// Center on `bounds` and dolly so it just fits the viewport from the current direction
+    ///
+    /// Fits the projection being transitioned to. The distance is still clamped between
+    /// `Self::CLOSEST` and `Self::FARTHEST`.
+    pub fn fit_to_bounds(&mut self, bounds: &Aabb) {
+        self.target = bounds.center();
+
+        let orientation = self.orientation();
+        let (right, up, back) = (
+            orientation * Vec3::X,
+            orientation * Vec3::Y,
+            orientation * Vec3::Z,
+        );
+        let tan_half_y = libm::tanf(self.fovy * 0.5);
+        let tan_half_x = tan_half_y * self.aspect;
+
+        let distance = bounds
+            .corners()
+            .iter()
+            .map(|&corner| {
+                let p = corner - self.target;
+                let (x, y, z) = (p.dot(right).abs(), p.dot(up).abs(), p.dot(back));
+                match self.projection {
+                    // The corner's depth in front of the eye must cover its lateral offset
+                    Projection::Perspective => (z + x / tan_half_x)
+                        .max(z + y / tan_half_y)
+                        .max(z + self.znear),
+                    Projection::Orthographic => (x / tan_half_x).max(y / tan_half_y),
+                }
+            })
+            .fold(0.0, f32::max);
+
+        self.distance = distance.clamp(Self::CLOSEST, Self::FARTHEST);
+    }
+
+    /// Construct an X, Y, Z coordinate from the `distance`, `zenith`, and `azimuth` coordinate
+    pub fn spherical_to_rect(&self) -> Vec3 {
+        let sc_zenith = libm::sincosf(self.zenith);
+        let sc_azimuth = libm::sincosf(self.azimuth);
+
+        Vec3 {
+            x: self.distance * sc_zenith.0 * sc_azimuth.1,
+            y: self.distance * sc_zenith.0 * sc_azimuth.0,
+            z: self.distance * sc_zenith.1,
+        }
+    }
+}
+
+/// Camera orientation for turntable angles, the camera's Z axis points from the target to the eye
+fn turntable_orientation(zenith: f32, azimuth: f32) -> Quat {
+    Quat::from_euler(EulerRot::ZXY, azimuth, zenith, 0.0)
+}
+
+/// Turntable angles of an arbitrary orientation's view direction, discarding roll
+fn turntable_angles(orientation: Quat) -> (f32, f32) {
+    let back = orientation * Vec3::Z;
+    let zenith = libm::acosf(back.z.clamp(-1.0, 1.0));
+
+    // Looking straight up or down the azimuth is only defined by the camera's up vector
+    let azimuth = if back.truncate().length_squared() > 1e-8 {
+        libm::atan2f(back.x, -back.y)
+    } else {
+        let up = orientation * Vec3::Y;
+        let up = if back.z > 0.0 { up } else { -up };
+        libm::atan2f(-up.x, up.y)
+    };
+
+    (zenith, float_modulo(azimuth, TAU))
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    /// Normalized device coordinates of a world space point
+    fn ndc(camera: &Camera, point: Vec3) -> Vec3 {
+        camera.view_proj().project_point3(point)
+    }
+
+    #[test]
+    fn projections_blend_over_the_transition() {
+        let mut camera = Camera::new(1.5);
+        let perspective = camera.proj();
+        camera.set_projection(Projection::Orthographic);
+        assert_eq!(camera.proj(), perspective);
+
+        // Halfway through, the eased blend is halfway too
+        assert!(camera.update(Camera::PROJECTION_TRANSITION_SECONDS * 0.5));
+        let halfway = camera.proj();
+        assert!(!camera.update(Camera::PROJECTION_TRANSITION_SECONDS));
+        let orthographic = camera.proj();
+        assert!(halfway.abs_diff_eq((perspective + orthographic) * 0.5, 1e-6));
+
+        camera.set_projection(Projection::Perspective);
+        assert!(!camera.update(2.0 * Camera::PROJECTION_TRANSITION_SECONDS));
+        assert_eq!(camera.proj(), perspective);
+    }
+
+    #[test]
+    fn orthographic_matches_perspective_at_the_focus_center() {
+        let mut camera = Camera::new(1.5);
+        camera.target = Vec3::new(1.0, -2.0, 0.5);
+        let orientation = camera.orientation();
+        let beside = camera.target + orientation * Vec3::new(2.0, -1.0, 0.0);
+        let perspective = ndc(&camera, beside);
+
+        camera.jump_to_projection(Projection::Orthographic);
+        let orthographic = ndc(&camera, beside);
+        assert!(
+            orthographic
+                .truncate()
+                .abs_diff_eq(perspective.truncate(), 1e-5)
+        );
+
+        // Depth no longer shrinks anything
+        let behind = beside - orientation * Vec3::Z * 5.0;
+        assert!(
+            ndc(&camera, behind)
+                .truncate()
+                .abs_diff_eq(orthographic.truncate(), 1e-5)
+        );
+    }
+
+    #[test]
+    fn fitted_bounds_stay_in_the_frustum() {
+        let bounds = Aabb {
+            min: Vec3::new(-2.0, -1.0, -0.5),
+            max: Vec3::new(3.0, 1.0, 1.5),
+        };
+        for projection in [Projection::Perspective, Projection::Orthographic] {
+            for view in [View::Top, View::Front, View::Isometric] {
+                let mut camera = Camera::new(1.5);
+                camera.jump_to_projection(projection);
+                camera.set_view(view);
+                camera.fit_to_bounds(&bounds);
+                assert_eq!(camera.target, bounds.center());
+
+                let corners = bounds.corners().map(|corner| ndc(&camera, corner));
+                for corner in corners {
+                    assert!(corner.x.abs() <= 1.0 + 1e-4 && corner.y.abs() <= 1.0 + 1e-4);
+                    assert!((0.0..=1.0).contains(&corner.z), "{projection:?} {view:?}");
+                }
+                // Just fits, so some corner touches an edge
+                let extent = corners
+                    .iter()
+                    .map(|c| c.x.abs().max(c.y.abs()))
+                    .fold(0.0, f32::max);
+                assert!(extent > 0.99, "{projection:?} {view:?} {extent}");
+            }
+        }
+    }
+
+    #[test]
+    fn views_look_from_their_side() {
+        let mut camera = Camera::new(1.0);
+        // The camera's Z axis points from the target to the eye
+        for (view, back) in [
+            (View::Top, Vec3::Z),
+            (View::Bottom, Vec3::NEG_Z),
+            (View::Front, Vec3::NEG_Y),
+            (View::Back, Vec3::Y),
+            (View::Left, Vec3::NEG_X),
+            (View::Right, Vec3::X),
+            (View::Isometric, Vec3::new(1.0, -1.0, 1.0).normalize()),
+        ] {
+            camera.set_view(view);
+            assert!(
+                (camera.orientation() * Vec3::Z).abs_diff_eq(back, 1e-6),
+                "{view:?}"
+            );
+        }
+        assert_eq!("iso".parse(), Ok(View::Isometric));
+    }
+
+    #[test]
+    fn reset_keeps_the_projection() {
+        let mut camera = Camera::new(1.0);
+        let initial = camera.view();
+        camera.jump_to_projection(Projection::Orthographic);
+        camera.pan(0.3, 0.1);
+        camera.move_distance(0.5);
+        camera.set_view(View::Left);
+
+        camera.reset();
+        assert!(camera.view().abs_diff_eq(initial, 1e-5));
+        assert_eq!(camera.projection, Projection::Orthographic);
+    }
+
+    #[test]
+    fn trackball_drags_round_trip() {
+        let mut camera = Camera::new(1.5);
+        camera.set_mode(CameraMode::Trackball);
+        let start = camera.orientation();
+        let (from, to) = (Vec2::new(-0.2, 0.1), Vec2::new(0.6, -0.4));
+
+        camera.rotate_local(camera.trackball_rotation(from, to));
+        assert!(!camera.orientation().abs_diff_eq(start, 1e-3));
+        camera.rotate_local(camera.trackball_rotation(to, from));
+        assert!(camera.orientation().abs_diff_eq(start, 1e-5));
+
+        // Dragging right turns the camera left, so the scene follows the cursor
+        let rotation = camera.trackball_rotation(Vec2::ZERO, Vec2::new(0.3, 0.0));
+        assert!((rotation * Vec3::Z).x < 0.0);
+    }
+
+    #[test]
+    fn trackball_orientation_stays_normalized() {
+        let mut camera = Camera::new(1.0);
+        camera.set_trackball_orientation(Quat::from_xyzw(0.0, 0.0, 0.0, 3.0));
+        assert_eq!(camera.trackball_orientation, Quat::IDENTITY);
+
+        camera.set_mode(CameraMode::Trackball);
+        let rotation = Quat::from_xyzw(0.3, 0.2, 0.1, 1.0);
+        for _ in 0..1000 {
+            camera.rotate_local(rotation);
+        }
+        assert!(camera.orientation().is_normalized());
+    }
+
+    #[test]
+    fn switching_modes_keeps_the_view_direction() {
+        let mut camera = Camera::new(1.0);
+        camera.set_angles(1.0, 2.0);
+        let turntable = camera.view();
+        camera.set_mode(CameraMode::Trackball);
+        assert!(camera.view().abs_diff_eq(turntable, 1e-5));
+
+        // Past the pole, which the turntable cannot reach, and back
+        camera.rotate_zenith(-1.5);
+        assert_eq!(camera.zenith, 1.0);
+        let back = camera.orientation() * Vec3::Z;
+        camera.set_mode(CameraMode::Turntable);
+        assert!((camera.orientation() * Vec3::Z).abs_diff_eq(back, 1e-5));
+        let (zenith, azimuth) = camera.angles();
+        assert!((zenith - 0.5).abs() < 1e-5 && (azimuth - (2.0 + PI)).abs() < 1e-5);
+
+        assert_eq!("arcball".parse(), Ok(CameraMode::Trackball));
+    }
+
+    #[test]
+    fn reverse_z_maps_the_near_plane_to_one_and_infinity_to_zero() {
+        let mut camera = Camera::new(1.5);
+        camera.reverse_z = true;
+        let proj = camera.proj();
+        let depth = |z: f32| proj.project_point3(Vec3::new(0.3, -0.2, z)).z;
+
+        assert!((depth(-camera.znear) - 1.0).abs() < 1e-6);
+        assert!(depth(-1e7) < 1e-6 && depth(-1e7) > 0.0);
+        // Closer is larger, and precision holds up far beyond `zfar`
+        assert!(depth(-10.0) > depth(-10.01));
+        assert!(depth(-10.0 * camera.zfar) > depth(-10.01 * camera.zfar));
+
+        camera.reverse_z = false;
+        let proj = camera.proj();
+        assert!(
+            proj.project_point3(Vec3::new(0.0, 0.0, -camera.znear))
+                .z
+                .abs()
+                < 1e-6
+        );
+        assert!((proj.project_point3(Vec3::new(0.0, 0.0, -camera.zfar)).z - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rays_pass_through_what_is_under_the_cursor() {
+        for (reverse_z, projection) in [
+            (true, Projection::Perspective),
+            (false, Projection::Perspective),
+            (true, Projection::Orthographic),
+        ] {
+            let mut camera = Camera::new(1.5);
+            camera.reverse_z = reverse_z;
+            camera.jump_to_projection(projection);
+            camera.target = Vec3::new(0.5, 1.0, -0.5);
+
+            let point = Vec3::new(2.0, -1.0, 0.8);
+            let (origin, direction) = camera.ray(ndc(&camera, point).truncate());
+            assert!(direction.is_normalized());
+            let along = (point - origin).dot(direction);
+            assert!(along > 0.0);
+            assert!((origin + direction * along).abs_diff_eq(point, 1e-3));
+
+            // Through the middle of the screen, straight ahead to the target
+            let (origin, direction) = camera.ray(Vec2::ZERO);
+            let forward = camera.orientation() * Vec3::NEG_Z;
+            assert!(direction.abs_diff_eq(forward, 1e-5), "{projection:?}");
+            assert!((camera.target - origin).cross(forward).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn panning_moves_the_target_in_the_view_plane() {
+        let mut camera = Camera::new(1.5);
+        camera.set_angles(0.7, 2.0);
+        let (target, distance) = (camera.target, camera.distance);
+        let eye = camera.view().inverse().transform_point3(Vec3::ZERO);
+        let forward = camera.orientation() * Vec3::NEG_Z;
+
+        camera.pan(0.25, -0.1);
+        let moved = camera.target - target;
+        assert!(moved.dot(forward).abs() < 1e-5);
+        assert_eq!(camera.distance, distance);
+        // The eye moves along, so the view direction is kept
+        let panned_eye = camera.view().inverse().transform_point3(Vec3::ZERO);
+        assert!((panned_eye - eye).abs_diff_eq(moved, 1e-4));
+
+        // The point under the cursor follows it: a quarter of the width is half of NDC
+        let grabbed = ndc(&camera, target);
+        assert!(grabbed.truncate().abs_diff_eq(Vec2::new(0.5, 0.2), 1e-5));
+    }
+}
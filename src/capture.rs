@@ -0,0 +1,214 @@
+//! Tiled and supersampled rendering for images larger or finer than the render target
+//!
+//! The image is split into tiles that fit the device's texture limit. Each tile is drawn with a
+//! sub-frustum of the camera, by scaling and offsetting clip space so that the tile fills it.
+
+use glam::{Mat4, Vec4};
+
+use crate::State;
+
+/// Part of an image in output pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Map the clip space of a `width` by `height` image to this tile
+    pub fn projection(&self, width: u32, height: u32) -> Mat4 {
+        let (width, height) = (width as f32, height as f32);
+        let (x, y) = (self.x as f32, self.y as f32);
+        let (w, h) = (self.width as f32, self.height as f32);
+
+        // Applied to clip coordinates so the offsets are scaled by w and work for any projection
+        Mat4::from_cols(
+            Vec4::new(width / w, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, height / h, 0.0, 0.0),
+            Vec4::Z,
+            Vec4::new(
+                (width - 2.0 * x - w) / w,
+                (2.0 * y + h - height) / h,
+                0.0,
+                1.0,
+            ),
+        )
+    }
+
+    /// Part of this tile also covered by `other`, if any
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (x < right && y < bottom).then(|| Self {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+
+    /// This tile in the pixels of an image supersampled by `factor`
+    pub fn scaled(&self, factor: u32) -> Self {
+        Self {
+            x: self.x * factor,
+            y: self.y * factor,
+            width: self.width * factor,
+            height: self.height * factor,
+        }
+    }
+}
+
+/// Split an image into rows of tiles no larger than `size`, top-left first
+pub fn tiles(width: u32, height: u32, size: u32) -> impl Iterator<Item = Tile> {
+    let size = size.max(1);
+    (0..height).step_by(size as usize).flat_map(move |y| {
+        (0..width).step_by(size as usize).map(move |x| Tile {
+            x,
+            y,
+            width: size.min(width - x),
+            height: size.min(height - y),
+        })
+    })
+}
+
+/// Rendered pixels per output pixel along each axis to reach `samples` per pixel
+///
+/// Up to `State::MSAA_SAMPLE_COUNT` samples multisampling alone is enough, beyond that each
+/// supersampled pixel is multisampled as well.
+pub fn supersampling_factor(samples: u32) -> u32 {
+    if samples <= State::MSAA_SAMPLE_COUNT {
+        1
+    } else {
+        libm::ceilf(libm::sqrtf(
+            samples as f32 / State::MSAA_SAMPLE_COUNT as f32,
+        )) as u32
+    }
+}
+
+/// Average `factor` by `factor` blocks of a tile's RGBA or BGRA pixels into an RGB image
+/// `image_width` pixels wide
+pub fn resolve_tile(
+    pixels: &[u8],
+    bgra: bool,
+    factor: u32,
+    tile: &Tile,
+    image: &mut [u8],
+    image_width: u32,
+) {
+    let factor = factor as usize;
+    let samples = (factor * factor) as u32;
+    let row_pixels = tile.width as usize * factor;
+
+    for y in 0..tile.height as usize {
+        for x in 0..tile.width as usize {
+            let mut sum = [0u32; 3];
+            for sy in 0..factor {
+                let row = (y * factor + sy) * row_pixels;
+                for sx in 0..factor {
+                    let pixel = &pixels[(row + x * factor + sx) * 4..][..3];
+                    for (sum, &value) in sum.iter_mut().zip(pixel) {
+                        *sum += value as u32;
+                    }
+                }
+            }
+
+            if bgra {
+                sum.swap(0, 2);
+            }
+            let out = ((tile.y as usize + y) * image_width as usize + tile.x as usize + x) * 3;
+            for (c, sum) in sum.into_iter().enumerate() {
+                image[out + c] = ((sum + samples / 2) / samples) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+    use glam::Vec3;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let tiles: Vec<Tile> = tiles(5, 3, 2).collect();
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles.iter().map(|t| t.width * t.height).sum::<u32>(), 15);
+        assert_eq!(
+            tiles[5],
+            Tile {
+                x: 4,
+                y: 2,
+                width: 1,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    fn tile_projection_fills_clip_space() {
+        let tile = Tile {
+            x: 100,
+            y: 50,
+            width: 200,
+            height: 100,
+        };
+        let projection = tile.projection(400, 300);
+
+        // Corners of the tile in the clip space of the whole image, with w = 2
+        let top_left = Vec4::new(-0.5, 2.0 / 3.0, 0.3, 1.0) * 2.0;
+        let bottom_right = Vec4::new(0.5, 0.0, 0.3, 1.0) * 2.0;
+
+        let top_left = projection * top_left;
+        let bottom_right = projection * bottom_right;
+        assert!((top_left.truncate() / top_left.w).abs_diff_eq(Vec3::new(-1.0, 1.0, 0.3), 1e-6));
+        assert!(
+            (bottom_right.truncate() / bottom_right.w).abs_diff_eq(Vec3::new(1.0, -1.0, 0.3), 1e-6)
+        );
+    }
+
+    #[test]
+    fn whole_image_tile_keeps_projection() {
+        let tile = tiles(640, 480, 4096).next().unwrap();
+        assert_eq!(tile.projection(640, 480), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn supersampling_starts_beyond_multisampling() {
+        assert_eq!(supersampling_factor(1), 1);
+        assert_eq!(supersampling_factor(State::MSAA_SAMPLE_COUNT), 1);
+        assert_eq!(supersampling_factor(16), 2);
+        assert_eq!(supersampling_factor(17), 3);
+    }
+
+    #[test]
+    fn resolves_blocks_into_place() {
+        // A 2 by 1 tile supersampled by 2, BGRA
+        let pixels = [
+            [0, 0, 10, 255],
+            [0, 0, 20, 255],
+            [1, 1, 1, 255],
+            [3, 3, 3, 255],
+            [0, 0, 30, 255],
+            [0, 0, 40, 255],
+            [5, 5, 5, 255],
+            [7, 7, 7, 255],
+        ]
+        .concat();
+        let tile = Tile {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        let mut image = vec![0; 3 * 3];
+        resolve_tile(&pixels, true, 2, &tile, &mut image, 3);
+
+        assert_eq!(image, [0, 0, 0, 25, 0, 0, 4, 4, 4]);
+    }
+}
@@ -0,0 +1,170 @@
+//! User clipping planes cutting the surface open to show cross sections
+//!
+//! Each plane discards the surface on the side its normal points to. Planes are tested per
+//! fragment in world space, so they stay put while the camera moves. Back faces seen through a
+//! cut are drawn in a solid cap colour: where a cut opens a surface that folds back over itself,
+//! its inside reads as a solid section instead of showing the far side's underside.
+//!
+//! A back face counts as seen through a cut when its view ray starts on the cut-away side of a
+//! plane. The underside of a surface seen from below a plane that cuts its top keeps its colour.
+
+use alloc::vec::Vec;
+
+use glam::{Vec3, Vec4};
+
+/// Most planes a viewport can be clipped by
+pub const MAX_CLIP_PLANES: usize = 6;
+
+/// A plane of points `p` with `normal.dot(p) == offset`, cutting away the side `normal` points to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipPlane {
+    /// Unit normal in world space
+    normal: Vec3,
+    offset: f32,
+}
+
+impl ClipPlane {
+    /// Plane with `normal`, which is normalized, at `offset` along it from the origin
+    ///
+    /// Returns `None` if `normal` is zero or anything is infinite or NaN.
+    pub fn new(normal: Vec3, offset: f32) -> Option<Self> {
+        let length = normal.length();
+        if !(length > 0.0 && length.is_finite() && offset.is_finite()) {
+            return None;
+        }
+        Some(Self {
+            normal: normal / length,
+            offset: offset / length,
+        })
+    }
+
+    /// Planes from a flat array of nx, ny, nz, offset quadruples
+    ///
+    /// Returns `None` if the length is not a multiple of 4 or a plane is invalid.
+    pub fn from_array(values: &[f32]) -> Option<Vec<Self>> {
+        if !values.len().is_multiple_of(4) {
+            return None;
+        }
+        values
+            .chunks_exact(4)
+            .map(|v| Self::new(Vec3::new(v[0], v[1], v[2]), v[3]))
+            .collect()
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Whether `point` lies on the side that is cut away
+    pub fn clips(&self, point: Vec3) -> bool {
+        self.normal.dot(point) > self.offset
+    }
+}
+
+/// Planes and cap colour of a viewport
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clipping {
+    planes: Vec<ClipPlane>,
+    cap_color: [f32; 3],
+}
+
+impl Default for Clipping {
+    fn default() -> Self {
+        Self {
+            planes: Vec::new(),
+            cap_color: Self::DEFAULT_CAP_COLOR,
+        }
+    }
+}
+
+impl Clipping {
+    pub const DEFAULT_CAP_COLOR: [f32; 3] = [0.8, 0.25, 0.2];
+
+    pub fn planes(&self) -> &[ClipPlane] {
+        &self.planes
+    }
+
+    /// Replace the planes, returning `false` and leaving them alone if there are more than
+    /// `MAX_CLIP_PLANES`
+    pub fn set_planes(&mut self, planes: &[ClipPlane]) -> bool {
+        if planes.len() > MAX_CLIP_PLANES {
+            return false;
+        }
+        self.planes = planes.to_vec();
+        true
+    }
+
+    pub fn cap_color(&self) -> [f32; 3] {
+        self.cap_color
+    }
+
+    pub fn set_cap_color(&mut self, color: [f32; 3]) {
+        self.cap_color = color;
+    }
+
+    /// Whether `point` is cut away by any plane
+    pub fn clips(&self, point: Vec3) -> bool {
+        self.planes.iter().any(|plane| plane.clips(point))
+    }
+
+    pub(crate) fn uniform(&self) -> ClipUniform {
+        let mut planes = [[0.0; 4]; MAX_CLIP_PLANES];
+        for (uniform, plane) in planes.iter_mut().zip(&self.planes) {
+            *uniform = Vec4::from((plane.normal, plane.offset)).to_array();
+        }
+        ClipUniform {
+            planes,
+            cap_color: self.cap_color,
+            plane_count: self.planes.len() as u32,
+        }
+    }
+}
+
+/// Planes as uploaded after the camera matrices, see `ClipUniform` in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ClipUniform {
+    /// Normal in xyz and offset in w
+    planes: [[f32; 4]; MAX_CLIP_PLANES],
+    cap_color: [f32; 3],
+    plane_count: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    #[test]
+    fn planes_are_normalized() {
+        let plane = ClipPlane::new(Vec3::new(0.0, 0.0, 2.0), 1.0).unwrap();
+        assert_eq!(plane.normal(), Vec3::Z);
+        assert_eq!(plane.offset(), 0.5);
+        assert!(plane.clips(Vec3::new(3.0, -1.0, 0.6)));
+        assert!(!plane.clips(Vec3::new(3.0, -1.0, 0.4)));
+
+        assert_eq!(ClipPlane::new(Vec3::ZERO, 1.0), None);
+        assert_eq!(ClipPlane::new(Vec3::X, f32::NAN), None);
+    }
+
+    #[test]
+    fn planes_from_array() {
+        let planes = ClipPlane::from_array(&[1.0, 0.0, 0.0, 0.5, 0.0, -1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[1].normal(), Vec3::NEG_Y);
+
+        assert_eq!(ClipPlane::from_array(&[1.0, 0.0, 0.0]), None);
+        assert_eq!(ClipPlane::from_array(&[0.0, 0.0, 0.0, 1.0]), None);
+
+        let mut clipping = Clipping::default();
+        assert!(!clipping.set_planes(&[planes[0]; MAX_CLIP_PLANES + 1]));
+        assert!(clipping.set_planes(&planes));
+        assert!(clipping.clips(Vec3::new(1.0, 1.0, 0.0)));
+        assert!(!clipping.clips(Vec3::new(0.0, 1.0, 0.0)));
+    }
+}
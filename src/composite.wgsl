@@ -0,0 +1,29 @@
+// Blend translucent surfaces gathered by weighted blended order-independent transparency over
+// the opaque frame, see transparency.rs
+
+// Weighted sum of premultiplied colours and of opacities
+@group(0) @binding(0)
+var accum_texture: texture_2d<f32>;
+// Product of one minus each opacity
+@group(0) @binding(1)
+var reveal_texture: texture_2d<f32>;
+
+// One triangle covering the target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    let pixel = vec2i(position.xy);
+    let reveal = textureLoad(reveal_texture, pixel, 0).r;
+    if reveal >= 1.0 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, pixel, 0);
+    // Avoids dividing by zero where the weights underflowed
+    let average = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+    return vec4f(average, 1.0 - reveal);
+}
@@ -0,0 +1,262 @@
+use glam::{Quat, Vec2, Vec3};
+
+use crate::camera::Camera;
+
+/// Applies camera moves with inertia and exponential damping
+///
+/// Each move is added as an impulse whose velocity decays at `friction` per second. The impulse
+/// is scaled so that its motion integrates to exactly the requested move, so inertia changes how
+/// a gesture feels but not where the camera ends up (unless `max_speed` clips it).
+#[derive(Clone)]
+pub struct CameraController {
+    enabled: bool,
+    friction: f32,
+    max_speed: f32,
+    zenith_velocity: f32,
+    azimuth_velocity: f32,
+    /// Rate of change of the natural logarithm of the camera distance
+    dolly_velocity: f32,
+    /// Viewport fractions per second as passed to `Camera::pan`
+    pan_velocity: Vec2,
+    /// Camera space rotation axis scaled by radians per second, see `Camera::rotate_local`
+    angular_velocity: Vec3,
+}
+
+impl CameraController {
+    pub const DEFAULT_FRICTION: f32 = 10.0;
+    pub const DEFAULT_MAX_SPEED: f32 = 20.0;
+
+    /// Remaining motion below which the camera is considered settled
+    const SETTLE_THRESHOLD: f32 = 1e-4;
+
+    /// Largest single dolly step, `Camera::move_distance` is undefined at and above 1.0
+    const MAX_DOLLY_STEP: f32 = 0.99;
+
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            friction: Self::DEFAULT_FRICTION,
+            max_speed: Self::DEFAULT_MAX_SPEED,
+            zenith_velocity: 0.0,
+            azimuth_velocity: 0.0,
+            dolly_velocity: 0.0,
+            pan_velocity: Vec2::ZERO,
+            angular_velocity: Vec3::ZERO,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable inertia, disabling stops any motion in progress
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.stop();
+        }
+    }
+
+    /// Set the damping rate per second, higher values settle faster
+    pub fn set_friction(&mut self, friction: f32) {
+        self.friction = friction.max(f32::EPSILON);
+    }
+
+    /// Set the speed limit per second
+    ///
+    /// Applies to radians for rotation, log distance for dollying and viewport fractions for
+    /// panning.
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.max_speed = max_speed.max(0.0);
+    }
+
+    pub fn stop(&mut self) {
+        self.zenith_velocity = 0.0;
+        self.azimuth_velocity = 0.0;
+        self.dolly_velocity = 0.0;
+        self.pan_velocity = Vec2::ZERO;
+        self.angular_velocity = Vec3::ZERO;
+    }
+
+    /// Rotate and dolly the camera, see `Camera::move_distance` for the meaning of `distance`
+    pub fn move_camera(&mut self, camera: &mut Camera, distance: f32, zenith: f32, azimuth: f32) {
+        if !self.enabled {
+            camera.move_distance(distance);
+            camera.rotate_zenith(zenith);
+            camera.rotate_azimuth(azimuth);
+            return;
+        }
+
+        let log_dolly = libm::logf(1.0 - distance.min(Self::MAX_DOLLY_STEP));
+        let max = self.max_speed;
+        self.zenith_velocity = (self.zenith_velocity + zenith * self.friction).clamp(-max, max);
+        self.azimuth_velocity = (self.azimuth_velocity + azimuth * self.friction).clamp(-max, max);
+        self.dolly_velocity = (self.dolly_velocity + log_dolly * self.friction).clamp(-max, max);
+    }
+
+    /// Pan the camera, see `Camera::pan`
+    pub fn pan_camera(&mut self, camera: &mut Camera, dx: f32, dy: f32) {
+        if !self.enabled {
+            camera.pan(dx, dy);
+            return;
+        }
+
+        self.pan_velocity = (self.pan_velocity + Vec2::new(dx, dy) * self.friction)
+            .clamp_length_max(self.max_speed);
+    }
+
+    /// Apply a camera space rotation, see `Camera::rotate_local`
+    pub fn rotate_camera(&mut self, camera: &mut Camera, rotation: Quat) {
+        if !self.enabled {
+            camera.rotate_local(rotation);
+            return;
+        }
+
+        self.angular_velocity = (self.angular_velocity + rotation.to_scaled_axis() * self.friction)
+            .clamp_length_max(self.max_speed);
+    }
+
+    /// Advance the camera by `dt` seconds, returning whether it is still moving
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        if !self.is_moving() {
+            return false;
+        }
+
+        // Exact integral of the decaying velocity over the step so motion is frame rate independent
+        let decay = libm::expf(-self.friction * dt);
+        let travel = (1.0 - decay) / self.friction;
+
+        camera.rotate_zenith(self.zenith_velocity * travel);
+        camera.rotate_azimuth(self.azimuth_velocity * travel);
+        camera.move_distance(1.0 - libm::expf(self.dolly_velocity * travel));
+        let pan = self.pan_velocity * travel;
+        camera.pan(pan.x, pan.y);
+        if self.angular_velocity != Vec3::ZERO {
+            camera.rotate_local(Quat::from_scaled_axis(self.angular_velocity * travel));
+        }
+
+        self.zenith_velocity *= decay;
+        self.azimuth_velocity *= decay;
+        self.dolly_velocity *= decay;
+        self.pan_velocity *= decay;
+        self.angular_velocity *= decay;
+
+        if !self.is_moving() {
+            self.stop();
+        }
+        self.is_moving()
+    }
+
+    fn is_moving(&self) -> bool {
+        // Compare the motion still to come rather than the velocity itself
+        let remaining = self
+            .zenith_velocity
+            .abs()
+            .max(self.azimuth_velocity.abs())
+            .max(self.dolly_velocity.abs())
+            .max(self.pan_velocity.abs().max_element())
+            .max(self.angular_velocity.length())
+            / self.friction;
+        remaining > Self::SETTLE_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::TAU;
+
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// Step until the camera settles, returning the number of steps
+    fn settle(controller: &mut CameraController, camera: &mut Camera) -> usize {
+        let steps = (1..1000).find(|_| !controller.update(camera, DT));
+        steps.expect("the camera never settled")
+    }
+
+    /// Angle between two azimuths, which wrap around at a full turn
+    fn azimuth_between(a: f32, b: f32) -> f32 {
+        let d = (a - b).abs() % TAU;
+        d.min(TAU - d)
+    }
+
+    #[test]
+    fn velocity_decays_exponentially() {
+        let (mut controller, mut camera) = (CameraController::new(), Camera::new(1.0));
+        let azimuth = camera.azimuth;
+        controller.move_camera(&mut camera, 0.0, 0.0, 0.1);
+        assert_eq!(camera.azimuth, azimuth);
+        assert_eq!(
+            controller.azimuth_velocity,
+            0.1 * CameraController::DEFAULT_FRICTION
+        );
+
+        let velocity = controller.azimuth_velocity;
+        assert!(controller.update(&mut camera, DT));
+        let decay = libm::expf(-CameraController::DEFAULT_FRICTION * DT);
+        assert!((controller.azimuth_velocity - velocity * decay).abs() < 1e-6);
+        let travel = velocity * (1.0 - decay) / CameraController::DEFAULT_FRICTION;
+        assert!((camera.azimuth - azimuth - travel).abs() < 1e-6);
+    }
+
+    #[test]
+    fn motion_adds_up_to_the_requested_move() {
+        let (mut controller, mut camera) = (CameraController::new(), Camera::new(1.0));
+        let (zenith, azimuth, distance) = (camera.zenith, camera.azimuth, camera.distance);
+        controller.move_camera(&mut camera, 0.5, 0.2, 0.3);
+        settle(&mut controller, &mut camera);
+
+        let settled = 2.0 * CameraController::SETTLE_THRESHOLD;
+        assert!((camera.zenith - zenith - 0.2).abs() < settled);
+        assert!((camera.azimuth - azimuth - 0.3).abs() < settled);
+        assert!((camera.distance / distance - 0.5).abs() < settled);
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        let (mut controller, mut camera) = (CameraController::new(), Camera::new(1.0));
+        controller.set_max_speed(2.0);
+        controller.move_camera(&mut camera, 0.0, 0.0, 10.0);
+        controller.pan_camera(&mut camera, 3.0, 4.0);
+        controller.rotate_camera(&mut camera, Quat::from_rotation_y(3.0));
+        assert_eq!(controller.azimuth_velocity, 2.0);
+        assert!((controller.pan_velocity.length() - 2.0).abs() < 1e-5);
+        assert!((controller.angular_velocity.length() - 2.0).abs() < 1e-5);
+
+        // No step moves further than the speed limit allows
+        let mut azimuth = camera.azimuth;
+        while controller.update(&mut camera, DT) {
+            assert!(azimuth_between(camera.azimuth, azimuth) <= 2.0 * DT + 1e-6);
+            azimuth = camera.azimuth;
+        }
+    }
+
+    #[test]
+    fn comes_to_rest() {
+        let (mut controller, mut camera) = (CameraController::new(), Camera::new(1.0));
+        controller.pan_camera(&mut camera, 0.2, -0.1);
+        controller.move_camera(&mut camera, 0.1, 0.1, 0.1);
+        // Settling takes about ln(speed / threshold) / friction seconds
+        let steps = settle(&mut controller, &mut camera);
+        assert!(steps < 120, "{steps} steps");
+        assert_eq!(controller.pan_velocity, Vec2::ZERO);
+        assert_eq!(controller.dolly_velocity, 0.0);
+        let target = camera.target;
+        assert!(!controller.update(&mut camera, DT));
+        assert_eq!(camera.target, target);
+
+        controller.move_camera(&mut camera, 0.0, 0.0, 1.0);
+        controller.stop();
+        assert!(!controller.update(&mut camera, DT));
+
+        // Without inertia moves apply at once
+        controller.set_enabled(false);
+        let azimuth = camera.azimuth;
+        controller.move_camera(&mut camera, 0.0, 0.0, 0.25);
+        assert_eq!(camera.azimuth, azimuth + 0.25);
+        assert!(!controller.update(&mut camera, DT));
+    }
+}
@@ -0,0 +1,25 @@
+// Sample a parametric curve at evenly spaced t. `fn curve(t: f32) -> vec3f` is built from the
+// user's expression and prepended to this file.
+
+struct Params {
+    count: u32,
+    t_start: f32,
+    t_end: f32,
+    // Tube radius, used by curve_mesh.wgsl
+    radius: f32,
+}
+
+// x, y, z and how far along the curve the point is, from 0 to 1
+@group(0) @binding(0) var<storage, read_write> points: array<vec4f>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+@compute @workgroup_size(256)
+fn evaluate(@builtin(global_invocation_id) gid: vec3u)
+{
+    let i = gid.x;
+    if i >= params.count {
+        return;
+    }
+    let s = f32(i) / f32(params.count - 1u);
+    points[i] = vec4f(curve(mix(params.t_start, params.t_end, s)), s);
+}
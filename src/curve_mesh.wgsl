@@ -0,0 +1,77 @@
+// Tube mesh around the points of a curve, a ring of SIDES vertices per point
+
+struct Params {
+    count: u32,
+    t_start: f32,
+    t_end: f32,
+    radius: f32,
+}
+
+@group(0) @binding(0) var<storage, read_write> points: array<vec4f>;
+@group(0) @binding(1) var<uniform> params: Params;
+// Normal of the ring at each point
+@group(0) @binding(2) var<storage, read_write> frames: array<vec4f>;
+// Position and t, then normal, for each vertex
+@group(0) @binding(3) var<storage, read_write> vertices: array<vec4f>;
+
+// Must match `curves::TUBE_SIDES`
+const SIDES = 8u;
+const TAU = 6.283185307;
+
+fn tangent(i: u32) -> vec3f {
+    let previous = points[max(i, 1u) - 1u].xyz;
+    let next = points[min(i + 1u, params.count - 1u)].xyz;
+    let d = next - previous;
+    let l = length(d);
+    if l == 0.0 {
+        return vec3f(0.0);
+    }
+    return d / l;
+}
+
+// Any unit vector perpendicular to `t`
+fn perpendicular(t: vec3f) -> vec3f {
+    var axis = vec3f(0.0, 0.0, 1.0);
+    if abs(t.z) > 0.9 {
+        axis = vec3f(1.0, 0.0, 0.0);
+    }
+    return normalize(cross(t, axis));
+}
+
+// Carry the normal along the curve by removing its component along each tangent, so the rings
+// do not twist. Sequential, so it runs as a single invocation, which is why tubes have at most
+// `curves::MAX_TUBE_POINTS` points.
+@compute @workgroup_size(1)
+fn frames_main()
+{
+    var normal = vec3f(0.0);
+    for (var i = 0u; i < params.count; i++) {
+        let t = tangent(i);
+        if any(t != vec3f(0.0)) {
+            let carried = normal - dot(normal, t) * t;
+            if length(carried) > 1e-3 {
+                normal = normalize(carried);
+            } else {
+                normal = perpendicular(t);
+            }
+        }
+        frames[i] = vec4f(normal, 0.0);
+    }
+}
+
+@compute @workgroup_size(256)
+fn tube(@builtin(global_invocation_id) gid: vec3u)
+{
+    let v = gid.x;
+    if v >= params.count * SIDES {
+        return;
+    }
+    let i = v / SIDES;
+    let angle = f32(v % SIDES) / f32(SIDES) * TAU;
+
+    let n = frames[i].xyz;
+    let b = cross(tangent(i), n);
+    let normal = cos(angle) * n + sin(angle) * b;
+    vertices[2u * v] = vec4f(points[i].xyz + params.radius * normal, points[i].w);
+    vertices[2u * v + 1u] = vec4f(normal, 0.0);
+}
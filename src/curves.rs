@@ -0,0 +1,977 @@
+//! 3D curves such as trajectories, drawn alongside the surface
+//!
+//! A curve is either a polyline or a parametric expression sampled on the GPU. It is drawn as a
+//! line of constant width on screen, or as a lit tube mesh generated by a compute shader. Both
+//! share the surface's camera bind group and depth buffer.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, ops::RangeInclusive, str::FromStr};
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::capture::Tile;
+
+/// Vertices around each ring of a tube, must match SIDES in curve_mesh.wgsl
+pub const TUBE_SIDES: u32 = 8;
+/// Most points sampled from or given for a curve
+pub const MAX_CURVE_POINTS: usize = 1 << 20;
+/// Most points of a tube, whose rings are oriented one point after another by a single GPU
+/// invocation that must finish well within the driver's timeout
+pub const MAX_TUBE_POINTS: usize = 1 << 14;
+
+/// Why a curve cannot be shown
+#[derive(Clone, Debug, PartialEq)]
+pub enum CurveError {
+    /// A curve needs at least 2 points
+    TooFewPoints,
+    TooManyPoints {
+        count: usize,
+        max: usize,
+    },
+    /// A point or the parameter range is infinite or NaN
+    NotFinite,
+    /// Widths must be positive
+    InvalidWidth(f32),
+    /// The tube mesh would exceed the device's buffer size limit
+    BufferTooLarge {
+        bytes: u64,
+        max: u64,
+    },
+    /// The parametric expression does not compile, with the shader compiler's message
+    Expression(String),
+}
+
+impl fmt::Display for CurveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooFewPoints => write!(f, "A curve needs at least 2 points"),
+            Self::TooManyPoints { count, max } => {
+                write!(
+                    f,
+                    "Cannot draw a curve of {count} points, at most {max} are supported"
+                )
+            }
+            Self::NotFinite => write!(f, "Curve points and parameters must be finite"),
+            Self::InvalidWidth(width) => write!(f, "Invalid curve width {width}"),
+            Self::BufferTooLarge { bytes, max } => write!(
+                f,
+                "Curve needs a {bytes} byte buffer but the device allows at most {max} bytes"
+            ),
+            Self::Expression(message) => write!(f, "Invalid curve expression: {message}"),
+        }
+    }
+}
+
+impl core::error::Error for CurveError {}
+
+/// How a curve is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CurveKind {
+    /// Constant width on screen, with round joins
+    #[default]
+    Line,
+    /// Lit tube mesh of constant width in world space
+    Tube,
+}
+
+impl FromStr for CurveKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(Self::Line),
+            "tube" => Ok(Self::Tube),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveColor {
+    Solid([f32; 3]),
+    /// Blend from `start` at the start of the curve to `end` at its end, following t
+    Gradient {
+        start: [f32; 3],
+        end: [f32; 3],
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurveStyle {
+    pub kind: CurveKind,
+    /// Line width in pixels, or tube diameter in world units
+    pub width: f32,
+    pub color: CurveColor,
+}
+
+impl CurveStyle {
+    pub const DEFAULT_LINE_WIDTH: f32 = 2.0;
+    pub const DEFAULT_TUBE_WIDTH: f32 = 0.05;
+    pub const DEFAULT_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+
+    /// Default width and colour for `kind`
+    pub fn new(kind: CurveKind) -> Self {
+        let width = match kind {
+            CurveKind::Line => Self::DEFAULT_LINE_WIDTH,
+            CurveKind::Tube => Self::DEFAULT_TUBE_WIDTH,
+        };
+        Self {
+            kind,
+            width,
+            color: CurveColor::Solid(Self::DEFAULT_COLOR),
+        }
+    }
+}
+
+/// Curve sampled from a WGSL expression of `t`, such as `vec3f(cos(t), sin(t), 0.1 * t)`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParametricCurve {
+    /// Evaluates to the `vec3f` point at `t: f32`
+    pub expression: String,
+    pub t_range: RangeInclusive<f32>,
+    /// Points sampled evenly over `t_range`
+    pub samples: u32,
+}
+
+impl ParametricCurve {
+    /// Prepended to curve_eval.wgsl
+    fn shader_source(&self) -> String {
+        format!(
+            "fn curve(t: f32) -> vec3f {{\n    return {};\n}}\n\n{}",
+            self.expression,
+            include_str!("curve_eval.wgsl")
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CurveSource {
+    Polyline(Vec<[f32; 3]>),
+    Parametric(ParametricCurve),
+}
+
+impl CurveSource {
+    fn point_count(&self) -> usize {
+        match self {
+            Self::Polyline(points) => points.len(),
+            Self::Parametric(curve) => curve.samples as usize,
+        }
+    }
+
+    fn validate(&self, kind: CurveKind) -> Result<(), CurveError> {
+        let count = self.point_count();
+        if count < 2 {
+            return Err(CurveError::TooFewPoints);
+        }
+        let max = match kind {
+            CurveKind::Line => MAX_CURVE_POINTS,
+            CurveKind::Tube => MAX_TUBE_POINTS,
+        };
+        if count > max {
+            return Err(CurveError::TooManyPoints { count, max });
+        }
+        let finite = match self {
+            Self::Polyline(points) => points.iter().flatten().all(|v| v.is_finite()),
+            Self::Parametric(curve) => {
+                curve.t_range.start().is_finite() && curve.t_range.end().is_finite()
+            }
+        };
+        if !finite {
+            return Err(CurveError::NotFinite);
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a curve of the layer, stays the same after recovering from device loss
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurveId(pub u32);
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CurveParams {
+    count: u32,
+    t_start: f32,
+    t_end: f32,
+    radius: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct StyleUniform {
+    color_start: [f32; 3],
+    width: f32,
+    color_end: [f32; 3],
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    viewport: [f32; 2],
+    origin: [f32; 2],
+    pixel_scale: f32,
+    _padding: f32,
+}
+
+/// Render pipelines for either kind of curve, for one sample count
+pub struct CurvePipelines {
+    line: wgpu::RenderPipeline,
+    tube: wgpu::RenderPipeline,
+}
+
+struct TubeMesh {
+    frame_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+struct Curve {
+    id: CurveId,
+    /// Kept to upload again after losing the device
+    source: CurveSource,
+    style: CurveStyle,
+    point_count: u32,
+    point_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    style_buffer: wgpu::Buffer,
+    style_bind_group: wgpu::BindGroup,
+    tube: Option<TubeMesh>,
+}
+
+impl Curve {
+    fn destroy(&self) {
+        self.point_buffer.destroy();
+        self.params_buffer.destroy();
+        self.style_buffer.destroy();
+        if let Some(tube) = &self.tube {
+            tube.frame_buffer.destroy();
+            tube.vertex_buffer.destroy();
+            tube.index_buffer.destroy();
+        }
+    }
+}
+
+/// Pipelines shared by the curve layers of every viewport
+pub struct CurveRenderer {
+    format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    module: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: CurvePipelines,
+    layer_bind_group_layout: wgpu::BindGroupLayout,
+    style_bind_group_layout: wgpu::BindGroupLayout,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    compute_pipeline_layout: wgpu::PipelineLayout,
+    frames_pipeline: wgpu::ComputePipeline,
+    tube_pipeline: wgpu::ComputePipeline,
+    /// Bound in place of the tube buffers while sampling expressions for lines
+    placeholder_buffer: wgpu::Buffer,
+}
+
+/// Curves of one viewport
+pub struct CurveLayer {
+    layer_buffer: wgpu::Buffer,
+    layer_bind_group: wgpu::BindGroup,
+    layer: LayerUniform,
+    curves: Vec<Curve>,
+    next_id: u32,
+}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+impl CurveRenderer {
+    /// Draw layers with the camera bind group at group 0
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        sample_count: u32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::include_wgsl!("curves.wgsl"));
+
+        let layer_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Curve layer bind group layout"),
+                entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+            });
+        let style_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Curve style bind group layout"),
+                entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+            });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Curve pipeline layout"),
+            bind_group_layouts: &[
+                camera_bind_group_layout,
+                &layer_bind_group_layout,
+                &style_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Curve compute bind group layout"),
+                entries: &[
+                    storage_entry(0),
+                    uniform_entry(1, wgpu::ShaderStages::COMPUTE),
+                    storage_entry(2),
+                    storage_entry(3),
+                ],
+            });
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Curve compute pipeline layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let mesh_module = device.create_shader_module(wgpu::include_wgsl!("curve_mesh.wgsl"));
+        let compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&compute_pipeline_layout),
+                module: &mesh_module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let frames_pipeline = compute_pipeline("Curve frames compute pipeline", "frames_main");
+        let tube_pipeline = compute_pipeline("Curve tube compute pipeline", "tube");
+
+        let placeholder_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Curve placeholder buffer"),
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let pipelines = Self::build_pipelines(
+            device,
+            &module,
+            &pipeline_layout,
+            format,
+            depth_format,
+            depth_compare,
+            sample_count,
+        );
+
+        Self {
+            format,
+            depth_format,
+            depth_compare,
+            module,
+            pipeline_layout,
+            pipelines,
+            layer_bind_group_layout,
+            style_bind_group_layout,
+            compute_bind_group_layout,
+            compute_pipeline_layout,
+            frames_pipeline,
+            tube_pipeline,
+            placeholder_buffer,
+        }
+    }
+
+    fn build_pipelines(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        sample_count: u32,
+    ) -> CurvePipelines {
+        const POINT_ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x4];
+        const END_ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![1 => Float32x4];
+        const TUBE_ATTRIBS: [wgpu::VertexAttribute; 2] =
+            wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4];
+        let point_stride = core::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress;
+
+        let pipeline = |label,
+                        vertex_entry,
+                        fragment_entry,
+                        buffers: &[wgpu::VertexBufferLayout],
+                        topology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: Some(vertex_entry),
+                    buffers,
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module,
+                    entry_point: Some(fragment_entry),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth_format,
+                    depth_write_enabled: true,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        // Each segment is an instance reading its two ends from the same buffer, offset by a point
+        let line = pipeline(
+            "Curve line pipeline",
+            "vs_line",
+            "fs_line",
+            &[
+                wgpu::VertexBufferLayout {
+                    array_stride: point_stride,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &POINT_ATTRIBS,
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: point_stride,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &END_ATTRIBS,
+                },
+            ],
+            wgpu::PrimitiveTopology::TriangleStrip,
+        );
+        let tube = pipeline(
+            "Curve tube pipeline",
+            "vs_tube",
+            "fs_tube",
+            &[wgpu::VertexBufferLayout {
+                array_stride: 2 * point_stride,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &TUBE_ATTRIBS,
+            }],
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+        CurvePipelines { line, tube }
+    }
+
+    /// Pipelines for render targets with another sample count than the renderer's own
+    pub fn create_pipelines(&self, device: &wgpu::Device, sample_count: u32) -> CurvePipelines {
+        Self::build_pipelines(
+            device,
+            &self.module,
+            &self.pipeline_layout,
+            self.format,
+            self.depth_format,
+            self.depth_compare,
+            sample_count,
+        )
+    }
+
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipelines = self.create_pipelines(device, sample_count);
+    }
+
+    pub fn pipelines(&self) -> &CurvePipelines {
+        &self.pipelines
+    }
+
+    /// Sample the expression and build the tube, as needed
+    fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, curve: &Curve) {
+        let evaluator = match &curve.source {
+            CurveSource::Parametric(parametric) => {
+                let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("Curve expression"),
+                    source: wgpu::ShaderSource::Wgsl(parametric.shader_source().into()),
+                });
+                Some(
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Curve evaluator compute pipeline"),
+                        layout: Some(&self.compute_pipeline_layout),
+                        module: &module,
+                        entry_point: Some("evaluate"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache: None,
+                    }),
+                )
+            }
+            CurveSource::Polyline(_) => None,
+        };
+        if evaluator.is_none() && curve.tube.is_none() {
+            return;
+        }
+
+        let (frames, vertices) = match &curve.tube {
+            Some(tube) => (&tube.frame_buffer, &tube.vertex_buffer),
+            None => (&self.placeholder_buffer, &self.placeholder_buffer),
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Curve compute bind group"),
+            layout: &self.compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: curve.point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: curve.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: frames.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: vertices.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Curve encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Curve compute pass"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &bind_group, &[]);
+            if let Some(evaluator) = &evaluator {
+                pass.set_pipeline(evaluator);
+                pass.dispatch_workgroups(curve.point_count.div_ceil(256), 1, 1);
+            }
+            if curve.tube.is_some() {
+                pass.set_pipeline(&self.frames_pipeline);
+                pass.dispatch_workgroups(1, 1, 1);
+                pass.set_pipeline(&self.tube_pipeline);
+                pass.dispatch_workgroups((curve.point_count * TUBE_SIDES).div_ceil(256), 1, 1);
+            }
+        }
+        queue.submit([encoder.finish()]);
+    }
+}
+
+impl CurveLayer {
+    pub fn new(device: &wgpu::Device, renderer: &CurveRenderer) -> Self {
+        let layer = LayerUniform {
+            viewport: [1.0, 1.0],
+            origin: [0.0, 0.0],
+            pixel_scale: 1.0,
+            _padding: 0.0,
+        };
+        let layer_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Curve layer uniform buffer"),
+            contents: bytemuck::bytes_of(&layer),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layer_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Curve layer bind group"),
+            layout: &renderer.layer_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: layer_buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            layer_buffer,
+            layer_bind_group,
+            layer,
+            curves: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Set the region of the render target drawn into
+    pub fn set_viewport(&mut self, queue: &wgpu::Queue, region: &Tile) {
+        self.layer.viewport = [region.width as f32, region.height as f32];
+        self.layer.origin = [region.x as f32, region.y as f32];
+        queue.write_buffer(&self.layer_buffer, 0, bytemuck::bytes_of(&self.layer));
+    }
+
+    /// Draw the next submission into `region` of the target with `pixel_scale` rendered pixels
+    /// per line pixel, until `set_viewport`
+    pub fn write_viewport(&self, queue: &wgpu::Queue, region: &Tile, pixel_scale: f32) {
+        let layer = LayerUniform {
+            viewport: [region.width as f32, region.height as f32],
+            origin: [region.x as f32, region.y as f32],
+            pixel_scale,
+            ..self.layer
+        };
+        queue.write_buffer(&self.layer_buffer, 0, bytemuck::bytes_of(&layer));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.curves.is_empty()
+    }
+
+    /// Add a polyline through `points`, which are ordered by t
+    pub fn add_polyline(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &CurveRenderer,
+        points: &[[f32; 3]],
+        style: CurveStyle,
+    ) -> Result<CurveId, CurveError> {
+        let id = CurveId(self.next_id);
+        self.insert(
+            device,
+            queue,
+            renderer,
+            id,
+            CurveSource::Polyline(points.to_vec()),
+            style,
+        )?;
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Add a curve sampled from an expression on the GPU
+    ///
+    /// The returned future resolves once the expression compiled, and fails with
+    /// `CurveError::Expression` if it did not, in which case the curve should be removed.
+    pub fn add_parametric(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &CurveRenderer,
+        curve: ParametricCurve,
+        style: CurveStyle,
+    ) -> Result<
+        (
+            CurveId,
+            impl Future<Output = Result<(), CurveError>> + 'static,
+        ),
+        CurveError,
+    > {
+        let id = CurveId(self.next_id);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let inserted = self.insert(
+            device,
+            queue,
+            renderer,
+            id,
+            CurveSource::Parametric(curve),
+            style,
+        );
+        let compiled = device.pop_error_scope();
+        inserted?;
+        self.next_id += 1;
+
+        Ok((id, async move {
+            match compiled.await {
+                Some(err) => Err(CurveError::Expression(format!("{err}"))),
+                None => Ok(()),
+            }
+        }))
+    }
+
+    /// Upload the curves of a layer on a lost device, keeping their ids
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &CurveRenderer,
+        lost: &CurveLayer,
+    ) -> Result<(), CurveError> {
+        for curve in &lost.curves {
+            self.insert(
+                device,
+                queue,
+                renderer,
+                curve.id,
+                curve.source.clone(),
+                curve.style,
+            )?;
+        }
+        self.next_id = lost.next_id;
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &CurveRenderer,
+        id: CurveId,
+        source: CurveSource,
+        style: CurveStyle,
+    ) -> Result<(), CurveError> {
+        source.validate(style.kind)?;
+        if !(style.width.is_finite() && style.width > 0.0) {
+            return Err(CurveError::InvalidWidth(style.width));
+        }
+
+        let point_count = source.point_count() as u32;
+        let max = device.limits().max_storage_buffer_binding_size as u64;
+        let vertex_bytes = match style.kind {
+            CurveKind::Line => 0,
+            CurveKind::Tube => point_count as u64 * TUBE_SIDES as u64 * 32,
+        };
+        let bytes = vertex_bytes.max(point_count as u64 * 16);
+        if bytes > max {
+            return Err(CurveError::BufferTooLarge { bytes, max });
+        }
+
+        let (t_start, t_end) = match &source {
+            CurveSource::Polyline(_) => (0.0, 1.0),
+            CurveSource::Parametric(curve) => (*curve.t_range.start(), *curve.t_range.end()),
+        };
+        let params = CurveParams {
+            count: point_count,
+            t_start,
+            t_end,
+            radius: 0.5 * style.width,
+        };
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Curve params buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let point_usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE;
+        let point_buffer = match &source {
+            CurveSource::Polyline(points) => {
+                let last = (points.len() - 1) as f32;
+                let points: Vec<[f32; 4]> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &[x, y, z])| [x, y, z, i as f32 / last])
+                    .collect();
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Curve point buffer"),
+                    contents: bytemuck::cast_slice(&points),
+                    usage: point_usage,
+                })
+            }
+            CurveSource::Parametric(_) => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Curve point buffer"),
+                size: point_count as u64 * 16,
+                usage: point_usage,
+                mapped_at_creation: false,
+            }),
+        };
+
+        let (color_start, color_end) = match style.color {
+            CurveColor::Solid(color) => (color, color),
+            CurveColor::Gradient { start, end } => (start, end),
+        };
+        let style_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Curve style buffer"),
+            contents: bytemuck::bytes_of(&StyleUniform {
+                color_start,
+                width: style.width,
+                color_end,
+                _padding: 0.0,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let style_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Curve style bind group"),
+            layout: &renderer.style_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: style_buffer.as_entire_binding(),
+            }],
+        });
+
+        let tube = (style.kind == CurveKind::Tube).then(|| {
+            let frame_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Curve frame buffer"),
+                size: point_count as u64 * 16,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Curve tube vertex buffer"),
+                size: vertex_bytes,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            let indices = tube_indices(point_count);
+            let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Curve tube index buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+            TubeMesh {
+                frame_buffer,
+                vertex_buffer,
+                index_buffer,
+                index_count: indices.len() as u32,
+            }
+        });
+
+        let curve = Curve {
+            id,
+            source,
+            style,
+            point_count,
+            point_buffer,
+            params_buffer,
+            style_buffer,
+            style_bind_group,
+            tube,
+        };
+        renderer.generate(device, queue, &curve);
+        self.curves.push(curve);
+        Ok(())
+    }
+
+    /// Returns whether there was a curve with this id
+    pub fn remove(&mut self, id: CurveId) -> bool {
+        let Some(index) = self.curves.iter().position(|curve| curve.id == id) else {
+            return false;
+        };
+        self.curves.remove(index).destroy();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for curve in self.curves.drain(..) {
+            curve.destroy();
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        self.clear();
+        self.layer_buffer.destroy();
+    }
+
+    /// Draw into a pass whose camera bind group is set, with `pipelines` matching its targets
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, pipelines: &CurvePipelines) {
+        render_pass.set_bind_group(1, &self.layer_bind_group, &[]);
+        for curve in &self.curves {
+            render_pass.set_bind_group(2, &curve.style_bind_group, &[]);
+            match &curve.tube {
+                Some(tube) => {
+                    render_pass.set_pipeline(&pipelines.tube);
+                    render_pass.set_vertex_buffer(0, tube.vertex_buffer.slice(..));
+                    render_pass
+                        .set_index_buffer(tube.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..tube.index_count, 0, 0..1);
+                }
+                None => {
+                    render_pass.set_pipeline(&pipelines.line);
+                    render_pass.set_vertex_buffer(0, curve.point_buffer.slice(..));
+                    render_pass.set_vertex_buffer(1, curve.point_buffer.slice(16..));
+                    render_pass.draw(0..4, 0..curve.point_count - 1);
+                }
+            }
+        }
+    }
+}
+
+/// Two triangles between each pair of neighbouring vertices of consecutive rings
+fn tube_indices(point_count: u32) -> Vec<u32> {
+    let mut indices = Vec::with_capacity((point_count as usize - 1) * TUBE_SIDES as usize * 6);
+    for i in 0..point_count - 1 {
+        for j in 0..TUBE_SIDES {
+            let a = i * TUBE_SIDES + j;
+            let b = i * TUBE_SIDES + (j + 1) % TUBE_SIDES;
+            let (c, d) = (a + TUBE_SIDES, b + TUBE_SIDES);
+            indices.extend_from_slice(&[a, b, d, a, d, c]);
+        }
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn validates_sources() {
+        let line =
+            |points: &[[f32; 3]]| CurveSource::Polyline(points.to_vec()).validate(CurveKind::Line);
+        assert_eq!(line(&[[0.0; 3]]), Err(CurveError::TooFewPoints));
+        assert_eq!(
+            line(&[[0.0; 3], [f32::NAN, 0.0, 0.0]]),
+            Err(CurveError::NotFinite)
+        );
+        assert_eq!(line(&[[0.0; 3], [1.0; 3]]), Ok(()));
+
+        let parametric = |samples, kind| {
+            CurveSource::Parametric(ParametricCurve {
+                expression: "vec3f(t)".into(),
+                t_range: 0.0..=1.0,
+                samples,
+            })
+            .validate(kind)
+        };
+        assert_eq!(parametric(2, CurveKind::Line), Ok(()));
+        assert_eq!(
+            parametric(MAX_CURVE_POINTS as u32 + 1, CurveKind::Line),
+            Err(CurveError::TooManyPoints {
+                count: MAX_CURVE_POINTS + 1,
+                max: MAX_CURVE_POINTS
+            })
+        );
+
+        // Tubes are oriented sequentially, so they get far fewer points
+        let tube_points = MAX_TUBE_POINTS as u32 + 1;
+        assert_eq!(parametric(tube_points, CurveKind::Line), Ok(()));
+        assert_eq!(
+            parametric(tube_points, CurveKind::Tube),
+            Err(CurveError::TooManyPoints {
+                count: MAX_TUBE_POINTS + 1,
+                max: MAX_TUBE_POINTS
+            })
+        );
+    }
+
+    #[test]
+    fn tube_rings_are_stitched_together() {
+        let indices = tube_indices(3);
+        assert_eq!(indices.len(), 2 * TUBE_SIDES as usize * 6);
+        assert_eq!(indices.iter().max(), Some(&(3 * TUBE_SIDES - 1)));
+        // The last quad of a ring wraps around to its first vertex
+        assert_eq!(
+            indices[(TUBE_SIDES as usize - 1) * 6..TUBE_SIDES as usize * 6],
+            [7, 0, 8, 7, 8, 15]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Curves drawn as thick screen-space lines or lit tubes, depth tested against the surface
+
+// The matrices at the start of the camera uniform, see shader.wgsl for the rest
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+};
+
+struct LayerUniform {
+    // Size of the region drawn into in pixels
+    viewport: vec2f,
+    // Top left corner of the region in framebuffer pixels
+    origin: vec2f,
+    // Rendered pixels per line pixel, above 1 when supersampling
+    pixel_scale: f32,
+};
+
+struct Style {
+    color_start: vec3f,
+    // Line width in pixels
+    width: f32,
+    color_end: vec3f,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> layer: LayerUniform;
+
+@group(2) @binding(0)
+var<uniform> style: Style;
+
+// Towards the upper left of the viewer, in view space, as in markers.wgsl
+const LIGHT = vec3f(-0.408, 0.408, 0.816);
+
+// Keeps lines in front of the camera, the rest of the near plane is clipped as usual
+const MIN_W = 1e-6;
+
+fn color_at(t: f32) -> vec3f {
+    return mix(style.color_start, style.color_end, t);
+}
+
+// Lines
+
+struct SegmentInput {
+    // x, y, z and t of either end
+    @location(0) start: vec4f,
+    @location(1) end: vec4f,
+};
+
+struct LineOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) t: f32,
+    // Ends of the segment in framebuffer pixels
+    @location(1) @interpolate(flat) start: vec2f,
+    @location(2) @interpolate(flat) end: vec2f,
+};
+
+fn to_pixels(clip: vec4f) -> vec2f {
+    let ndc = clip.xy / clip.w;
+    return layer.origin + vec2f(0.5 + 0.5 * ndc.x, 0.5 - 0.5 * ndc.y) * layer.viewport;
+}
+
+// Each segment is a quad around it, widened by half the line width on every side, with the
+// corners cut away into round caps by the fragment shader. Caps overlap into round joins.
+@vertex
+fn vs_line(@builtin(vertex_index) index: u32, segment: SegmentInput) -> LineOutput {
+    var a = camera.view_proj * vec4f(segment.start.xyz, 1.0);
+    var b = camera.view_proj * vec4f(segment.end.xyz, 1.0);
+    var out: LineOutput;
+    if a.w < MIN_W && b.w < MIN_W {
+        out.clip_position = vec4f(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    // Cut the part behind the camera
+    if a.w < MIN_W {
+        a = mix(a, b, (MIN_W - a.w) / (b.w - a.w));
+    } else if b.w < MIN_W {
+        b = mix(b, a, (MIN_W - b.w) / (a.w - b.w));
+    }
+
+    let pa = to_pixels(a);
+    let pb = to_pixels(b);
+    var direction = vec2f(1.0, 0.0);
+    if any(pa != pb) {
+        direction = normalize(pb - pa);
+    }
+    let normal = vec2f(-direction.y, direction.x);
+
+    let at_end = index >= 2u;
+    let along = select(-1.0, 1.0, at_end);
+    let side = f32(index & 1u) * 2.0 - 1.0;
+    let half_width = 0.5 * style.width * layer.pixel_scale;
+    let offset = (direction * along + normal * side) * half_width;
+
+    let clip = select(a, b, at_end);
+    out.clip_position = clip + vec4f(offset * vec2f(2.0, -2.0) / layer.viewport * clip.w, 0.0, 0.0);
+    out.t = select(segment.start.w, segment.end.w, at_end);
+    out.start = pa;
+    out.end = pb;
+    return out;
+}
+
+@fragment
+fn fs_line(in: LineOutput) -> @location(0) vec4f {
+    let p = in.clip_position.xy;
+    let d = in.end - in.start;
+    let l2 = dot(d, d);
+    var along = 0.0;
+    if l2 > 0.0 {
+        along = clamp(dot(p - in.start, d) / l2, 0.0, 1.0);
+    }
+    let half_width = 0.5 * style.width * layer.pixel_scale;
+    if distance(p, in.start + along * d) > half_width {
+        discard;
+    }
+    return vec4f(color_at(in.t), 1.0);
+}
+
+// Tubes
+
+struct TubeInput {
+    @location(0) position: vec4f,
+    @location(1) normal: vec4f,
+};
+
+struct TubeOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) t: f32,
+    @location(1) normal: vec3f,
+};
+
+@vertex
+fn vs_tube(vertex: TubeInput) -> TubeOutput {
+    var out: TubeOutput;
+    out.clip_position = camera.view_proj * vec4f(vertex.position.xyz, 1.0);
+    out.t = vertex.position.w;
+    out.normal = (camera.view * vec4f(vertex.normal.xyz, 0.0)).xyz;
+    return out;
+}
+
+@fragment
+fn fs_tube(in: TubeOutput) -> @location(0) vec4f {
+    let shade = 0.3 + 0.7 * max(dot(normalize(in.normal), LIGHT), 0.0);
+    return vec4f(color_at(in.t) * shade, 1.0);
+}
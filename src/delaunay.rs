@@ -0,0 +1,444 @@
+//! 2D Delaunay triangulation of scattered points with the Bowyer–Watson algorithm
+//!
+//! Points are inserted one at a time. Each insertion walks to the triangle containing the point,
+//! grows the cavity of triangles whose circumcircle contains it and fans new triangles from the
+//! point to the cavity's boundary. Everything runs in `f64` on coordinates normalised to the unit
+//! square, which keeps the predicates well conditioned for `f32` input.
+//!
+//! Degenerate input is handled rather than rejected: duplicate points are triangulated once,
+//! cocircular points such as a regular grid get either diagonal, and input that is entirely
+//! collinear yields no triangles.
+
+use alloc::vec::Vec;
+
+/// Marks a triangle edge on the outside of the triangulation
+const NONE: u32 = u32::MAX;
+
+/// Points closer than this, relative to the size of the input, are treated as duplicates
+const MERGE_DISTANCE: f64 = 1e-10;
+
+/// Size of the enclosing super triangle relative to the input
+///
+/// Larger is closer to the true convex hull but less precise. Slivers along nearly straight
+/// stretches of the hull may be missing.
+const SUPER_TRIANGLE_SCALE: f64 = 1e3;
+
+#[derive(Clone, Copy)]
+struct Triangle {
+    /// Counter-clockwise
+    vertices: [u32; 3],
+    /// Neighbour across the edge opposite each vertex
+    neighbours: [u32; 3],
+    alive: bool,
+}
+
+/// Edge of the cavity left by the removed triangles, counter-clockwise around it
+struct BoundaryEdge {
+    from: u32,
+    to: u32,
+    /// Triangle on the far side of the edge
+    outside: u32,
+}
+
+struct Triangulation {
+    points: Vec<[f64; 2]>,
+    triangles: Vec<Triangle>,
+    /// Recently created triangle to start point location walks from
+    last: u32,
+}
+
+/// Twice the signed area of `a`, `b`, `c`, positive when counter-clockwise
+fn orient(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Positive when `d` is strictly inside the circumcircle of the counter-clockwise `a`, `b`, `c`
+fn in_circle(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> f64 {
+    let (adx, ady) = (a[0] - d[0], a[1] - d[1]);
+    let (bdx, bdy) = (b[0] - d[0], b[1] - d[1]);
+    let (cdx, cdy) = (c[0] - d[0], c[1] - d[1]);
+
+    let ad = adx * adx + ady * ady;
+    let bd = bdx * bdx + bdy * bdy;
+    let cd = cdx * cdx + cdy * cdy;
+
+    adx * (bdy * cd - bd * cdy) - ady * (bdx * cd - bd * cdx) + ad * (bdx * cdy - bdy * cdx)
+}
+
+impl Triangulation {
+    /// Start with a single triangle around the unit square, its vertices are the last three
+    /// points
+    fn new(mut points: Vec<[f64; 2]>) -> Self {
+        let first = points.len() as u32;
+        let s = SUPER_TRIANGLE_SCALE;
+        points.extend_from_slice(&[[-s, -s], [s, -s], [0.5, s]]);
+
+        Self {
+            points,
+            triangles: alloc::vec![Triangle {
+                vertices: [first, first + 1, first + 2],
+                neighbours: [NONE; 3],
+                alive: true,
+            }],
+            last: 0,
+        }
+    }
+
+    fn point(&self, vertex: u32) -> [f64; 2] {
+        self.points[vertex as usize]
+    }
+
+    /// Counter-clockwise edge of `triangle` opposite its vertex `i`
+    fn edge(&self, triangle: u32, i: usize) -> (u32, u32) {
+        let vertices = self.triangles[triangle as usize].vertices;
+        (vertices[(i + 1) % 3], vertices[(i + 2) % 3])
+    }
+
+    /// `orient` of the edge `from`, `to` and `p`
+    ///
+    /// Always evaluated in the same vertex order so the triangles on either side of an edge
+    /// agree exactly which side `p` is on, otherwise rounding can leave points on an edge
+    /// outside both.
+    fn side(&self, from: u32, to: u32, p: [f64; 2]) -> f64 {
+        if from < to {
+            orient(self.point(from), self.point(to), p)
+        } else {
+            -orient(self.point(to), self.point(from), p)
+        }
+    }
+
+    fn contains(&self, triangle: u32, p: [f64; 2]) -> bool {
+        (0..3).all(|i| {
+            let (a, b) = self.edge(triangle, i);
+            self.side(a, b, p) >= 0.0
+        })
+    }
+
+    /// Triangle containing `p`, found by walking towards it from the last inserted triangle
+    fn locate(&self, p: [f64; 2]) -> Option<u32> {
+        let mut triangle = self.last;
+        // Trying the edges in a fixed order can cycle between cocircular triangles, so rotate it.
+        // The limit only guards against rounding.
+        'walk: for step in 0..self.triangles.len() {
+            for i in (0..3).map(|i| (i + step) % 3) {
+                let (a, b) = self.edge(triangle, i);
+                if self.side(a, b, p) < 0.0 {
+                    let next = self.triangles[triangle as usize].neighbours[i];
+                    if next == NONE {
+                        break 'walk;
+                    }
+                    triangle = next;
+                    continue 'walk;
+                }
+            }
+            return Some(triangle);
+        }
+
+        (0..self.triangles.len() as u32)
+            .find(|&triangle| self.triangles[triangle as usize].alive && self.contains(triangle, p))
+    }
+
+    fn is_in_circumcircle(&self, triangle: u32, p: [f64; 2]) -> bool {
+        let [a, b, c] = self.triangles[triangle as usize].vertices;
+        in_circle(self.point(a), self.point(b), self.point(c), p) > 0.0
+    }
+
+    fn insert(&mut self, vertex: u32) {
+        let p = self.point(vertex);
+        let Some(start) = self.locate(p) else {
+            return;
+        };
+        // Nearly coincident with a vertex already in place, inserting it would only add slivers
+        let near = |v: u32| {
+            let q = self.point(v);
+            (q[0] - p[0]).abs() <= MERGE_DISTANCE && (q[1] - p[1]).abs() <= MERGE_DISTANCE
+        };
+        if self.triangles[start as usize]
+            .vertices
+            .into_iter()
+            .any(near)
+        {
+            return;
+        }
+
+        // Grow the cavity from the containing triangle so that it stays connected
+        let mut cavity = alloc::vec![start];
+        self.triangles[start as usize].alive = false;
+        let mut boundary = Vec::new();
+        let mut next = 0;
+        while let Some(&triangle) = cavity.get(next) {
+            next += 1;
+            for i in 0..3 {
+                let (from, to) = self.edge(triangle, i);
+                let outside = self.triangles[triangle as usize].neighbours[i];
+                if outside != NONE && !self.triangles[outside as usize].alive {
+                    // Already in the cavity
+                    continue;
+                }
+                // Rounding can reject a triangle the point cannot see across, which would leave
+                // a cavity that is not star-shaped, so those are always removed
+                let visible = self.side(from, to, p) > 0.0;
+                if outside != NONE && (!visible || self.is_in_circumcircle(outside, p)) {
+                    self.triangles[outside as usize].alive = false;
+                    cavity.push(outside);
+                } else {
+                    boundary.push(BoundaryEdge { from, to, outside });
+                }
+            }
+        }
+
+        // Edges removed later in the walk may have been recorded as boundary first
+        boundary.retain(|edge| edge.outside == NONE || self.triangles[edge.outside as usize].alive);
+
+        // Fan from the point to each boundary edge, reusing the removed triangles' slots
+        let mut slots = cavity.into_iter();
+        let created: Vec<u32> = boundary
+            .iter()
+            .map(|edge| {
+                let triangle = Triangle {
+                    vertices: [edge.from, edge.to, vertex],
+                    neighbours: [NONE, NONE, edge.outside],
+                    alive: true,
+                };
+                match slots.next() {
+                    Some(slot) => {
+                        self.triangles[slot as usize] = triangle;
+                        slot
+                    }
+                    None => {
+                        self.triangles.push(triangle);
+                        self.triangles.len() as u32 - 1
+                    }
+                }
+            })
+            .collect();
+
+        for (edge, &triangle) in boundary.iter().zip(&created) {
+            if edge.outside != NONE {
+                let outside = &mut self.triangles[edge.outside as usize];
+                for i in 0..3 {
+                    let [a, b, c] = outside.vertices;
+                    let (from, to) = [(b, c), (c, a), (a, b)][i];
+                    if from == edge.to && to == edge.from {
+                        outside.neighbours[i] = triangle;
+                    }
+                }
+            }
+            // The fan neighbours share the edges to the point, across `to` and across `from`
+            let after = boundary.iter().position(|other| other.from == edge.to);
+            let before = boundary.iter().position(|other| other.to == edge.from);
+            let neighbours = &mut self.triangles[triangle as usize].neighbours;
+            neighbours[0] = after.map_or(NONE, |i| created[i]);
+            neighbours[1] = before.map_or(NONE, |i| created[i]);
+        }
+
+        if let Some(&last) = created.first() {
+            self.last = last;
+        }
+    }
+}
+
+/// Position of `p` in the unit square along a Hilbert curve
+fn hilbert_index(p: [f64; 2]) -> u64 {
+    const ORDER: u32 = 16;
+    let scale = ((1u32 << ORDER) - 1) as f64;
+    let (mut x, mut y) = ((p[0] * scale) as u32, (p[1] * scale) as u32);
+
+    let mut index = 0;
+    let mut s = 1u32 << (ORDER - 1);
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = s.wrapping_sub(1).wrapping_sub(x) & (s - 1);
+                y = s.wrapping_sub(1).wrapping_sub(y) & (s - 1);
+            }
+            core::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+/// Order for insertion so that consecutive points are close, keeping point location walks short
+/// and cavities small
+///
+/// Follows a Hilbert curve, which unlike sweeping in rows also avoids building long slivers out
+/// of the collinear rows of gridded data.
+fn insertion_order(points: &[[f64; 2]]) -> Vec<u32> {
+    let mut order: Vec<u32> = (0..points.len() as u32).collect();
+    order.sort_by_cached_key(|&i| hilbert_index(points[i as usize]));
+    order
+}
+
+/// Triangulate `points` by their X and Y coordinates
+///
+/// Returns counter-clockwise triangles of indices into `points`. Duplicate points are only
+/// referenced once and non-finite ones never.
+pub fn triangulate(points: &[[f64; 2]]) -> Vec<[u32; 3]> {
+    let finite: Vec<u32> = (0..points.len() as u32)
+        .filter(|&i| points[i as usize].iter().all(|v| v.is_finite()))
+        .collect();
+
+    let (min, max) = finite.iter().fold(
+        ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+        |(min, max), &i| {
+            let p = points[i as usize];
+            (
+                [min[0].min(p[0]), min[1].min(p[1])],
+                [max[0].max(p[0]), max[1].max(p[1])],
+            )
+        },
+    );
+    let size = (max[0] - min[0]).max(max[1] - min[1]);
+    if finite.len() < 3 || size <= 0.0 {
+        return Vec::new();
+    }
+
+    let normalised: Vec<[f64; 2]> = finite
+        .iter()
+        .map(|&i| {
+            let p = points[i as usize];
+            [(p[0] - min[0]) / size, (p[1] - min[1]) / size]
+        })
+        .collect();
+
+    let mut triangulation = Triangulation::new(normalised.clone());
+    for vertex in insertion_order(&normalised) {
+        triangulation.insert(vertex);
+    }
+
+    // Drop everything attached to the super triangle, which leaves nothing for collinear input
+    let first_super = finite.len() as u32;
+    triangulation
+        .triangles
+        .iter()
+        .filter(|triangle| triangle.alive && triangle.vertices.iter().all(|&v| v < first_super))
+        .map(|triangle| triangle.vertices.map(|v| finite[v as usize]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeSet;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    /// Deterministic points in `0..1` from a linear congruential generator
+    fn random_points(n: usize) -> Vec<[f64; 2]> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..n).map(|_| [next(), next()]).collect()
+    }
+
+    fn area(points: &[[f64; 2]], triangles: &[[u32; 3]]) -> f64 {
+        triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|v| points[v as usize]);
+                orient(a, b, c) / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn square_has_two_triangles() {
+        let points = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&points, &triangles) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn duplicates_are_used_once() {
+        let points = [
+            [0.0, 0.0],
+            [2.0, 0.0],
+            [0.0, 0.0],
+            [0.0, 2.0],
+            [2.0, 0.0],
+            [0.0, 0.0],
+        ];
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), 1);
+        assert!((area(&points, &triangles) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn collinear_points_have_no_triangles() {
+        let points: Vec<[f64; 2]> = (0..10).map(|i| [i as f64, 2.0 * i as f64]).collect();
+        assert!(triangulate(&points).is_empty());
+        assert!(triangulate(&[[0.0, 0.0], [1.0, 1.0]]).is_empty());
+        assert!(triangulate(&[[f64::NAN, 0.0], [1.0, 1.0], [1.0, 0.0]]).is_empty());
+    }
+
+    #[test]
+    fn fans_from_a_point_off_a_line() {
+        let mut points: Vec<[f64; 2]> = (0..8).map(|i| [i as f64, 0.0]).collect();
+        points.push([3.5, 1.0]);
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), 7);
+        assert!((area(&points, &triangles) - 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn regular_grid_is_fully_covered() {
+        // Every cell's corners are cocircular
+        let points: Vec<[f64; 2]> = (0..100)
+            .map(|i| [(i % 10) as f64, (i / 10) as f64])
+            .collect();
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), 2 * 9 * 9);
+        assert!((area(&points, &triangles) - 81.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn random_points_are_delaunay() {
+        let points = random_points(500);
+        let triangles = triangulate(&points);
+
+        for t in &triangles {
+            let [a, b, c] = t.map(|v| points[v as usize]);
+            assert!(orient(a, b, c) > 0.0);
+            for &p in &points {
+                assert!(in_circle(a, b, c, p) <= 1e-12);
+            }
+        }
+
+        // Euler's formula, 2n - 2 - h triangles for h edges on the hull
+        let edges: BTreeSet<(u32, u32)> = triangles
+            .iter()
+            .flat_map(|t| (0..3).map(move |i| (t[i], t[(i + 1) % 3])))
+            .collect();
+        let hull = edges
+            .iter()
+            .filter(|&&(a, b)| !edges.contains(&(b, a)))
+            .count();
+        assert_eq!(triangles.len(), 2 * points.len() - 2 - hull);
+    }
+
+    #[test]
+    fn nearly_coincident_points_are_merged() {
+        let mut points = random_points(50);
+        let copies: Vec<[f64; 2]> = points.iter().map(|p| [p[0] + 1e-14, p[1]]).collect();
+        points.extend(copies);
+        let triangles = triangulate(&points);
+
+        // Either of each pair may be kept, depending on insertion order
+        let used: BTreeSet<u32> = triangles.iter().flatten().copied().collect();
+        assert!((0..50).all(|v| !(used.contains(&v) && used.contains(&(v + 50)))));
+        assert!(triangulate(&[[0.5, 0.5]; 4]).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use core::fmt;
+
+use crate::{curves::CurveError, meshgrid::ResolutionError, scatter::ScatterError};
+
+/// Failures setting up or driving the GPU
+#[derive(Debug)]
+pub enum Error {
+    CreateSurface(wgpu::CreateSurfaceError),
+    RequestAdapter(wgpu::RequestAdapterError),
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The adapter cannot present to the surface
+    SurfaceUnsupported,
+    /// The next frame could not be acquired from the surface
+    SurfaceTexture(wgpu::SurfaceError),
+    MapBuffer(wgpu::BufferAsyncError),
+    Resolution(ResolutionError),
+    /// Frames in this format cannot be encoded as an image
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// An image of this width and height cannot be captured, it is empty or too large
+    ImageSize((u32, u32)),
+    Scatter(ScatterError),
+    Curve(CurveError),
+    /// The render target cannot be split into this many rows and columns of viewports
+    ViewportGrid((u32, u32)),
+    /// More clipping planes than a viewport supports
+    ClipPlanes(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateSurface(err) => write!(f, "Could not create surface: {err}"),
+            Self::RequestAdapter(err) => write!(f, "Could not get appropriate adapter: {err}"),
+            Self::RequestDevice(err) => write!(f, "Could not create device: {err}"),
+            Self::SurfaceUnsupported => write!(f, "Surface not supported by adapter"),
+            Self::SurfaceTexture(err) => write!(f, "Could not get current texture: {err}"),
+            Self::MapBuffer(err) => write!(f, "Could not map buffer: {err}"),
+            Self::Resolution(err) => err.fmt(f),
+            Self::UnsupportedFormat(format) => write!(f, "Cannot encode {format:?} frames"),
+            Self::ImageSize((width, height)) => {
+                write!(f, "Cannot capture an image of {width}x{height} pixels")
+            }
+            Self::Scatter(err) => err.fmt(f),
+            Self::Curve(err) => err.fmt(f),
+            Self::ViewportGrid((rows, cols)) => write!(
+                f,
+                "Cannot split into {rows}x{cols} viewports, at most {max}x{max} are supported",
+                max = crate::viewport::MAX_GRID_SIZE
+            ),
+            Self::ClipPlanes(count) => write!(
+                f,
+                "Cannot clip by {count} planes, at most {max} are supported",
+                max = crate::clipping::MAX_CLIP_PLANES
+            ),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::CreateSurface(err) => Some(err),
+            Self::RequestAdapter(err) => Some(err),
+            Self::RequestDevice(err) => Some(err),
+            Self::SurfaceUnsupported => None,
+            Self::SurfaceTexture(err) => Some(err),
+            Self::MapBuffer(err) => Some(err),
+            Self::Resolution(err) => Some(err),
+            Self::Scatter(err) => Some(err),
+            Self::Curve(err) => Some(err),
+            Self::UnsupportedFormat(_)
+            | Self::ImageSize(_)
+            | Self::ViewportGrid(_)
+            | Self::ClipPlanes(_) => None,
+        }
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        Self::CreateSurface(err)
+    }
+}
+
+impl From<wgpu::RequestAdapterError> for Error {
+    fn from(err: wgpu::RequestAdapterError) -> Self {
+        Self::RequestAdapter(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        Self::RequestDevice(err)
+    }
+}
+
+impl From<wgpu::SurfaceError> for Error {
+    fn from(err: wgpu::SurfaceError) -> Self {
+        Self::SurfaceTexture(err)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        Self::MapBuffer(err)
+    }
+}
+
+impl From<ResolutionError> for Error {
+    fn from(err: ResolutionError) -> Self {
+        Self::Resolution(err)
+    }
+}
+
+impl From<ScatterError> for Error {
+    fn from(err: ScatterError) -> Self {
+        Self::Scatter(err)
+    }
+}
+
+impl From<CurveError> for Error {
+    fn from(err: CurveError) -> Self {
+        Self::Curve(err)
+    }
+}
@@ -15,6 +15,11 @@ fn func(input: vec2f) -> f32 {
 
 const ELEMENT_SIZE = 6;
 
+// Greyscale colour mapping, COLOR_MIN maps to black and COLOR_MAX to white.
+// Must match `meshgrid::COLOR_RANGE` which labels the colorbar.
+const COLOR_MIN = -0.5;
+const COLOR_MAX = 2.0;
+
 @compute @workgroup_size(256)
 fn evaluate(@builtin(global_invocation_id) gid: vec3u)
 {
@@ -26,8 +31,7 @@ fn evaluate(@builtin(global_invocation_id) gid: vec3u)
     let value = func(input);
     vertex_buffer[e + 2] = value;
 
-    let color = (value + 0.5) * 0.4;
-    // TODO
+    let color = (value - COLOR_MIN) / (COLOR_MAX - COLOR_MIN);
     vertex_buffer[e + 3] = color;
     vertex_buffer[e + 4] = color;
     vertex_buffer[e + 5] = color;
@@ -0,0 +1,218 @@
+use alloc::{format, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use glam::Vec2;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    AddEventListenerOptions, HtmlCanvasElement, KeyboardEvent, PointerEvent, WheelEvent,
+};
+
+use crate::{
+    State,
+    frame_loop::FrameLoop,
+    input::{Action, Command, Gestures, PointerInput, PointerKind},
+};
+
+type EventCallback = Closure<dyn FnMut(web_sys::Event)>;
+
+/// Canvas event listeners feeding `Gestures` and applying its commands to the `State`
+///
+/// Listeners are removed when this is dropped.
+pub struct EventListeners {
+    canvas: HtmlCanvasElement,
+    listeners: Vec<(&'static str, EventCallback)>,
+}
+
+/// Everything a listener needs, shared between all of them
+struct Context {
+    canvas: HtmlCanvasElement,
+    state: Rc<RefCell<State>>,
+    frame_loop: FrameLoop,
+    gestures: Rc<RefCell<Gestures>>,
+}
+
+impl EventListeners {
+    pub fn new(
+        canvas: HtmlCanvasElement,
+        state: Rc<RefCell<State>>,
+        frame_loop: FrameLoop,
+        gestures: Rc<RefCell<Gestures>>,
+    ) -> Self {
+        // Key events are only sent to focusable elements
+        if canvas.tab_index() < 0 {
+            canvas.set_tab_index(0);
+        }
+
+        let context = Rc::new(Context {
+            canvas: canvas.clone(),
+            state,
+            frame_loop,
+            gestures,
+        });
+
+        let mut listeners = Self {
+            canvas,
+            listeners: Vec::new(),
+        };
+
+        listeners.listen(&context, "pointerdown", |context, event| {
+            let event: &PointerEvent = event.unchecked_ref();
+            let input = context.pointer_input(event);
+            // Gestures apply to the viewport they started in
+            if context.gestures.borrow().is_idle() {
+                context.state.borrow_mut().focus_viewport_at(input.position);
+            }
+            if context.gestures.borrow_mut().pointer_down(input) {
+                // Keep receiving moves when the pointer leaves the canvas mid drag
+                let _ = context.canvas.set_pointer_capture(input.id);
+                let _ = context.canvas.focus();
+                event.prevent_default();
+            }
+        });
+
+        listeners.listen(&context, "pointermove", |context, event| {
+            let input = context.pointer_input(event.unchecked_ref());
+            let command = context.gestures.borrow_mut().pointer_move(input);
+            if let Some(command) = command {
+                context.apply(command);
+            }
+        });
+
+        for name in ["pointerup", "pointercancel"] {
+            listeners.listen(&context, name, |context, event| {
+                let event: &PointerEvent = event.unchecked_ref();
+                context.gestures.borrow_mut().pointer_up(event.pointer_id());
+            });
+        }
+
+        listeners.listen(&context, "wheel", |context, event| {
+            let event: &WheelEvent = event.unchecked_ref();
+            let delta_y = match event.delta_mode() {
+                WheelEvent::DOM_DELTA_LINE => event.delta_y() * Gestures::LINE_HEIGHT as f64,
+                WheelEvent::DOM_DELTA_PAGE => {
+                    event.delta_y() * context.canvas.client_height() as f64
+                }
+                _ => event.delta_y(),
+            };
+            let command = context.gestures.borrow().wheel(delta_y as f32);
+            if let Some(command) = command {
+                event.prevent_default();
+                let position = context.to_pixels(event.offset_x(), event.offset_y());
+                context.state.borrow_mut().focus_viewport_at(position);
+                context.apply(command);
+            }
+        });
+
+        listeners.listen(&context, "keydown", |context, event| {
+            let event: &KeyboardEvent = event.unchecked_ref();
+            // Leave browser shortcuts alone
+            if event.ctrl_key() || event.meta_key() || event.alt_key() || event.repeat() {
+                return;
+            }
+            let command = context.gestures.borrow().key(&event.key());
+            if let Some(command) = command {
+                event.prevent_default();
+                context.apply(command);
+            }
+        });
+
+        // The secondary button pans by default
+        listeners.listen(&context, "contextmenu", |_, event| event.prevent_default());
+
+        listeners
+    }
+
+    fn listen(
+        &mut self,
+        context: &Rc<Context>,
+        name: &'static str,
+        handler: impl Fn(&Context, &web_sys::Event) + 'static,
+    ) {
+        let context = context.clone();
+        let callback = EventCallback::new(move |event: web_sys::Event| handler(&context, &event));
+
+        // Passive listeners cannot prevent scrolling and touch gestures
+        let options = AddEventListenerOptions::new();
+        options.set_passive(false);
+        if self
+            .canvas
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                name,
+                callback.as_ref().unchecked_ref(),
+                &options,
+            )
+            .is_err()
+        {
+            log::warn!("Could not listen to {name} events");
+            return;
+        }
+
+        self.listeners.push((name, callback));
+    }
+}
+
+impl Drop for EventListeners {
+    fn drop(&mut self) {
+        for (name, callback) in &self.listeners {
+            let _ = self
+                .canvas
+                .remove_event_listener_with_callback(name, callback.as_ref().unchecked_ref());
+        }
+    }
+}
+
+impl Context {
+    fn pointer_input(&self, event: &PointerEvent) -> PointerInput {
+        let kind = match event.pointer_type().as_str() {
+            "touch" => PointerKind::Touch,
+            "pen" => PointerKind::Pen,
+            _ => PointerKind::Mouse,
+        };
+
+        PointerInput {
+            id: event.pointer_id(),
+            kind,
+            button: event.button().into(),
+            position: self.to_pixels(event.offset_x(), event.offset_y()),
+            shift: event.shift_key(),
+        }
+    }
+
+    /// Offsets are in CSS pixels, the render target may be scaled by the device pixel ratio
+    fn to_pixels(&self, offset_x: i32, offset_y: i32) -> Vec2 {
+        let client = Vec2::new(
+            self.canvas.client_width() as f32,
+            self.canvas.client_height() as f32,
+        );
+        let scale = Vec2::new(self.canvas.width() as f32, self.canvas.height() as f32)
+            / client.max(Vec2::ONE);
+        Vec2::new(offset_x as f32, offset_y as f32) * scale
+    }
+
+    fn apply(&self, command: Command) {
+        let mut state = self.state.borrow_mut();
+        if command == Command::Action(Action::SaveState) {
+            Self::save_state(&state);
+        } else if let Err(err) = state.apply_command(command) {
+            log::warn!("{err}");
+        }
+
+        drop(state);
+        self.frame_loop.request();
+    }
+
+    /// Store a bookmark of the current view in the page URL
+    fn save_state(state: &State) {
+        let url = format!("#{}", state.bookmark());
+        let saved = web_sys::window()
+            .and_then(|window| window.history().ok())
+            .and_then(|history| {
+                history
+                    .replace_state_with_url(&JsValue::NULL, "", Some(&url))
+                    .ok()
+            });
+        if saved.is_none() {
+            log::warn!("Could not save the view in the page URL");
+        }
+    }
+}
@@ -0,0 +1,610 @@
+//! Files for taking the surface out of the viewer
+//!
+//! Meshes go to CAD tools, 3D printing and other viewers, sampled values to analysis tools.
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt::Write, str::FromStr};
+
+use glam::Vec3;
+
+use crate::meshgrid::VERTEX_ELEMENTS;
+
+const NAME: &str = "wasmgpu";
+
+/// File formats a mesh can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// Wavefront OBJ, with vertex colours after the positions
+    Obj,
+    StlBinary,
+    StlAscii,
+    /// Binary PLY with 8 bit vertex colours
+    Ply,
+    /// Binary glTF 2.0
+    Glb,
+}
+
+impl MeshFormat {
+    /// Usual file name extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Obj => "obj",
+            Self::StlBinary | Self::StlAscii => "stl",
+            Self::Ply => "ply",
+            Self::Glb => "glb",
+        }
+    }
+}
+
+impl FromStr for MeshFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "obj" => Ok(Self::Obj),
+            "stl" => Ok(Self::StlBinary),
+            "stl-ascii" => Ok(Self::StlAscii),
+            "ply" => Ok(Self::Ply),
+            "glb" => Ok(Self::Glb),
+            _ => Err(()),
+        }
+    }
+}
+
+/// File formats the sampled values of a grid can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridFormat {
+    /// `x,y,z` per sample
+    CsvLong,
+    /// Heights with a row of Y coordinates above and a column of X coordinates on the left
+    CsvMatrix,
+    /// NumPy array of the heights with shape `(rows, cols)`
+    Npy,
+    /// Heights as little-endian `f32`, row by row
+    RawF32,
+}
+
+impl GridFormat {
+    /// Usual file name extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::CsvLong | Self::CsvMatrix => "csv",
+            Self::Npy => "npy",
+            Self::RawF32 => "f32",
+        }
+    }
+}
+
+impl FromStr for GridFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" | "csv-long" => Ok(Self::CsvLong),
+            "csv-matrix" => Ok(Self::CsvMatrix),
+            "npy" => Ok(Self::Npy),
+            "f32" | "raw" => Ok(Self::RawF32),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Sampled heights read back from `GridBuffers`
+///
+/// Rows advance along X and columns along Y, matching gen_vertex.wgsl.
+pub struct GridValues {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    /// Row-major
+    heights: Vec<f32>,
+}
+
+impl GridValues {
+    /// Pick the coordinates and heights out of the raw contents of a `GridBuffers::vertex_buffer`
+    pub fn from_vertices((rows, cols): (u32, u32), vertices: &[f32]) -> Self {
+        let vertex = |r: u32, c: u32| {
+            let start = (r * cols + c) as usize * VERTEX_ELEMENTS;
+            &vertices[start..start + 3]
+        };
+        Self {
+            xs: (0..rows).map(|r| vertex(r, 0)[0]).collect(),
+            ys: (0..cols).map(|c| vertex(0, c)[1]).collect(),
+            heights: vertices
+                .chunks_exact(VERTEX_ELEMENTS)
+                .map(|vertex| vertex[2])
+                .collect(),
+        }
+    }
+
+    pub fn encode(&self, format: GridFormat) -> Vec<u8> {
+        match format {
+            GridFormat::CsvLong => self.to_csv_long().into_bytes(),
+            GridFormat::CsvMatrix => self.to_csv_matrix().into_bytes(),
+            GridFormat::Npy => self.to_npy(),
+            GridFormat::RawF32 => self.to_raw_f32(),
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = (f32, &[f32])> + '_ {
+        self.xs
+            .iter()
+            .copied()
+            .zip(self.heights.chunks_exact(self.ys.len().max(1)))
+    }
+
+    fn to_csv_long(&self) -> String {
+        let mut csv = String::from("x,y,z\n");
+        for (x, heights) in self.rows() {
+            for (y, z) in self.ys.iter().zip(heights) {
+                let _ = writeln!(csv, "{x},{y},{z}");
+            }
+        }
+        csv
+    }
+
+    fn to_csv_matrix(&self) -> String {
+        let mut csv = String::new();
+        for y in &self.ys {
+            let _ = write!(csv, ",{y}");
+        }
+        csv.push('\n');
+        for (x, heights) in self.rows() {
+            let _ = write!(csv, "{x}");
+            for z in heights {
+                let _ = write!(csv, ",{z}");
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    fn to_npy(&self) -> Vec<u8> {
+        const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+        let mut header = String::new();
+        let _ = write!(
+            header,
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.xs.len(),
+            self.ys.len()
+        );
+        // The data starts 64 byte aligned, the header is padded with spaces and ends in a newline
+        let unpadded = MAGIC.len() + 2 + header.len() + 1;
+        let padding = unpadded.next_multiple_of(64) - unpadded;
+        header.extend(core::iter::repeat_n(' ', padding));
+        header.push('\n');
+
+        let mut npy = Vec::with_capacity(MAGIC.len() + 2 + header.len() + self.heights.len() * 4);
+        npy.extend_from_slice(MAGIC);
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        npy.extend(self.to_raw_f32());
+        npy
+    }
+
+    fn to_raw_f32(&self) -> Vec<u8> {
+        self.heights.iter().flat_map(|z| z.to_le_bytes()).collect()
+    }
+}
+
+/// Triangle mesh read back from `GridBuffers`
+pub struct Mesh {
+    /// Interleaved positions and colours, `VERTEX_ELEMENTS` floats per vertex
+    vertices: Vec<f32>,
+    /// Triangle list
+    indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<f32>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / VERTEX_ELEMENTS
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn encode(&self, format: MeshFormat) -> Vec<u8> {
+        match format {
+            MeshFormat::Obj => self.to_obj().into_bytes(),
+            MeshFormat::StlBinary => self.to_stl_binary(),
+            MeshFormat::StlAscii => self.to_stl_ascii().into_bytes(),
+            MeshFormat::Ply => self.to_ply(),
+            MeshFormat::Glb => self.to_glb(),
+        }
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.vertices
+            .chunks_exact(VERTEX_ELEMENTS)
+            .map(|vertex| Vec3::from_slice(&vertex[0..3]))
+    }
+
+    /// Colours as written to the framebuffer, which displays them as sRGB
+    fn colors(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.vertices
+            .chunks_exact(VERTEX_ELEMENTS)
+            .map(|vertex| Vec3::from_slice(&vertex[3..6]))
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        let position = |index: u32| {
+            let start = index as usize * VERTEX_ELEMENTS;
+            Vec3::from_slice(&self.vertices[start..start + 3])
+        };
+        self.indices
+            .chunks_exact(3)
+            .map(move |triangle| [0, 1, 2].map(|i| position(triangle[i])))
+    }
+
+    fn to_obj(&self) -> String {
+        let mut obj = String::new();
+        let _ = writeln!(obj, "# {NAME} surface");
+        for (position, color) in self.positions().zip(self.colors()) {
+            let _ = writeln!(
+                obj,
+                "v {} {} {} {} {} {}",
+                position.x, position.y, position.z, color.x, color.y, color.z
+            );
+        }
+        // Indices start at one
+        for triangle in self.indices.chunks_exact(3) {
+            let _ = writeln!(
+                obj,
+                "f {} {} {}",
+                triangle[0] + 1,
+                triangle[1] + 1,
+                triangle[2] + 1
+            );
+        }
+        obj
+    }
+
+    fn to_stl_binary(&self) -> Vec<u8> {
+        let mut stl = Vec::with_capacity(84 + self.triangle_count() * 50);
+
+        let mut header = [b' '; 80];
+        header[..NAME.len()].copy_from_slice(NAME.as_bytes());
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(self.triangle_count() as u32).to_le_bytes());
+
+        for triangle in self.triangles() {
+            for v in [facet_normal(triangle)].iter().chain(&triangle) {
+                stl.extend_from_slice(bytemuck::bytes_of(&v.to_array()));
+            }
+            // Attribute byte count, unused
+            stl.extend_from_slice(&[0, 0]);
+        }
+        stl
+    }
+
+    fn to_stl_ascii(&self) -> String {
+        let mut stl = String::new();
+        let _ = writeln!(stl, "solid {NAME}");
+        for triangle in self.triangles() {
+            let normal = facet_normal(triangle);
+            let _ = writeln!(stl, "facet normal {} {} {}", normal.x, normal.y, normal.z);
+            let _ = writeln!(stl, "  outer loop");
+            for v in triangle {
+                let _ = writeln!(stl, "    vertex {} {} {}", v.x, v.y, v.z);
+            }
+            let _ = writeln!(stl, "  endloop");
+            let _ = writeln!(stl, "endfacet");
+        }
+        let _ = writeln!(stl, "endsolid {NAME}");
+        stl
+    }
+
+    fn to_ply(&self) -> Vec<u8> {
+        let mut header = String::new();
+        let _ = write!(
+            header,
+            "ply\n\
+             format binary_little_endian 1.0\n\
+             comment {NAME} surface\n\
+             element vertex {}\n\
+             property float x\n\
+             property float y\n\
+             property float z\n\
+             property uchar red\n\
+             property uchar green\n\
+             property uchar blue\n\
+             element face {}\n\
+             property list uchar uint vertex_indices\n\
+             end_header\n",
+            self.vertex_count(),
+            self.triangle_count(),
+        );
+
+        let mut ply = header.into_bytes();
+        ply.reserve(self.vertex_count() * 15 + self.triangle_count() * 13);
+        for (position, color) in self.positions().zip(self.colors()) {
+            ply.extend_from_slice(bytemuck::bytes_of(&position.to_array()));
+            ply.extend(
+                color
+                    .to_array()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8),
+            );
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            ply.push(3);
+            ply.extend_from_slice(bytemuck::cast_slice(triangle));
+        }
+        ply
+    }
+
+    fn to_glb(&self) -> Vec<u8> {
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const TRIANGLES: u32 = 4;
+
+        let vertex_count = self.vertex_count();
+        // Non-finite heights would write inf or NaN into the JSON, so they are left out
+        let (min, max) = self
+            .positions()
+            .filter(|position| position.is_finite())
+            .fold(None, |bounds: Option<(Vec3, Vec3)>, position| {
+                Some(bounds.map_or((position, position), |(min, max)| {
+                    (min.min(position), max.max(position))
+                }))
+            })
+            .unwrap_or_default();
+
+        // Positions, then colours, then indices, all 4 byte aligned
+        let mut bin = Vec::with_capacity(vertex_count * 24 + self.indices.len() * 4);
+        for position in self.positions() {
+            bin.extend_from_slice(bytemuck::bytes_of(&position.to_array()));
+        }
+        // glTF vertex colours are linear
+        for color in self.colors() {
+            bin.extend_from_slice(bytemuck::bytes_of(&color.to_array().map(srgb_to_linear)));
+        }
+        bin.extend_from_slice(bytemuck::cast_slice(&self.indices));
+        let attribute_bytes = vertex_count * 12;
+        let index_bytes = self.indices.len() * 4;
+
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            concat!(
+                r#"{{"asset":{{"version":"2.0","generator":"{name}"}},"#,
+                r#""scene":0,"scenes":[{{"nodes":[0]}}],"#,
+                // The surface is Z up, glTF is Y up
+                r#""nodes":[{{"mesh":0,"rotation":[-0.70710677,0,0,0.70710677]}}],"#,
+                r#""meshes":[{{"name":"{name}","primitives":[{{"#,
+                r#""attributes":{{"POSITION":0,"COLOR_0":1}},"indices":2,"mode":{triangles}}}]}}],"#,
+                r#""buffers":[{{"byteLength":{buffer_length}}}],"#,
+                r#""bufferViews":["#,
+                r#"{{"buffer":0,"byteOffset":0,"byteLength":{attribute_bytes},"target":{array_buffer}}},"#,
+                r#"{{"buffer":0,"byteOffset":{attribute_bytes},"byteLength":{attribute_bytes},"target":{array_buffer}}},"#,
+                r#"{{"buffer":0,"byteOffset":{index_offset},"byteLength":{index_bytes},"target":{element_array_buffer}}}],"#,
+                r#""accessors":["#,
+                r#"{{"bufferView":0,"componentType":{float},"count":{vertex_count},"type":"VEC3","min":[{min_x},{min_y},{min_z}],"max":[{max_x},{max_y},{max_z}]}},"#,
+                r#"{{"bufferView":1,"componentType":{float},"count":{vertex_count},"type":"VEC3"}},"#,
+                r#"{{"bufferView":2,"componentType":{unsigned_int},"count":{index_count},"type":"SCALAR"}}]}}"#,
+            ),
+            name = NAME,
+            triangles = TRIANGLES,
+            buffer_length = bin.len(),
+            attribute_bytes = attribute_bytes,
+            index_offset = 2 * attribute_bytes,
+            index_bytes = index_bytes,
+            array_buffer = ARRAY_BUFFER,
+            element_array_buffer = ELEMENT_ARRAY_BUFFER,
+            float = FLOAT,
+            unsigned_int = UNSIGNED_INT,
+            vertex_count = vertex_count,
+            index_count = self.indices.len(),
+            min_x = min.x,
+            min_y = min.y,
+            min_z = min.z,
+            max_x = max.x,
+            max_y = max.y,
+            max_z = max.z,
+        );
+
+        // Chunks are padded to 4 bytes, JSON with spaces and binary data with zeros
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut glb = Vec::with_capacity(length);
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(length as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+        glb
+    }
+}
+
+/// Unit normal following the winding of the triangle, zero for degenerate ones
+fn facet_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a).normalize_or_zero()
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        libm::powf((c + 0.055) / 1.055, 2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    /// A unit square in the XY plane split into two triangles
+    fn square() -> Mesh {
+        let vertices = vec![
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, 1.0, 1.0, 1.0, //
+            1.0, 0.0, 0.0, 1.0, 0.0, 0.0, //
+            1.0, 1.0, 1.0, 0.0, 1.0, 0.0, //
+        ];
+        Mesh::new(vertices, vec![0, 1, 2, 2, 1, 3])
+    }
+
+    /// Two rows at x = 0 and 1, three columns at y = 0, 0.5 and 1, with z = x + y
+    fn grid() -> GridValues {
+        let mut vertices = Vec::new();
+        for x in [0.0, 1.0] {
+            for y in [0.0, 0.5, 1.0] {
+                vertices.extend_from_slice(&[x, y, x + y, 0.0, 0.0, 0.0]);
+            }
+        }
+        GridValues::from_vertices((2, 3), &vertices)
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("stl".parse(), Ok(MeshFormat::StlBinary));
+        assert_eq!("stl-ascii".parse(), Ok(MeshFormat::StlAscii));
+        assert_eq!(
+            "glb".parse::<MeshFormat>().map(MeshFormat::extension),
+            Ok("glb")
+        );
+        assert_eq!("fbx".parse::<MeshFormat>(), Err(()));
+    }
+
+    #[test]
+    fn parses_grid_formats() {
+        assert_eq!("csv".parse(), Ok(GridFormat::CsvLong));
+        assert_eq!("csv-matrix".parse(), Ok(GridFormat::CsvMatrix));
+        assert_eq!(
+            "npy".parse::<GridFormat>().map(GridFormat::extension),
+            Ok("npy")
+        );
+        assert_eq!("raw".parse(), Ok(GridFormat::RawF32));
+        assert_eq!("mat".parse::<GridFormat>(), Err(()));
+    }
+
+    #[test]
+    fn csv_long_has_a_line_per_sample() {
+        let csv = grid().to_csv_long();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 1 + 6);
+        assert_eq!(lines[0], "x,y,z");
+        assert_eq!(lines[2], "0,0.5,0.5");
+        assert_eq!(lines[6], "1,1,2");
+    }
+
+    #[test]
+    fn csv_matrix_labels_rows_and_columns() {
+        assert_eq!(grid().to_csv_matrix(), ",0,0.5,1\n0,0,0.5,1\n1,1,1.5,2\n");
+    }
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let npy = grid().to_npy();
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = core::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!((10 + header_len) % 64, 0);
+        assert!(header.contains("'shape': (2, 3)"));
+        assert!(header.ends_with('\n'));
+
+        let data: Vec<f32> = npy[10 + header_len..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(data, [0.0, 0.5, 1.0, 1.0, 1.5, 2.0]);
+    }
+
+    #[test]
+    fn obj_indices_start_at_one() {
+        let obj = square().to_obj();
+        let lines: Vec<&str> = obj.lines().collect();
+
+        assert_eq!(lines.len(), 1 + 4 + 2);
+        assert_eq!(lines[2], "v 0 1 0 1 1 1");
+        assert_eq!(lines[5], "f 1 2 3");
+        assert_eq!(lines[6], "f 3 2 4");
+    }
+
+    #[test]
+    fn stl_binary_has_a_record_per_triangle() {
+        let stl = square().to_stl_binary();
+
+        assert_eq!(stl.len(), 84 + 2 * 50);
+        assert_eq!(&stl[80..84], &2u32.to_le_bytes());
+        // Counter-clockwise seen from below, so the normal points down
+        let normal: &[f32] = bytemuck::cast_slice(&stl[84..96]);
+        assert_eq!(normal, &[0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn stl_ascii_closes_every_facet() {
+        let stl = square().to_stl_ascii();
+
+        assert!(stl.starts_with("solid wasmgpu\nfacet normal 0 0 -1\n"));
+        assert_eq!(stl.matches("endfacet").count(), 2);
+        assert!(stl.ends_with("endsolid wasmgpu\n"));
+    }
+
+    #[test]
+    fn ply_header_counts_elements() {
+        let ply = square().to_ply();
+        let end = b"end_header\n";
+        let header_len = ply.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = core::str::from_utf8(&ply[..header_len]).unwrap();
+
+        assert!(header.contains("element vertex 4\n"));
+        assert!(header.contains("element face 2\n"));
+        assert_eq!(ply.len(), header_len + 4 * 15 + 2 * 13);
+        // Second vertex is white
+        assert_eq!(
+            &ply[header_len + 15 + 12..header_len + 30],
+            &[255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned() {
+        let glb = square().to_glb();
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(&glb[8..12], &(glb.len() as u32).to_le_bytes());
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(json_len % 4, 0);
+        assert_eq!(&glb[16..20], b"JSON");
+
+        let json = core::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""min":[0,0,0],"max":[1,1,1]"#));
+
+        let bin = 20 + json_len;
+        let bin_len = u32::from_le_bytes(glb[bin..bin + 4].try_into().unwrap()) as usize;
+        assert_eq!(&glb[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!(bin_len, 4 * 12 * 2 + 6 * 4);
+        assert_eq!(glb.len(), bin + 8 + bin_len);
+    }
+
+    #[test]
+    fn glb_bounds_skip_non_finite_heights() {
+        let mut vertices = square().vertices;
+        vertices[3 * VERTEX_ELEMENTS + 2] = f32::NAN;
+        vertices[2 * VERTEX_ELEMENTS + 2] = f32::INFINITY;
+        let glb = Mesh::new(vertices, vec![0, 1, 2, 2, 1, 3]).to_glb();
+
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let json = core::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""min":[0,0,0],"max":[0,1,0]"#), "{json}");
+        assert!(!json.contains("NaN") && !json.contains("inf"));
+    }
+}
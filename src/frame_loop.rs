@@ -0,0 +1,98 @@
+use alloc::rc::{Rc, Weak};
+use core::cell::{Cell, RefCell};
+
+use wasm_bindgen::prelude::*;
+
+use crate::State;
+
+type FrameCallback = Closure<dyn FnMut(f64)>;
+
+/// Drives `State::update` and `State::render` from `requestAnimationFrame`
+///
+/// Frames are only requested while something is animating, so an idle plot costs nothing.
+#[derive(Clone)]
+pub struct FrameLoop {
+    inner: Rc<FrameLoopInner>,
+}
+
+struct FrameLoopInner {
+    state: Rc<RefCell<State>>,
+    callback: RefCell<Option<FrameCallback>>,
+    pending: Cell<bool>,
+    last_timestamp: Cell<Option<f64>>,
+}
+
+impl FrameLoop {
+    /// Longest step fed to `State::update` so a throttled background tab does not jump
+    const MAX_STEP_SECONDS: f64 = 0.1;
+
+    pub fn new(state: Rc<RefCell<State>>) -> Self {
+        let inner = Rc::new(FrameLoopInner {
+            state,
+            callback: RefCell::new(None),
+            pending: Cell::new(false),
+            last_timestamp: Cell::new(None),
+        });
+
+        // The closure only holds a weak reference so dropping the loop stops it
+        let weak: Weak<FrameLoopInner> = Rc::downgrade(&inner);
+        let callback = FrameCallback::new(move |timestamp: f64| {
+            if let Some(inner) = weak.upgrade() {
+                inner.frame(timestamp);
+            }
+        });
+        inner.callback.replace(Some(callback));
+
+        Self { inner }
+    }
+
+    /// Schedule a frame if one is not already pending
+    pub fn request(&self) {
+        self.inner.request();
+    }
+}
+
+impl FrameLoopInner {
+    fn request(&self) {
+        if self.pending.get() {
+            return;
+        }
+
+        let callback = self.callback.borrow();
+        let Some(callback) = callback.as_ref() else {
+            return;
+        };
+
+        let requested = web_sys::window().and_then(|window| {
+            window
+                .request_animation_frame(callback.as_ref().unchecked_ref())
+                .ok()
+        });
+        self.pending.set(requested.is_some());
+    }
+
+    fn frame(&self, timestamp: f64) {
+        self.pending.set(false);
+
+        let dt = match self.last_timestamp.get() {
+            Some(last) => ((timestamp - last) * 0.001).clamp(0.0, FrameLoop::MAX_STEP_SECONDS),
+            None => 0.0,
+        };
+
+        let animating = {
+            let mut state = self.state.borrow_mut();
+            let animating = state.update(dt as f32);
+            if let Err(err) = state.render() {
+                log::error!("{err}");
+            }
+            animating
+        };
+
+        if animating {
+            self.last_timestamp.set(Some(timestamp));
+            self.request();
+        } else {
+            self.last_timestamp.set(None);
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! Gesture recognition and key bindings, independent of where events come from
+//!
+//! `Gestures` turns pointer, wheel and key input into `Command`s. Positions are in pixels of the
+//! render target with the origin at the top left, matching `State::pick`.
+
+use alloc::{string::String, vec::Vec};
+use core::str::FromStr;
+
+use glam::Vec2;
+
+use crate::camera::View;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PointerKind {
+    Mouse,
+    Pen,
+    Touch,
+}
+
+/// Buttons as numbered by `MouseEvent.button`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+    Primary,
+    Middle,
+    Secondary,
+    Other,
+}
+
+impl From<i16> for Button {
+    fn from(button: i16) -> Self {
+        match button {
+            0 => Self::Primary,
+            1 => Self::Middle,
+            2 => Self::Secondary,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PointerInput {
+    pub id: i32,
+    pub kind: PointerKind,
+    pub button: Button,
+    pub position: Vec2,
+    pub shift: bool,
+}
+
+/// What dragging a pointer does to the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DragAction {
+    Rotate,
+    Pan,
+    None,
+}
+
+impl FromStr for DragAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rotate" => Ok(Self::Rotate),
+            "pan" => Ok(Self::Pan),
+            "none" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Commands that can be bound to keys
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    ResetCamera,
+    FitToSurface,
+    SetView(View),
+    ToggleProjection,
+    ToggleCameraMode,
+    ToggleColorbar,
+    ToggleMultisampling,
+    /// Store a bookmark of the current view in the page URL
+    SaveState,
+    /// Set both grid dimensions
+    SetResolution(u32),
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    /// Parse names such as `"fit-to-surface"`, `"view-top"` or `"resolution-255"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(view) = s.strip_prefix("view-") {
+            return view.parse().map(Self::SetView);
+        }
+        if let Some(resolution) = s.strip_prefix("resolution-") {
+            return resolution.parse().map(Self::SetResolution).map_err(|_| ());
+        }
+
+        match s {
+            "reset-camera" => Ok(Self::ResetCamera),
+            "fit-to-surface" => Ok(Self::FitToSurface),
+            "toggle-projection" => Ok(Self::ToggleProjection),
+            "toggle-camera-mode" => Ok(Self::ToggleCameraMode),
+            "toggle-colorbar" => Ok(Self::ToggleColorbar),
+            "toggle-multisampling" => Ok(Self::ToggleMultisampling),
+            "save-state" => Ok(Self::SaveState),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Drag rotation between two positions, see `State::drag_camera`
+    Rotate {
+        from: Vec2,
+        to: Vec2,
+    },
+    /// Move the scene by a pixel offset
+    Pan(Vec2),
+    /// Dolly as passed to `Camera::move_distance`
+    Dolly(f32),
+    /// Two finger gesture, dollying with the spread and panning with the midpoint
+    Pinch {
+        dolly: f32,
+        pan: Vec2,
+    },
+    Action(Action),
+}
+
+/// Which drags, keys and scroll speeds map to which commands
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    pub primary: DragAction,
+    pub middle: DragAction,
+    pub secondary: DragAction,
+    /// Replaces the primary button's action while shift is held
+    pub shift_primary: DragAction,
+    /// Dolly per pixel scrolled
+    pub wheel_speed: f32,
+    keys: Vec<(String, Action)>,
+}
+
+impl Bindings {
+    pub const DEFAULT_WHEEL_SPEED: f32 = 0.001;
+
+    /// Keys '1' to '9' pick resolutions from 15 to 4095 doubling each step
+    const RESOLUTION_KEYS: &str = "123456789";
+
+    pub fn drag_action(&self, button: Button, shift: bool) -> DragAction {
+        match button {
+            Button::Primary if shift => self.shift_primary,
+            Button::Primary => self.primary,
+            Button::Middle => self.middle,
+            Button::Secondary => self.secondary,
+            Button::Other => DragAction::None,
+        }
+    }
+
+    /// Bind a key, named as in `KeyboardEvent.key`, replacing any existing binding
+    pub fn bind_key(&mut self, key: &str, action: Action) {
+        self.unbind_key(key);
+        self.keys.push((key.into(), action));
+    }
+
+    pub fn unbind_key(&mut self, key: &str) {
+        self.keys.retain(|(bound, _)| bound != key);
+    }
+
+    pub fn key_action(&self, key: &str) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(bound, _)| bound == key)
+            .map(|(_, action)| *action)
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self {
+            primary: DragAction::Rotate,
+            middle: DragAction::Pan,
+            secondary: DragAction::Pan,
+            shift_primary: DragAction::Pan,
+            wheel_speed: Self::DEFAULT_WHEEL_SPEED,
+            keys: Vec::new(),
+        };
+
+        let mut buf = [0; 4];
+        for (i, key) in Self::RESOLUTION_KEYS.chars().enumerate() {
+            let resolution = (16 << i) - 1;
+            bindings.bind_key(key.encode_utf8(&mut buf), Action::SetResolution(resolution));
+        }
+        bindings.bind_key("a", Action::ToggleMultisampling);
+        bindings.bind_key("b", Action::ToggleCameraMode);
+        bindings.bind_key("c", Action::ToggleColorbar);
+        bindings.bind_key("f", Action::FitToSurface);
+        bindings.bind_key("o", Action::ToggleProjection);
+        bindings.bind_key("r", Action::ResetCamera);
+        bindings.bind_key("s", Action::SaveState);
+
+        bindings
+    }
+}
+
+struct Pointer {
+    id: i32,
+    kind: PointerKind,
+    position: Vec2,
+    drag: DragAction,
+}
+
+/// Tracks pressed pointers and recognizes drags and two finger pinches
+///
+/// A mouse or pen drag does whatever its button is bound to. One finger rotates, two fingers
+/// pinch to dolly and move together to pan, further fingers are ignored.
+#[derive(Default)]
+pub struct Gestures {
+    pub bindings: Bindings,
+    pointers: Vec<Pointer>,
+}
+
+impl Gestures {
+    /// Pixels per line for wheels that scroll by lines
+    pub const LINE_HEIGHT: f32 = 16.0;
+
+    const MAX_TOUCHES: usize = 2;
+
+    /// Start tracking a pointer, returning whether it takes part in a gesture
+    pub fn pointer_down(&mut self, input: PointerInput) -> bool {
+        self.pointer_up(input.id);
+
+        let drag = match input.kind {
+            PointerKind::Touch => {
+                if self.touches().count() >= Self::MAX_TOUCHES {
+                    return false;
+                }
+                DragAction::Rotate
+            }
+            PointerKind::Mouse | PointerKind::Pen => {
+                self.bindings.drag_action(input.button, input.shift)
+            }
+        };
+        if drag == DragAction::None {
+            return false;
+        }
+
+        self.pointers.push(Pointer {
+            id: input.id,
+            kind: input.kind,
+            position: input.position,
+            drag,
+        });
+        true
+    }
+
+    /// Move a tracked pointer, ignoring pointers that are not pressed
+    pub fn pointer_move(&mut self, input: PointerInput) -> Option<Command> {
+        let index = self.pointers.iter().position(|p| p.id == input.id)?;
+        let from = self.pointers[index].position;
+        let to = input.position;
+        self.pointers[index].position = to;
+
+        if self.pointers[index].kind == PointerKind::Touch {
+            let other = self
+                .touches()
+                .find(|p| p.id != input.id)
+                .map(|p| p.position);
+            if let Some(other) = other {
+                return Some(Self::pinch(other, from, to));
+            }
+        }
+
+        match self.pointers[index].drag {
+            DragAction::Rotate => Some(Command::Rotate { from, to }),
+            DragAction::Pan => Some(Command::Pan(to - from)),
+            DragAction::None => None,
+        }
+    }
+
+    /// Whether no pointer is pressed, so the next one starts a new gesture
+    pub fn is_idle(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// Stop tracking a released or cancelled pointer
+    pub fn pointer_up(&mut self, id: i32) {
+        self.pointers.retain(|p| p.id != id);
+    }
+
+    /// Convert a vertical scroll in pixels, positive when scrolling down
+    pub fn wheel(&self, delta_y: f32) -> Option<Command> {
+        (delta_y != 0.0).then(|| Command::Dolly(-delta_y * self.bindings.wheel_speed))
+    }
+
+    pub fn key(&self, key: &str) -> Option<Command> {
+        self.bindings.key_action(key).map(Command::Action)
+    }
+
+    fn touches(&self) -> impl Iterator<Item = &Pointer> {
+        self.pointers
+            .iter()
+            .filter(|p| p.kind == PointerKind::Touch)
+    }
+
+    /// One finger of a pinch moved from `from` to `to` while the other stayed at `other`
+    fn pinch(other: Vec2, from: Vec2, to: Vec2) -> Command {
+        let spread_before = other.distance(from);
+        let spread_after = other.distance(to);
+
+        // Scale the distance with the spread so the fingers stay over the same points
+        let dolly = if spread_before > f32::EPSILON && spread_after > f32::EPSILON {
+            1.0 - spread_before / spread_after
+        } else {
+            0.0
+        };
+        Command::Pinch {
+            dolly,
+            pan: (to - from) * 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    fn mouse(id: i32, button: i16, x: f32, y: f32) -> PointerInput {
+        PointerInput {
+            id,
+            kind: PointerKind::Mouse,
+            button: button.into(),
+            position: Vec2::new(x, y),
+            shift: false,
+        }
+    }
+
+    fn touch(id: i32, x: f32, y: f32) -> PointerInput {
+        PointerInput {
+            kind: PointerKind::Touch,
+            ..mouse(id, 0, x, y)
+        }
+    }
+
+    #[test]
+    fn primary_drag_rotates() {
+        let mut gestures = Gestures::default();
+        assert!(gestures.pointer_down(mouse(1, 0, 10.0, 10.0)));
+        assert_eq!(
+            gestures.pointer_move(mouse(1, 0, 15.0, 12.0)),
+            Some(Command::Rotate {
+                from: Vec2::new(10.0, 10.0),
+                to: Vec2::new(15.0, 12.0)
+            })
+        );
+    }
+
+    #[test]
+    fn secondary_and_shift_drag_pan() {
+        let mut gestures = Gestures::default();
+        gestures.pointer_down(mouse(1, 2, 0.0, 0.0));
+        assert_eq!(
+            gestures.pointer_move(mouse(1, 2, 3.0, 4.0)),
+            Some(Command::Pan(Vec2::new(3.0, 4.0)))
+        );
+        gestures.pointer_up(1);
+
+        gestures.pointer_down(PointerInput {
+            shift: true,
+            ..mouse(2, 0, 0.0, 0.0)
+        });
+        assert_eq!(
+            gestures.pointer_move(mouse(2, 0, -1.0, 0.0)),
+            Some(Command::Pan(Vec2::new(-1.0, 0.0)))
+        );
+    }
+
+    #[test]
+    fn hover_and_released_pointers_are_ignored() {
+        let mut gestures = Gestures::default();
+        assert_eq!(gestures.pointer_move(mouse(1, 0, 1.0, 1.0)), None);
+
+        gestures.pointer_down(mouse(1, 0, 0.0, 0.0));
+        gestures.pointer_up(1);
+        assert_eq!(gestures.pointer_move(mouse(1, 0, 1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn unbound_button_does_not_track() {
+        let mut gestures = Gestures::default();
+        gestures.bindings.middle = DragAction::None;
+        assert!(!gestures.pointer_down(mouse(1, 1, 0.0, 0.0)));
+        assert_eq!(gestures.pointer_move(mouse(1, 1, 1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn pinch_apart_dollies_in() {
+        let mut gestures = Gestures::default();
+        gestures.pointer_down(touch(1, 0.0, 0.0));
+        gestures.pointer_down(touch(2, 100.0, 0.0));
+
+        // Doubling the spread halves the distance
+        assert_eq!(
+            gestures.pointer_move(touch(2, 200.0, 0.0)),
+            Some(Command::Pinch {
+                dolly: 0.5,
+                pan: Vec2::new(50.0, 0.0)
+            })
+        );
+    }
+
+    #[test]
+    fn two_fingers_moving_together_pan() {
+        let mut gestures = Gestures::default();
+        gestures.pointer_down(touch(1, 0.0, 0.0));
+        gestures.pointer_down(touch(2, 100.0, 0.0));
+
+        let mut pan = Vec2::ZERO;
+        for (id, x) in [(1, 0.0), (2, 100.0)] {
+            let Some(Command::Pinch { dolly, pan: step }) =
+                gestures.pointer_move(touch(id, x, 20.0))
+            else {
+                panic!("expected a pinch");
+            };
+            assert!(dolly.abs() < 0.05);
+            pan += step;
+        }
+        assert_eq!(pan, Vec2::new(0.0, 20.0));
+    }
+
+    #[test]
+    fn lifting_a_finger_returns_to_rotation() {
+        let mut gestures = Gestures::default();
+        gestures.pointer_down(touch(1, 0.0, 0.0));
+        gestures.pointer_down(touch(2, 100.0, 0.0));
+        assert!(!gestures.pointer_down(touch(3, 50.0, 50.0)));
+
+        gestures.pointer_up(1);
+        assert_eq!(
+            gestures.pointer_move(touch(2, 110.0, 0.0)),
+            Some(Command::Rotate {
+                from: Vec2::new(100.0, 0.0),
+                to: Vec2::new(110.0, 0.0)
+            })
+        );
+    }
+
+    #[test]
+    fn wheel_down_dollies_out() {
+        let gestures = Gestures::default();
+        assert_eq!(gestures.wheel(0.0), None);
+        assert_eq!(
+            gestures.wheel(100.0),
+            Some(Command::Dolly(-100.0 * Bindings::DEFAULT_WHEEL_SPEED))
+        );
+    }
+
+    #[test]
+    fn keys_can_be_rebound() {
+        let mut gestures = Gestures::default();
+        assert_eq!(
+            gestures.key("1"),
+            Some(Command::Action(Action::SetResolution(15)))
+        );
+        assert_eq!(
+            gestures.key("9"),
+            Some(Command::Action(Action::SetResolution(4095)))
+        );
+
+        gestures.bindings.bind_key("r", Action::SetView(View::Top));
+        assert_eq!(
+            gestures.key("r"),
+            Some(Command::Action(Action::SetView(View::Top)))
+        );
+
+        gestures.bindings.unbind_key("r");
+        assert_eq!(gestures.key("r"), None);
+    }
+
+    #[test]
+    fn actions_parse() {
+        assert_eq!("view-iso".parse(), Ok(Action::SetView(View::Isometric)));
+        assert_eq!("resolution-63".parse(), Ok(Action::SetResolution(63)));
+        assert_eq!("save-state".parse(), Ok(Action::SaveState));
+        assert_eq!("resolution-x".parse::<Action>(), Err(()));
+    }
+}
@@ -0,0 +1,82 @@
+// Resample scattered points onto the grid, in place of the function evaluator.
+// Each point is x, y, z and its weight for Gaussian RBF interpolation.
+
+struct Params {
+    count: u32,
+    method: u32,
+    // Inverse distance weighting exponent
+    power: f32,
+    // Points further away are ignored by inverse distance weighting, the Gaussian's width for RBF
+    radius: f32,
+    // Where no point is in range, and the base the RBF interpolates around
+    mean: f32,
+}
+
+@group(0) @binding(0) var<storage, read_write> vertex_buffer: array<f32>;
+@group(0) @binding(1) var<storage, read> points: array<vec4f>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+const ELEMENT_SIZE = 6;
+
+// Must match `scatter::Interpolation`
+const METHOD_INVERSE_DISTANCE = 0u;
+const METHOD_GAUSSIAN_RBF = 1u;
+
+// Greyscale colour mapping, as in evaluator.wgsl. Must match `meshgrid::COLOR_RANGE`.
+const COLOR_MIN = -0.5;
+const COLOR_MAX = 2.0;
+
+fn inverse_distance(p: vec2f) -> f32 {
+    var weighted = 0.0;
+    var total = 0.0;
+    for (var i = 0u; i < params.count; i++) {
+        let d = p - points[i].xy;
+        let d2 = dot(d, d);
+        // Exactly on a point, which would otherwise divide by zero
+        if d2 == 0.0 {
+            return points[i].z;
+        }
+        if params.radius > 0.0 && d2 > params.radius * params.radius {
+            continue;
+        }
+        let w = pow(d2, -0.5 * params.power);
+        weighted += w * points[i].z;
+        total += w;
+    }
+    if total == 0.0 {
+        return params.mean;
+    }
+    return weighted / total;
+}
+
+fn gaussian_rbf(p: vec2f) -> f32 {
+    var value = params.mean;
+    let scale = 1.0 / (params.radius * params.radius);
+    for (var i = 0u; i < params.count; i++) {
+        let d = p - points[i].xy;
+        value += points[i].w * exp(-dot(d, d) * scale);
+    }
+    return value;
+}
+
+@compute @workgroup_size(256)
+fn interpolate(@builtin(global_invocation_id) gid: vec3u)
+{
+    let e: u32 = gid.x*ELEMENT_SIZE;
+    if (e + ELEMENT_SIZE) > arrayLength(&vertex_buffer) {
+        return;
+    }
+    let p = vec2f(vertex_buffer[e], vertex_buffer[e + 1]);
+    var value: f32;
+    if params.method == METHOD_GAUSSIAN_RBF {
+        value = gaussian_rbf(p);
+    } else {
+        value = inverse_distance(p);
+    }
+    vertex_buffer[e + 2] = value;
+
+    let color = (value - COLOR_MIN) / (COLOR_MAX - COLOR_MIN);
+    vertex_buffer[e + 3] = color;
+    vertex_buffer[e + 4] = color;
+    vertex_buffer[e + 5] = color;
+}
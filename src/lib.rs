@@ -1,147 +1,317 @@
 #![no_std]
 
-use glam::{EulerRot, Mat4, Quat, Vec3};
-use wasm_bindgen::prelude::*;
-use web_sys::HtmlCanvasElement;
-use wgpu::{
-    Surface,
-    util::{BufferInitDescriptor, DeviceExt},
-};
-
-use core::f32::consts::{PI, TAU};
-
+extern crate alloc;
+
+use alloc::{rc::Rc, string::String, vec::Vec};
+use glam::{Mat4, Vec2};
+use wgpu::Surface;
+
+use core::{cell::RefCell, f32::consts::PI, ops::RangeInclusive};
+
+use camera::Camera;
+use controller::CameraController;
+pub use error::Error;
+use input::{Action, Command};
+pub use meshgrid::ResolutionError;
+use viewport::Viewport;
+
+pub mod bookmark;
+pub mod camera;
+mod capture;
+pub mod clipping;
+mod controller;
+pub mod curves;
+mod delaunay;
+mod error;
+pub mod export;
+pub mod input;
+pub mod markers;
 mod meshgrid;
-
-#[inline(always)]
-fn float_modulo(a: f32, b: f32) -> f32 {
-    let r = a % b;
-    if r < 0.0 { r + b.abs() } else { r }
-}
-
-struct Camera {
-    target: Vec3,
-    distance: f32,
-    zenith: f32,
-    azimuth: f32,
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
-}
-
-#[allow(unused)]
-impl Camera {
-    const CLOSEST: f32 = 0.1;
-    const FARTHEST: f32 = 15.0;
-    const ZENITH_CLAMP: f32 = 0.0;
-
-    fn view_proj(&self) -> Mat4 {
-        let center = Mat4::from_translation(-self.target);
-        let quat = Quat::from_euler(EulerRot::ZXY, self.azimuth, self.zenith, 0.0);
-        let view =
-            Mat4::from_rotation_translation(quat.inverse(), Vec3::new(0.0, 0.0, -self.distance));
-        let proj = Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
-        proj * view * center
-    }
-
-    /// Rotate from the Z axis in radians relative to its current rotation
-    fn rotate_zenith(&mut self, angle: f32) {
-        self.zenith = (self.zenith + angle).clamp(Self::ZENITH_CLAMP, PI - Self::ZENITH_CLAMP);
-    }
-
-    /// Rotate about the XY plane in radians relative to its current rotation
-    fn rotate_azimuth(&mut self, angle: f32) {
-        self.azimuth = float_modulo(self.azimuth + angle, TAU);
-    }
-
-    /// Dolly into or out of the focus center relative to its current position
-    ///
-    /// This is multiplicative. Values < 1.0 dolly in and values > 1.0 dolly out.
-    /// Values are clamped between `Self::CLOSEST` and `SELF::FARTHEST`.
-    fn move_distance(&mut self, distance: f32) {
-        self.distance *= 1.0 - distance;
-        self.distance = self.distance.clamp(Self::CLOSEST, Self::FARTHEST);
-    }
-
-    /// Construct an X, Y, Z coordinate from the `distance`, `zenith`, and `azimuth` coordinate
-    fn spherical_to_rect(&self) -> Vec3 {
-        let sc_zenith = libm::sincosf(self.zenith);
-        let sc_azimuth = libm::sincosf(self.azimuth);
-
-        Vec3 {
-            x: self.distance * sc_zenith.0 * sc_azimuth.1,
-            y: self.distance * sc_zenith.0 * sc_azimuth.0,
-            z: self.distance * sc_zenith.1,
-        }
-    }
-}
+mod overlay;
+mod picking;
+mod png;
+mod readback;
+pub mod scatter;
+mod transparency;
+mod viewport;
+
+// The browser front end, see the `viewer` binary for the native one
+#[cfg(target_arch = "wasm32")]
+mod event_listeners;
+#[cfg(target_arch = "wasm32")]
+mod frame_loop;
+#[cfg(target_arch = "wasm32")]
+mod recovery;
+#[cfg(target_arch = "wasm32")]
+mod web;
+
+#[cfg(target_arch = "wasm32")]
+pub use web::{JsApp, start_app};
 
 pub struct State {
-    surface: Surface<'static>,
+    instance: wgpu::Instance,
+    target: RenderTarget,
+    /// Software adapter requested, kept for recovering from device loss
+    force_fallback_adapter: bool,
+    /// Receives a message when the device is lost, see `take_device_lost_receiver`
+    device_lost: Option<futures::channel::mpsc::UnboundedReceiver<String>>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     meshgrid_generator: meshgrid::Generator,
-    meshgrid_buffers: meshgrid::GridBuffers,
     evaluator: meshgrid::Evaluator,
+    interpolator: meshgrid::Interpolator,
+    marker_renderer: markers::MarkerRenderer,
+    curve_renderer: curves::CurveRenderer,
     depth_texture: wgpu::Texture,
     depth_texture_view: wgpu::TextureView,
     render_pipeline_layout: wgpu::PipelineLayout,
     render_shader_module: wgpu::ShaderModule,
     render_pipeline: wgpu::RenderPipeline,
-    camera: Camera,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
+    /// Draws translucent surfaces, see `transparency`
+    transparent_pipeline: wgpu::RenderPipeline,
+    compositor: transparency::Compositor,
+    /// Only allocated while a viewport has a translucent surface
+    oit_targets: Option<transparency::OitTargets>,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    surface_bind_group_layout: wgpu::BindGroupLayout,
+    /// Row by row, see `set_viewport_grid`
+    viewports: Vec<Viewport>,
+    /// Generation of the most recently created viewport
+    viewport_generation: u64,
+    /// Rows and columns of viewports
+    grid: (u32, u32),
+    /// Viewport the camera, plot and input methods apply to
+    active: usize,
+    /// Whether every camera follows the active one
+    cameras_linked: bool,
     multisample_texture: Option<wgpu::Texture>,
     multisample_texture_view: Option<wgpu::TextureView>,
     multisampling_enabled: bool,
+    colorbar: overlay::Colorbar,
+}
+
+/// Where frames are drawn
+enum RenderTarget {
+    /// Presented on a canvas or window, shared so a new adapter can be requested for it without
+    /// borrowing the state
+    Surface(Rc<Surface<'static>>),
+    /// Kept on the GPU for `render_png`
+    Offscreen(wgpu::Texture),
+}
+
+/// Textures and pipeline a frame is drawn with
+struct FrameTargets<'a> {
+    output: &'a wgpu::TextureView,
+    /// Multisampled colour attachment resolved into `output`
+    multisample: Option<&'a wgpu::TextureView>,
+    depth: &'a wgpu::TextureView,
+    /// Part of the output each viewport is drawn into, if any
+    regions: &'a [Option<capture::Tile>],
+    /// Accumulation and revealage targets, needed when a viewport has a translucent surface
+    oit: Option<&'a transparency::OitTargets>,
+    pipeline: &'a wgpu::RenderPipeline,
+    transparent_pipeline: &'a wgpu::RenderPipeline,
+    marker_pipeline: &'a wgpu::RenderPipeline,
+    curve_pipelines: &'a curves::CurvePipelines,
+}
+
+/// Pass a surface pipeline draws in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SurfacePass {
+    /// Replaces the colour and writes depth
+    Opaque,
+    /// Accumulates into the `transparency` targets, depth tested without writing depth
+    Translucent,
+}
+
+/// Camera matrices shared by the surface and marker shaders, followed by the clipping planes
+/// only the surface shader reads
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view_proj: Mat4,
+    view: Mat4,
+    proj: Mat4,
+    clipping: clipping::ClipUniform,
+}
+
+impl CameraUniform {
+    fn new(camera: &Camera, clipping: &clipping::Clipping) -> Self {
+        Self::from_matrices(camera.view(), camera.proj(), clipping)
+    }
+
+    fn from_matrices(view: Mat4, proj: Mat4, clipping: &clipping::Clipping) -> Self {
+        Self {
+            view_proj: proj * view,
+            view,
+            proj,
+            clipping: clipping.uniform(),
+        }
+    }
 }
 
 impl State {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// Map the far plane to 0 and the near plane to 1, which spreads float precision evenly
+    /// over distance. Only worthwhile with a floating point depth format.
+    pub const REVERSE_Z: bool = matches!(Self::DEPTH_FORMAT, wgpu::TextureFormat::Depth32Float);
+    const DEPTH_COMPARE: wgpu::CompareFunction = if Self::REVERSE_Z {
+        wgpu::CompareFunction::Greater
+    } else {
+        wgpu::CompareFunction::Less
+    };
+    const DEPTH_CLEAR: f32 = if Self::REVERSE_Z { 0.0 } else { 1.0 };
     pub const MSAA_SAMPLE_COUNT: u32 = 4;
+    /// Most rendered pixels in a capture, counting each supersampled pixel
+    ///
+    /// Every tile is queued before the capture resolves, so its targets and readback buffers
+    /// for the whole image are alive at once. This keeps them to a few hundred MiB, e.g. 8192
+    /// by 8192 pixels, or 4096 by 4096 at 16 samples.
+    pub const MAX_CAPTURE_PIXELS: u64 = 1 << 26;
+    pub const MAX_COLORBAR_TICKS: u32 = overlay::Colorbar::MAX_TICKS;
+    /// Same as the usual browser surface format, so offscreen frames match what is shown there
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 
     pub async fn new(
         width: u32,
         height: u32,
-        adapter: wgpu::Adapter,
-        surface: wgpu::Surface<'static>,
-    ) -> Self {
-        let (device, queue) = adapter
+        instance: wgpu::Instance,
+        surface: Surface<'static>,
+    ) -> Result<Self, Error> {
+        Self::with_target(width, height, instance, Some(Rc::new(surface)), false).await
+    }
+
+    /// Render into a texture instead of a surface, for reading frames back with `render_png`
+    ///
+    /// `force_fallback_adapter` requests a software adapter such as lavapipe or llvmpipe, for
+    /// machines without a GPU.
+    pub async fn new_offscreen(
+        width: u32,
+        height: u32,
+        instance: wgpu::Instance,
+        force_fallback_adapter: bool,
+    ) -> Result<Self, Error> {
+        Self::with_target(width, height, instance, None, force_fallback_adapter).await
+    }
+
+    async fn with_target(
+        width: u32,
+        height: u32,
+        instance: wgpu::Instance,
+        surface: Option<Rc<Surface<'static>>>,
+        force_fallback_adapter: bool,
+    ) -> Result<Self, Error> {
+        let adapter =
+            Self::request_adapter(&instance, surface.as_deref(), force_fallback_adapter).await?;
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let state = Self::with_device(
+            width,
+            height,
+            instance,
+            surface,
+            force_fallback_adapter,
+            &adapter,
+            device,
+            queue,
+        )?;
+
+        // Inspect the meshgrid buffers
+        #[cfg(feature = "readback")]
+        {
+            let generator = &state.meshgrid_generator;
+            let buffers = &state.viewport().meshgrid_buffers;
+            generator.print_vertices(buffers).await?;
+            generator.print_indices(buffers).await?;
+        }
+
+        Ok(state)
+    }
+
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&Surface<'static>>,
+        force_fallback_adapter: bool,
+    ) -> Result<wgpu::Adapter, Error> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::None,
+                force_fallback_adapter,
+                compatible_surface: surface,
+            })
+            .await?;
+        Ok(adapter)
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), Error> {
+        let device = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: Some("Device"),
                 required_features: wgpu::Features::default(),
-                required_limits: wgpu::Limits::defaults(),
+                // Large grids need more than the default buffer size and workgroup limits
+                required_limits: adapter.limits(),
                 experimental_features: wgpu::ExperimentalFeatures::disabled(),
                 memory_hints: wgpu::MemoryHints::default(),
                 trace: wgpu::Trace::Off,
             })
-            .await
-            .expect("Could not create device");
+            .await?;
+        Ok(device)
+    }
+
+    /// Create every GPU resource on a freshly requested device
+    #[allow(clippy::too_many_arguments)]
+    fn with_device(
+        width: u32,
+        height: u32,
+        instance: wgpu::Instance,
+        surface: Option<Rc<Surface<'static>>>,
+        force_fallback_adapter: bool,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+    ) -> Result<Self, Error> {
+        // Only report losses we did not cause by dropping the device
+        let (device_lost_sender, device_lost) = futures::channel::mpsc::unbounded();
+        device.set_device_lost_callback(move |reason, message| {
+            if reason != wgpu::DeviceLostReason::Destroyed {
+                let _ = device_lost_sender.unbounded_send(message);
+            }
+        });
 
         // Create a compute pipeline
 
         let meshgrid_generator = meshgrid::Generator::new(&device, &queue);
-        let meshgrid_buffers =
-            meshgrid_generator.generate_buffers((255, 255), -5.0..=5.0, -5.0..=5.0);
 
         let evaluator_module = device.create_shader_module(wgpu::include_wgsl!("evaluator.wgsl"));
         let evaluator = meshgrid_generator.create_evaluator(&evaluator_module, Some("evaluate"));
 
-        evaluator.evaluate_buffers(&[&meshgrid_buffers]);
-
-        // Inspect the meshgrid buffers
-        #[cfg(feature = "readback")]
-        {
-            meshgrid_generator.print_vertices(&meshgrid_buffers).await;
-            meshgrid_generator.print_indices(&meshgrid_buffers).await;
-        }
+        let interpolator = meshgrid_generator.create_interpolator();
 
-        // Configure the surface
-        let config = surface
-            .get_default_config(&adapter, width, height)
-            .expect("Surface not supported by adapter");
-        surface.configure(&device, &config);
+        // Configure the surface, or describe the offscreen texture in the same terms
+        let (config, target) = match surface {
+            Some(surface) => {
+                let config = surface
+                    .get_default_config(adapter, width, height)
+                    .ok_or(Error::SurfaceUnsupported)?;
+                surface.configure(&device, &config);
+                (config, RenderTarget::Surface(surface))
+            }
+            None => {
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    format: Self::OFFSCREEN_FORMAT,
+                    width,
+                    height,
+                    present_mode: wgpu::PresentMode::Fifo,
+                    desired_maximum_frame_latency: 2,
+                    alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                    view_formats: Vec::new(),
+                };
+                let texture = Self::create_offscreen_texture(&device, &config);
+                (config, RenderTarget::Offscreen(texture))
+            }
+        };
 
         // TODO: Move render setup somewhere else
 
@@ -150,7 +320,8 @@ impl State {
                 label: Some("Camera bind group layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    // Clipping planes are tested per fragment
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -160,76 +331,233 @@ impl State {
                 }],
             });
 
-        let camera = Camera {
-            //eye: Vec3::new(4.0, -8.0, 8.0), // TODO: Remove
-            target: Vec3::ZERO,
-            distance: 12.0,
-            zenith: 0.841_068_7,
-            azimuth: 1.107_148_8,
-            aspect: width as f32 / height as f32,
-            fovy: f32::to_radians(90.0),
-            znear: 0.1,
-            zfar: 100.0,
-        };
-
-        let camera_uniform: Mat4 = camera.view_proj();
-
-        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Camera buffer"),
-            contents: bytemuck::bytes_of(&camera_uniform),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Camera bind group"),
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(camera_buffer.as_entire_buffer_binding()),
-            }],
-        });
+        let surface_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Surface bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
 
         let render_shader_module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render pipeline layout"),
-                bind_group_layouts: &[&camera_bind_group_layout],
+                bind_group_layouts: &[&camera_bind_group_layout, &surface_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = Self::create_render_pipeline(
+        let [render_pipeline, transparent_pipeline] =
+            [SurfacePass::Opaque, SurfacePass::Translucent].map(|pass| {
+                Self::create_render_pipeline(
+                    &device,
+                    &config,
+                    Some(&render_pipeline_layout),
+                    &render_shader_module,
+                    Some("vs_main"),
+                    pass,
+                    1,
+                )
+            });
+        let compositor = transparency::Compositor::new(&device, config.format);
+
+        let marker_renderer = markers::MarkerRenderer::new(
+            &device,
+            config.format,
+            &camera_bind_group_layout,
+            Self::DEPTH_FORMAT,
+            Self::DEPTH_COMPARE,
+            1,
+        );
+        let curve_renderer = curves::CurveRenderer::new(
             &device,
-            &config,
-            Some(&render_pipeline_layout),
-            &render_shader_module,
-            Some("vs_main"),
-            Some("fs_main"),
+            config.format,
+            &camera_bind_group_layout,
+            Self::DEPTH_FORMAT,
+            Self::DEPTH_COMPARE,
             1,
         );
 
         let (depth_texture, depth_texture_view) = Self::create_depth_texture(&device, &config, 1);
 
-        Self {
-            surface,
+        let mut colorbar = overlay::Colorbar::new(&device, config.format);
+        colorbar.update(&device, &queue, config.width, config.height);
+
+        let mut state = Self {
+            instance,
+            target,
+            force_fallback_adapter,
+            device_lost: Some(device_lost),
             device,
             queue,
             config,
             meshgrid_generator,
-            meshgrid_buffers,
             evaluator,
+            interpolator,
+            marker_renderer,
+            curve_renderer,
             depth_texture,
             depth_texture_view,
             render_pipeline_layout,
             render_shader_module,
             render_pipeline,
-            camera,
-            camera_buffer,
-            camera_bind_group,
+            transparent_pipeline,
+            compositor,
+            oit_targets: None,
+            camera_bind_group_layout,
+            surface_bind_group_layout,
+            viewports: Vec::new(),
+            viewport_generation: 0,
+            grid: (1, 1),
+            active: 0,
+            cameras_linked: false,
             multisample_texture: None,
             multisample_texture_view: None,
             multisampling_enabled: false,
+            colorbar,
+        };
+
+        // Aspect ratio set from the viewport's cell by `layout_viewports`
+        let mut camera = Camera::new(1.0);
+        camera.reverse_z = Self::REVERSE_Z;
+        let viewport = state.create_viewport(camera, CameraController::new())?;
+        state.viewports.push(viewport);
+        state.layout_viewports();
+        Ok(state)
+    }
+
+    /// Channel receiving a message if the current device is lost, only handed out once per device
+    pub fn take_device_lost_receiver(
+        &mut self,
+    ) -> Option<futures::channel::mpsc::UnboundedReceiver<String>> {
+        self.device_lost.take()
+    }
+
+    /// Rebuild every GPU resource after the device was lost
+    ///
+    /// The viewports with their cameras and plots, and the colorbar and multisampling settings
+    /// carry over to the new device.
+    pub async fn recover_device(state: &RefCell<Self>) -> Result<(), Error> {
+        // Do not hold a borrow across awaits, input handlers may need the state meanwhile
+        let (instance, surface, force_fallback_adapter) = {
+            let state = state.borrow();
+            let surface = match &state.target {
+                RenderTarget::Surface(surface) => Some(surface.clone()),
+                RenderTarget::Offscreen(_) => None,
+            };
+            (
+                state.instance.clone(),
+                surface,
+                state.force_fallback_adapter,
+            )
+        };
+        let adapter =
+            Self::request_adapter(&instance, surface.as_deref(), force_fallback_adapter).await?;
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let mut state = state.borrow_mut();
+        let (width, height) = state.size();
+        let mut fresh = Self::with_device(
+            width,
+            height,
+            instance,
+            surface,
+            force_fallback_adapter,
+            &adapter,
+            device,
+            queue,
+        )?;
+
+        fresh.colorbar.set_visible(state.colorbar.is_visible());
+        fresh.set_colorbar_corner(state.colorbar.corner());
+        fresh.set_colorbar_ticks(state.colorbar_ticks());
+
+        let (rows, cols) = state.grid;
+        fresh.set_viewport_grid(rows, cols)?;
+        for (index, lost) in state.viewports.iter_mut().enumerate() {
+            // Rebuild the plots through the same methods as the active viewport
+            fresh.active = index;
+            let viewport = &mut fresh.viewports[index];
+            viewport.generation = lost.generation;
+            core::mem::swap(&mut viewport.camera, &mut lost.camera);
+            core::mem::swap(&mut viewport.camera_controller, &mut lost.camera_controller);
+            if let Some(interpolated) = &lost.interpolated {
+                viewport.interpolated =
+                    Some(fresh.interpolator.upload(interpolated.samples.clone())?);
+            }
+            if let Some(scattered) = &lost.scattered {
+                viewport.scattered = Some(scatter::ScatterBuffers::new(
+                    &fresh.device,
+                    scattered.mesh.clone(),
+                )?);
+            }
+            viewport.set_opacity(&fresh.queue, lost.opacity());
+            viewport.set_clipping(&fresh.queue, lost.clipping().clone());
+            viewport
+                .markers
+                .set_markers(&fresh.device, lost.markers.markers());
+            viewport
+                .markers
+                .set_shape(&fresh.queue, lost.markers.shape());
+            viewport.curves.restore(
+                &fresh.device,
+                &fresh.queue,
+                &fresh.curve_renderer,
+                &lost.curves,
+            )?;
+
+            let buffers = &lost.meshgrid_buffers;
+            if viewport.meshgrid_buffers.resolution != buffers.resolution
+                || viewport.meshgrid_buffers.x_range != buffers.x_range
+                || viewport.meshgrid_buffers.y_range != buffers.y_range
+            {
+                fresh.regenerate_grid(
+                    buffers.resolution,
+                    buffers.x_range.clone(),
+                    buffers.y_range.clone(),
+                )?;
+            } else if viewport.interpolated.is_some() {
+                fresh.evaluate_grid();
+            }
         }
+        fresh.viewport_generation = state.viewport_generation;
+        fresh.active = state.active;
+        fresh.cameras_linked = state.cameras_linked;
+        fresh.set_multisampling_enabled(state.multisampling_enabled);
+        fresh.update_oit_targets(false);
+        fresh.layout_viewports();
+
+        *state = fresh;
+        Ok(())
+    }
+
+    #[must_use]
+    fn create_offscreen_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
     }
 
     // This is an associated function because if it took &self then it would not be callable from the constructor
@@ -250,7 +578,7 @@ impl State {
             sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         });
 
@@ -278,7 +606,7 @@ impl State {
             sample_count: Self::MSAA_SAMPLE_COUNT,
             dimension: wgpu::TextureDimension::D2,
             format: config.format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         });
 
@@ -295,11 +623,27 @@ impl State {
         layout: Option<&wgpu::PipelineLayout>,
         module: &wgpu::ShaderModule,
         vs_main: Option<&str>,
-        fs_main: Option<&str>,
+        pass: SurfacePass,
         sample_count: u32,
     ) -> wgpu::RenderPipeline {
+        let (label, fs_main, targets) = match pass {
+            SurfacePass::Opaque => (
+                "Render pipeline",
+                "fs_main",
+                &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })][..],
+            ),
+            SurfacePass::Translucent => (
+                "Transparent pipeline",
+                "fs_transparent",
+                &transparency::color_targets()[..],
+            ),
+        };
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render pipeline"),
+            label: Some(label),
             layout,
             vertex: wgpu::VertexState {
                 module,
@@ -309,12 +653,8 @@ impl State {
             },
             fragment: Some(wgpu::FragmentState {
                 module,
-                entry_point: fs_main,
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                entry_point: Some(fs_main),
+                targets,
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             }),
             primitive: wgpu::PrimitiveState {
@@ -328,8 +668,8 @@ impl State {
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Self::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+                depth_write_enabled: pass == SurfacePass::Opaque,
+                depth_compare: Self::DEPTH_COMPARE,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
@@ -343,11 +683,54 @@ impl State {
         })
     }
 
+    /// Opaque and translucent surface pipelines for targets with `sample_count` samples
+    fn surface_pipelines(&self, sample_count: u32) -> [wgpu::RenderPipeline; 2] {
+        [SurfacePass::Opaque, SurfacePass::Translucent].map(|pass| {
+            Self::create_render_pipeline(
+                &self.device,
+                &self.config,
+                Some(&self.render_pipeline_layout),
+                &self.render_shader_module,
+                Some("vs_main"),
+                pass,
+                sample_count,
+            )
+        })
+    }
+
+    /// Keep the translucent pass' targets while any viewport has a translucent surface
+    ///
+    /// `resized` replaces existing targets to match the frame again.
+    fn update_oit_targets(&mut self, resized: bool) {
+        let needed = self.viewports.iter().any(Viewport::is_translucent);
+        if needed == self.oit_targets.is_some() && !resized {
+            return;
+        }
+        if let Some(targets) = self.oit_targets.take() {
+            targets.destroy();
+        }
+        self.oit_targets = needed.then(|| {
+            transparency::OitTargets::new(
+                &self.device,
+                &self.compositor,
+                self.config.width,
+                self.config.height,
+                self.sample_count(),
+            )
+        });
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
             self.config.width = width;
             self.config.height = height;
-            self.surface.configure(&self.device, &self.config);
+            match &mut self.target {
+                RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Offscreen(texture) => {
+                    texture.destroy();
+                    *texture = Self::create_offscreen_texture(&self.device, &self.config);
+                }
+            }
 
             // More efficient to explicitly destory here than rely on Drop
             self.depth_texture.destroy();
@@ -371,44 +754,809 @@ impl State {
             let (tex, view) = Self::create_depth_texture(&self.device, &self.config, sample_count);
             self.depth_texture = tex;
             self.depth_texture_view = view;
+            self.update_oit_targets(true);
+
+            self.colorbar
+                .update(&self.device, &self.queue, width, height);
+            self.layout_viewports();
+        }
+    }
+
+    /// Vertices of the grid along X and Y
+    pub fn grid_resolution(&self) -> (u32, u32) {
+        self.viewport().meshgrid_buffers.resolution
+    }
+
+    /// Resample the grid, leaving the current one in place if the device cannot fit the new one
+    pub fn set_grid_resolution(&mut self, width: u32, height: u32) -> Result<(), Error> {
+        let buffers = &self.viewport().meshgrid_buffers;
+        let (x_range, y_range) = (buffers.x_range.clone(), buffers.y_range.clone());
+        self.regenerate_grid((width, height), x_range, y_range)
+    }
+
+    /// Largest square grid resolution the device supports
+    pub fn max_grid_resolution(&self) -> u32 {
+        self.meshgrid_generator.max_square_resolution()
+    }
+
+    /// Largest side of a texture, captures beyond it are rendered in tiles
+    pub fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Set the X and Y ranges the function is sampled over
+    pub fn set_domain(
+        &mut self,
+        x_range: RangeInclusive<f32>,
+        y_range: RangeInclusive<f32>,
+    ) -> Result<(), Error> {
+        let resolution = self.viewport().meshgrid_buffers.resolution;
+        self.regenerate_grid(resolution, x_range, y_range)
+    }
 
-            self.camera.aspect = width as f32 / height as f32;
-            let camera_uniform = self.camera.view_proj();
-            self.queue
-                .write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera_uniform));
+    fn regenerate_grid(
+        &mut self,
+        resolution: (u32, u32),
+        x_range: RangeInclusive<f32>,
+        y_range: RangeInclusive<f32>,
+    ) -> Result<(), Error> {
+        let meshgrid_buffers = self
+            .meshgrid_generator
+            .generate_buffers(resolution, x_range, y_range)?;
+        let viewport = &mut self.viewports[self.active];
+        viewport.meshgrid_buffers.destroy();
+        viewport.meshgrid_buffers = meshgrid_buffers;
+
+        self.evaluate_grid();
+        Ok(())
+    }
+
+    /// Fill in the grid's heights from the function or the interpolated points
+    fn evaluate_grid(&mut self) {
+        let viewport = &mut self.viewports[self.active];
+        match &viewport.interpolated {
+            Some(points) => self
+                .interpolator
+                .interpolate(points, &viewport.meshgrid_buffers),
+            None => self
+                .evaluator
+                .evaluate_buffers(&[&viewport.meshgrid_buffers]),
         }
+        viewport.request_height_readback(&self.device, &self.queue);
     }
 
-    pub fn set_grid_resolution(&mut self, width: u32, height: u32) {
-        self.meshgrid_buffers.destroy();
+    /// Resample irregular (x, y, z) samples onto the grid in place of the function
+    ///
+    /// The domain is set to the points' bounds, keeping the resolution, and changing the grid
+    /// afterwards interpolates them again. Everything built on the grid, such as picking and
+    /// `export_grid`, then applies to the points. Nothing changes if they cannot be interpolated.
+    pub fn interpolate_scattered_points(
+        &mut self,
+        points: &[[f32; 3]],
+        interpolation: scatter::Interpolation,
+    ) -> Result<(), Error> {
+        let samples = scatter::InterpolationSamples::new(points, interpolation)?;
+        // Points on a line along X or Y still need a domain with some width
+        let widen = |range: &RangeInclusive<f32>| {
+            let (start, end) = (*range.start(), *range.end());
+            if start < end {
+                start..=end
+            } else {
+                start - 0.5..=end + 0.5
+            }
+        };
+        let (x_range, y_range) = (widen(&samples.x_range), widen(&samples.y_range));
+        let interpolated = self.interpolator.upload(samples)?;
+
+        let previous = self.viewport_mut().interpolated.replace(interpolated);
+        let resolution = self.viewport().meshgrid_buffers.resolution;
+        if let Err(err) = self.regenerate_grid(resolution, x_range, y_range) {
+            if let Some(rejected) =
+                core::mem::replace(&mut self.viewport_mut().interpolated, previous)
+            {
+                rejected.destroy();
+            }
+            return Err(err);
+        }
+
+        if let Some(previous) = previous {
+            previous.destroy();
+        }
+        if let Some(scattered) = self.viewport_mut().scattered.take() {
+            scattered.destroy();
+        }
+        Ok(())
+    }
+
+    /// Evaluate the function on the grid again after `interpolate_scattered_points`
+    fn clear_interpolated_points(&mut self) {
+        if let Some(interpolated) = self.viewport_mut().interpolated.take() {
+            interpolated.destroy();
+            self.evaluate_grid();
+        }
+    }
 
+    /// Show a surface triangulated from irregular (x, y, z) samples in place of the grid
+    ///
+    /// The grid goes back to the function, which is still sampled and exported by `export_grid`,
+    /// and shown again after `clear_scattered_points`. Nothing changes if the points cannot be
+    /// triangulated.
+    pub fn set_scattered_points(&mut self, points: &[[f32; 3]]) -> Result<(), Error> {
+        let mesh = scatter::ScatterMesh::new(points)?;
+        let scattered = scatter::ScatterBuffers::new(&self.device, mesh)?;
+        if let Some(previous) = self.viewport_mut().scattered.replace(scattered) {
+            previous.destroy();
+        }
+        self.clear_interpolated_points();
+        Ok(())
+    }
+
+    /// Go back to showing the sampled function, whether the points were triangulated or
+    /// interpolated
+    pub fn clear_scattered_points(&mut self) {
+        if let Some(scattered) = self.viewport_mut().scattered.take() {
+            scattered.destroy();
+        }
+        self.clear_interpolated_points();
+    }
+
+    pub fn has_scattered_points(&self) -> bool {
+        let viewport = self.viewport();
+        viewport.scattered.is_some() || viewport.interpolated.is_some()
+    }
+
+    pub fn surface_opacity(&self) -> f32 {
+        self.viewport().opacity()
+    }
+
+    /// Let what lies beneath show through the surface, from 0 for invisible to 1 for opaque
+    ///
+    /// Translucent surfaces are blended independently of their drawing order, so a surface that
+    /// folds over itself or surfaces in other viewports need no sorting.
+    pub fn set_surface_opacity(&mut self, opacity: f32) {
+        let opacity = if opacity.is_nan() {
+            1.0
+        } else {
+            opacity.clamp(0.0, 1.0)
+        };
+        let viewport = &mut self.viewports[self.active];
+        viewport.set_opacity(&self.queue, opacity);
+        self.update_oit_targets(false);
+    }
+
+    pub fn clip_planes(&self) -> &[clipping::ClipPlane] {
+        self.viewport().clipping().planes()
+    }
+
+    /// Cut away the active viewport's surface beyond `planes`, at most
+    /// `clipping::MAX_CLIP_PLANES`, or show all of it again if empty
+    ///
+    /// Back faces seen through a cut are capped with a solid colour, see `clipping`. Picking
+    /// passes through the parts that are cut away.
+    pub fn set_clip_planes(&mut self, planes: &[clipping::ClipPlane]) -> Result<(), Error> {
+        let mut clipping = self.viewport().clipping().clone();
+        if !clipping.set_planes(planes) {
+            return Err(Error::ClipPlanes(planes.len()));
+        }
+        let viewport = &mut self.viewports[self.active];
+        viewport.set_clipping(&self.queue, clipping);
+        Ok(())
+    }
+
+    pub fn clip_cap_color(&self) -> [f32; 3] {
+        self.viewport().clipping().cap_color()
+    }
+
+    /// Colour the inside of the active viewport's surface is capped with where it is cut open
+    pub fn set_clip_cap_color(&mut self, color: [f32; 3]) {
+        let mut clipping = self.viewport().clipping().clone();
+        clipping.set_cap_color(color);
+        let viewport = &mut self.viewports[self.active];
+        viewport.set_clipping(&self.queue, clipping);
+    }
+
+    /// Overlay markers at 3D points, hidden where the surface passes in front of them
+    ///
+    /// Replaces any previous markers, an empty slice removes them.
+    pub fn set_markers(&mut self, markers: &[markers::Marker]) {
+        let viewport = &mut self.viewports[self.active];
+        viewport.markers.set_markers(&self.device, markers);
+    }
+
+    pub fn clear_markers(&mut self) {
+        self.set_markers(&[]);
+    }
+
+    pub fn marker_shape(&self) -> markers::MarkerShape {
+        self.viewport().markers.shape()
+    }
+
+    pub fn set_marker_shape(&mut self, shape: markers::MarkerShape) {
+        let viewport = &mut self.viewports[self.active];
+        viewport.markers.set_shape(&self.queue, shape);
+    }
+
+    /// Draw a polyline through `points`, ordered by t, alongside the surface
+    pub fn add_curve(
+        &mut self,
+        points: &[[f32; 3]],
+        style: curves::CurveStyle,
+    ) -> Result<curves::CurveId, Error> {
+        let viewport = &mut self.viewports[self.active];
+        Ok(viewport.curves.add_polyline(
+            &self.device,
+            &self.queue,
+            &self.curve_renderer,
+            points,
+            style,
+        )?)
+    }
+
+    /// Draw a curve sampled from an expression of t on the GPU, see `curves::ParametricCurve`
+    ///
+    /// Resolves once the expression compiled. If it did not, the curve is removed again and
+    /// `CurveError::Expression` has the compiler's message.
+    pub async fn add_parametric_curve(
+        state: &RefCell<Self>,
+        curve: curves::ParametricCurve,
+        style: curves::CurveStyle,
+    ) -> Result<curves::CurveId, Error> {
+        // Do not hold a borrow across the await, the active viewport may change meanwhile and
+        // the grid may replace the viewport the curve was added to
+        let (generation, id, compiled) = {
+            let state = &mut *state.borrow_mut();
+            let viewport = &mut state.viewports[state.active];
+            let (id, compiled) = viewport.curves.add_parametric(
+                &state.device,
+                &state.queue,
+                &state.curve_renderer,
+                curve,
+                style,
+            )?;
+            (viewport.generation, id, compiled)
+        };
+        if let Err(err) = compiled.await {
+            let mut state = state.borrow_mut();
+            let viewport = state
+                .viewports
+                .iter_mut()
+                .find(|v| v.generation == generation);
+            if let Some(viewport) = viewport {
+                viewport.curves.remove(id);
+            }
+            return Err(err.into());
+        }
+        Ok(id)
+    }
+
+    /// Returns whether there was a curve with this id
+    pub fn remove_curve(&mut self, id: curves::CurveId) -> bool {
+        self.viewport_mut().curves.remove(id)
+    }
+
+    pub fn clear_curves(&mut self) {
+        self.viewport_mut().curves.clear();
+    }
+
+    fn viewport(&self) -> &Viewport {
+        &self.viewports[self.active]
+    }
+
+    fn viewport_mut(&mut self) -> &mut Viewport {
+        &mut self.viewports[self.active]
+    }
+
+    /// A viewport showing the default grid through `camera`
+    fn create_viewport(
+        &mut self,
+        camera: Camera,
+        camera_controller: CameraController,
+    ) -> Result<Viewport, Error> {
         let meshgrid_buffers =
             self.meshgrid_generator
-                .generate_buffers((width, height), -5.0..=5.0, -5.0..=5.0);
-
+                .generate_buffers((255, 255), -5.0..=5.0, -5.0..=5.0)?;
         self.evaluator.evaluate_buffers(&[&meshgrid_buffers]);
-        self.meshgrid_buffers = meshgrid_buffers;
+
+        self.viewport_generation += 1;
+        let mut viewport = Viewport::new(
+            self.viewport_generation,
+            &self.device,
+            &self.camera_bind_group_layout,
+            &self.surface_bind_group_layout,
+            &self.marker_renderer,
+            &self.curve_renderer,
+            meshgrid_buffers,
+            camera,
+            camera_controller,
+        );
+        viewport.request_height_readback(&self.device, &self.queue);
+        Ok(viewport)
     }
 
-    pub fn render(&mut self) {
-        let output = self
-            .surface
-            .get_current_texture()
-            .expect("Could not get current texture");
+    /// Region of a viewport in pixels of the render target
+    fn viewport_cell(&self, index: usize) -> capture::Tile {
+        viewport::cell(index, self.grid, self.config.width, self.config.height)
+    }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    /// Fit every viewport to its cell of the render target
+    fn layout_viewports(&mut self) {
+        for index in 0..self.viewports.len() {
+            let cell = self.viewport_cell(index);
+            self.viewports[index].set_size(&self.queue, &cell);
+        }
+        self.write_camera_uniform();
+    }
 
-        let (view, resolve_target) = if self.multisampling_enabled {
-            (
-                self.multisample_texture_view
-                    .as_ref()
-                    .expect("Multisampling enabled with no texture view"),
-                Some(view),
-            )
+    /// Split the render target into `rows` by `cols` viewports, counted row by row from the top
+    /// left
+    ///
+    /// Viewports up to the new count keep their cameras and plots. New ones show the default grid
+    /// through a copy of the active camera.
+    pub fn set_viewport_grid(&mut self, rows: u32, cols: u32) -> Result<(), Error> {
+        let sizes = 1..=viewport::MAX_GRID_SIZE;
+        if !sizes.contains(&rows) || !sizes.contains(&cols) {
+            return Err(Error::ViewportGrid((rows, cols)));
+        }
+
+        let count = (rows * cols) as usize;
+        while self.viewports.len() > count {
+            let mut viewport = self.viewports.pop().unwrap();
+            viewport.destroy();
+        }
+        while self.viewports.len() < count {
+            let active = self.viewport();
+            let mut camera_controller = active.camera_controller.clone();
+            camera_controller.stop();
+            let viewport = self.create_viewport(active.camera.clone(), camera_controller)?;
+            self.viewports.push(viewport);
+        }
+
+        self.grid = (rows, cols);
+        self.active = self.active.min(count - 1);
+        self.layout_viewports();
+        self.update_oit_targets(false);
+        Ok(())
+    }
+
+    /// Rows and columns of viewports
+    pub fn viewport_grid(&self) -> (u32, u32) {
+        self.grid
+    }
+
+    /// Viewport the camera and plot methods apply to, counted row by row from the top left
+    pub fn active_viewport(&self) -> usize {
+        self.active
+    }
+
+    /// Returns whether there is a viewport with this index
+    pub fn set_active_viewport(&mut self, index: usize) -> bool {
+        if index >= self.viewports.len() {
+            return false;
+        }
+        self.active = index;
+        true
+    }
+
+    /// Viewport containing the pixel `px`, `py` of the render target
+    pub fn viewport_at(&self, px: f32, py: f32) -> Option<usize> {
+        (0..self.viewports.len()).find(|&index| {
+            let cell = self.viewport_cell(index);
+            (cell.x as f32..(cell.x + cell.width) as f32).contains(&px)
+                && (cell.y as f32..(cell.y + cell.height) as f32).contains(&py)
+        })
+    }
+
+    /// Route input to the viewport under `position`, in pixels of the render target
+    pub fn focus_viewport_at(&mut self, position: Vec2) {
+        if let Some(index) = self.viewport_at(position.x, position.y) {
+            self.active = index;
+        }
+    }
+
+    pub fn are_cameras_linked(&self) -> bool {
+        self.cameras_linked
+    }
+
+    /// Make every camera follow the active one, keeping its own aspect ratio
+    pub fn set_cameras_linked(&mut self, linked: bool) {
+        self.cameras_linked = linked;
+        self.write_camera_uniform();
+    }
+
+    /// Snapshot of the active viewport's camera and plot configuration
+    pub fn bookmark(&self) -> bookmark::Bookmark {
+        let Viewport {
+            camera,
+            meshgrid_buffers,
+            ..
+        } = self.viewport();
+        let (zenith, azimuth) = camera.angles();
+        bookmark::Bookmark {
+            target: camera.target,
+            distance: camera.distance,
+            zenith,
+            azimuth,
+            mode: camera.mode,
+            orientation: camera.orientation(),
+            fovy: camera.fovy,
+            projection: camera.projection,
+            function: bookmark::FUNCTION.into(),
+            x_range: meshgrid_buffers.x_range.clone(),
+            y_range: meshgrid_buffers.y_range.clone(),
+            resolution: meshgrid_buffers.resolution,
+            multisampling: self.multisampling_enabled,
+            colormap: bookmark::COLORMAP.into(),
+        }
+    }
+
+    /// Restore a snapshot taken by `bookmark` in the active viewport, only regenerating the grid
+    /// if it changed
+    ///
+    /// Nothing changes if the bookmark's grid does not fit the device.
+    pub fn apply_bookmark(&mut self, bookmark: &bookmark::Bookmark) -> Result<(), Error> {
+        self.meshgrid_generator
+            .validate_resolution(bookmark.resolution)?;
+
+        let viewport = self.viewport_mut();
+        viewport.camera_controller.stop();
+        let camera = &mut viewport.camera;
+        camera.target = bookmark.target;
+        camera.set_distance(bookmark.distance);
+        camera.set_angles(bookmark.zenith, bookmark.azimuth);
+        camera.mode = bookmark.mode;
+        if bookmark.mode == camera::CameraMode::Trackball {
+            camera.set_trackball_orientation(bookmark.orientation);
+        }
+        camera.set_fovy(bookmark.fovy);
+        camera.jump_to_projection(bookmark.projection);
+        self.write_camera_uniform();
+
+        let buffers = &self.viewport().meshgrid_buffers;
+        if buffers.resolution != bookmark.resolution
+            || buffers.x_range != bookmark.x_range
+            || buffers.y_range != bookmark.y_range
+        {
+            self.regenerate_grid(
+                bookmark.resolution,
+                bookmark.x_range.clone(),
+                bookmark.y_range.clone(),
+            )?;
+        }
+
+        self.set_multisampling_enabled(bookmark.multisampling);
+        Ok(())
+    }
+
+    /// Find the point on the surface under the pixel `px`, `py` of the render target
+    ///
+    /// Returns `None` if the pixel does not cover the surface of the viewport it falls in or its
+    /// evaluated heights have not been read back from the GPU yet. Only the grid can be picked,
+    /// not scattered points.
+    pub fn pick(&mut self, px: f32, py: f32) -> Option<[f32; 3]> {
+        let index = self.viewport_at(px, py)?;
+        let cell = self.viewport_cell(index);
+        let viewport = &mut self.viewports[index];
+        if viewport.scattered.is_some() {
+            return None;
+        }
+
+        let ndc = Vec2::new(
+            2.0 * (px - cell.x as f32) / cell.width as f32 - 1.0,
+            1.0 - 2.0 * (py - cell.y as f32) / cell.height as f32,
+        );
+        let (origin, direction) = viewport.camera.ray(ndc);
+
+        let clipping = viewport.clipping().clone();
+        viewport
+            .height_field(&self.device)?
+            .intersect(origin, direction, |hit| !clipping.clips(hit))
+            .map(|hit| hit.to_array())
+    }
+
+    pub fn render(&mut self) -> Result<(), Error> {
+        let surface = match &self.target {
+            RenderTarget::Surface(surface) => surface,
+            RenderTarget::Offscreen(texture) => {
+                self.draw(&texture.create_view(&wgpu::TextureViewDescriptor::default()));
+                return Ok(());
+            }
+        };
+
+        let output = match surface.get_current_texture() {
+            Ok(output) => output,
+            // The surface no longer matches its configuration, for example after the page was
+            // hidden, so configure it again and retry once
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                surface.configure(&self.device, &self.config);
+                surface.get_current_texture()?
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Timed out waiting for the surface, skipping frame");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        self.draw(
+            &output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        output.present();
+        Ok(())
+    }
+
+    /// Render a frame and encode it as a PNG, without presenting it
+    ///
+    /// States with a surface draw into a temporary texture of the same size.
+    pub async fn render_png(&mut self) -> Result<Vec<u8>, Error> {
+        let bgra = self.is_bgra()?;
+
+        let texture = match &self.target {
+            RenderTarget::Offscreen(texture) => texture.clone(),
+            RenderTarget::Surface(_) => Self::create_offscreen_texture(&self.device, &self.config),
+        };
+        self.draw(&texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let pixels = readback::TextureReadback::new(&self.device, &self.queue, &texture)
+            .read()
+            .await?;
+
+        let (width, height) = (texture.width(), texture.height());
+        let tile = capture::Tile {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        let mut image = alloc::vec![0; width as usize * height as usize * 3];
+        capture::resolve_tile(&pixels, bgra, 1, &tile, &mut image, width);
+        Ok(png::encode_rgb(width, height, &image))
+    }
+
+    /// Render the current view into an image of any size and encode it as a PNG
+    ///
+    /// Images larger than the device's texture limit are drawn in tiles. Up to
+    /// `MSAA_SAMPLE_COUNT` `samples` per pixel are multisampled, more are supersampled on a finer
+    /// grid as well. The tiles are queued right away, the returned future does not borrow the
+    /// state.
+    ///
+    /// Returns `Error::ImageSize` if the image is empty or needs more than
+    /// `MAX_CAPTURE_PIXELS` rendered pixels.
+    pub fn capture(
+        &mut self,
+        width: u32,
+        height: u32,
+        samples: u32,
+    ) -> Result<impl Future<Output = Result<Vec<u8>, Error>> + 'static, Error> {
+        let bgra = self.is_bgra()?;
+
+        let max_size = self.max_texture_size();
+        let factor = capture::supersampling_factor(samples).min(max_size);
+        let image_bytes = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3));
+        let (Some(full_width), Some(full_height), Some(image_bytes)) = (
+            width.checked_mul(factor),
+            height.checked_mul(factor),
+            image_bytes,
+        ) else {
+            return Err(Error::ImageSize((width, height)));
+        };
+        let rendered = u64::from(full_width) * u64::from(full_height);
+        if image_bytes == 0 || rendered > Self::MAX_CAPTURE_PIXELS {
+            return Err(Error::ImageSize((width, height)));
+        }
+
+        let sample_count = if samples > 1 {
+            Self::MSAA_SAMPLE_COUNT
+        } else {
+            1
+        };
+        let pipelines =
+            (sample_count != self.sample_count()).then(|| self.surface_pipelines(sample_count));
+        let [pipeline, transparent_pipeline] = match &pipelines {
+            Some([pipeline, transparent_pipeline]) => [pipeline, transparent_pipeline],
+            None => [&self.render_pipeline, &self.transparent_pipeline],
+        };
+        let translucent = self.viewports.iter().any(Viewport::is_translucent);
+        let marker_pipeline = (sample_count != self.sample_count()).then(|| {
+            self.marker_renderer
+                .create_pipeline(&self.device, sample_count)
+        });
+        let marker_pipeline = marker_pipeline
+            .as_ref()
+            .unwrap_or(self.marker_renderer.pipeline());
+        let curve_pipelines = (sample_count != self.sample_count()).then(|| {
+            self.curve_renderer
+                .create_pipelines(&self.device, sample_count)
+        });
+        let curve_pipelines = curve_pipelines
+            .as_ref()
+            .unwrap_or(self.curve_renderer.pipelines());
+
+        // The image may have another aspect ratio than the render target, so its cells may too
+        let cells: Vec<_> = (0..self.viewports.len())
+            .map(|index| {
+                let cell = viewport::cell(index, self.grid, width, height);
+                let mut camera = self.viewports[index].camera.clone();
+                camera.aspect = cell.width.max(1) as f32 / cell.height.max(1) as f32;
+                (cell, camera.view(), camera.proj())
+            })
+            .collect();
+
+        let mut readbacks = Vec::new();
+        for tile in capture::tiles(width, height, max_size / factor) {
+            let scaled = tile.scaled(factor);
+            let mut config = self.config.clone();
+            config.width = scaled.width;
+            config.height = scaled.height;
+
+            let texture = Self::create_offscreen_texture(&self.device, &config);
+            let (_, depth) = Self::create_depth_texture(&self.device, &config, sample_count);
+            let multisample = (sample_count > 1)
+                .then(|| Self::create_multisample_texture(&self.device, &config).1);
+            let oit = translucent.then(|| {
+                transparency::OitTargets::new(
+                    &self.device,
+                    &self.compositor,
+                    scaled.width,
+                    scaled.height,
+                    sample_count,
+                )
+            });
+
+            // Queued writes land before the next submission, so every tile sees its own
+            let mut regions = Vec::with_capacity(cells.len());
+            for (viewport, (cell, view, proj)) in self.viewports.iter().zip(&cells) {
+                let Some(visible) = cell.intersection(&tile) else {
+                    regions.push(None);
+                    continue;
+                };
+                let in_cell = capture::Tile {
+                    x: visible.x - cell.x,
+                    y: visible.y - cell.y,
+                    ..visible
+                };
+                let projection = in_cell.projection(cell.width, cell.height) * *proj;
+                viewport.write_camera_matrices(&self.queue, *view, projection);
+
+                let region = capture::Tile {
+                    x: visible.x - tile.x,
+                    y: visible.y - tile.y,
+                    ..visible
+                }
+                .scaled(factor);
+                let scale = factor as f32;
+                viewport
+                    .markers
+                    .write_viewport(&self.queue, region.width, region.height, scale);
+                viewport.curves.write_viewport(&self.queue, &region, scale);
+                regions.push(Some(region));
+            }
+            self.colorbar.update_region(
+                &self.device,
+                &self.queue,
+                full_width,
+                full_height,
+                [scaled.x, scaled.y, scaled.width, scaled.height],
+            );
+
+            self.draw_frame(FrameTargets {
+                output: &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                multisample: multisample.as_ref(),
+                depth: &depth,
+                regions: &regions,
+                oit: oit.as_ref(),
+                pipeline,
+                transparent_pipeline,
+                marker_pipeline,
+                curve_pipelines,
+            });
+            let readback = readback::TextureReadback::new(&self.device, &self.queue, &texture);
+            readbacks.push((tile, readback));
+        }
+
+        self.layout_viewports();
+        self.colorbar.update(
+            &self.device,
+            &self.queue,
+            self.config.width,
+            self.config.height,
+        );
+
+        Ok(async move {
+            let mut image = alloc::vec![0; image_bytes];
+            for (tile, readback) in readbacks {
+                let pixels = readback.read().await?;
+                capture::resolve_tile(&pixels, bgra, factor, &tile, &mut image, width);
+            }
+            Ok(png::encode_rgb(width, height, &image))
+        })
+    }
+
+    /// Whether frames are read back as 8 bit BGRA rather than RGBA
+    fn is_bgra(&self) -> Result<bool, Error> {
+        match self.config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Ok(false),
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => Ok(true),
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        if self.multisampling_enabled {
+            Self::MSAA_SAMPLE_COUNT
         } else {
-            (&view, None)
+            1
+        }
+    }
+
+    /// Read back the active viewport's sampled coordinates and heights and encode them for
+    /// analysis tools
+    ///
+    /// The copy is queued right away, the returned future does not borrow the state.
+    pub fn export_grid(
+        &self,
+        format: export::GridFormat,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> + 'static {
+        let buffers = &self.viewport().meshgrid_buffers;
+        let vertices =
+            readback::BufferReadback::new(&self.device, &self.queue, &buffers.vertex_buffer);
+        let resolution = buffers.resolution;
+
+        async move {
+            let vertices = vertices.read().await?;
+            Ok(export::GridValues::from_vertices(resolution, &vertices).encode(format))
+        }
+    }
+
+    /// Read back the active viewport's surface and encode it as a mesh file
+    ///
+    /// The copies are queued right away, the returned future does not borrow the state.
+    pub fn export_mesh(
+        &self,
+        format: export::MeshFormat,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> + 'static {
+        let mesh = self.viewport().surface_mesh();
+        let vertices = readback::BufferReadback::new(&self.device, &self.queue, mesh.vertex_buffer);
+        let indices = readback::BufferReadback::new(&self.device, &self.queue, mesh.index_buffer);
+        let index_count = mesh.index_count as usize;
+
+        async move {
+            let vertices = vertices.read().await?;
+            let mut indices: Vec<u32> = indices.read().await?;
+            indices.truncate(index_count);
+            Ok(export::Mesh::new(vertices, indices).encode(format))
+        }
+    }
+
+    /// Draw a frame into `output_view`, which must match the configured size and format
+    fn draw(&self, output_view: &wgpu::TextureView) {
+        let multisample = self.multisampling_enabled.then(|| {
+            self.multisample_texture_view
+                .as_ref()
+                .expect("Multisampling enabled with no texture view")
+        });
+        let regions: Vec<_> = (0..self.viewports.len())
+            .map(|index| Some(self.viewport_cell(index)))
+            .collect();
+        self.draw_frame(FrameTargets {
+            output: output_view,
+            multisample,
+            depth: &self.depth_texture_view,
+            regions: &regions,
+            oit: self.oit_targets.as_ref(),
+            pipeline: &self.render_pipeline,
+            transparent_pipeline: &self.transparent_pipeline,
+            marker_pipeline: self.marker_renderer.pipeline(),
+            curve_pipelines: self.curve_renderer.pipelines(),
+        });
+    }
+
+    fn draw_frame(&self, frame: FrameTargets) {
+        let (view, resolve_target) = match frame.multisample {
+            Some(multisample) => (multisample, Some(frame.output)),
+            None => (frame.output, None),
         };
 
         let mut encoder = self
@@ -423,16 +1571,16 @@ impl State {
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view,
                     depth_slice: None,
-                    resolve_target: resolve_target.as_ref(),
+                    resolve_target,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
+                    view: frame.depth,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(Self::DEPTH_CLEAR),
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -441,28 +1589,278 @@ impl State {
                 occlusion_query_set: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.meshgrid_buffers.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.meshgrid_buffers.index_buffer.slice(..),
-                self.meshgrid_buffers.index_format,
-            );
-            render_pass.draw_indexed(0..self.meshgrid_buffers.index_count, 0, 0..1);
+            for (viewport, region) in self.viewports.iter().zip(frame.regions) {
+                let Some(region) = region.filter(|r| r.width > 0 && r.height > 0) else {
+                    continue;
+                };
+                let (x, y) = (region.x as f32, region.y as f32);
+                let (width, height) = (region.width as f32, region.height as f32);
+                render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+                render_pass.set_scissor_rect(region.x, region.y, region.width, region.height);
+                viewport.draw(&mut render_pass, &frame);
+            }
+
+            // The GL backend resolves with a blit, which is clipped to the scissor rect
+            let size = frame.output.texture().size();
+            render_pass.set_scissor_rect(0, 0, size.width, size.height);
+        }
+
+        // Translucent surfaces over everything opaque, with the same depth buffer
+        if let Some(oit) = frame.oit {
+            let mut transparent_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transparent pass"),
+                color_attachments: &oit.color_attachments(),
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: frame.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            for (viewport, region) in self.viewports.iter().zip(frame.regions) {
+                let Some(region) = region.filter(|r| r.width > 0 && r.height > 0) else {
+                    continue;
+                };
+                let (x, y) = (region.x as f32, region.y as f32);
+                let (width, height) = (region.width as f32, region.height as f32);
+                transparent_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+                transparent_pass.set_scissor_rect(region.x, region.y, region.width, region.height);
+                viewport.draw_translucent(&mut transparent_pass, &frame);
+            }
+            let size = frame.output.texture().size();
+            transparent_pass.set_scissor_rect(0, 0, size.width, size.height);
+            drop(transparent_pass);
+
+            self.compositor.composite(&mut encoder, frame.output, oit);
+        }
+
+        // Overlays are drawn on top of the resolved surface
+        if self.colorbar.is_visible() {
+            let mut overlay_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: frame.output,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.colorbar.draw(&mut overlay_pass);
         }
 
         let command_buffer = encoder.finish();
         self.queue.submit([command_buffer]);
-        output.present();
     }
 
+    /// Rotate and dolly the active camera, applied gradually by `update` while inertia is enabled
     pub fn move_camera(&mut self, distance: f32, zenith: f32, azimuth: f32) {
-        self.camera.move_distance(distance);
-        self.camera.rotate_zenith(zenith);
-        self.camera.rotate_azimuth(azimuth);
-        let camera_uniform = self.camera.view_proj();
-        self.queue
-            .write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera_uniform));
+        let viewport = self.viewport_mut();
+        viewport
+            .camera_controller
+            .move_camera(&mut viewport.camera, distance, zenith, azimuth);
+        self.write_camera_uniform();
+    }
+
+    /// Pan the active camera, applied gradually by `update` while inertia is enabled
+    pub fn pan_camera(&mut self, dx: f32, dy: f32) {
+        let viewport = self.viewport_mut();
+        viewport
+            .camera_controller
+            .pan_camera(&mut viewport.camera, dx, dy);
+        self.write_camera_uniform();
+    }
+
+    /// Size of the render target in pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    /// Rotate the active camera for a drag from `from` to `to` in pixels of the render target
+    ///
+    /// The turntable maps the full width of the viewport to a half turn, the trackball rolls a
+    /// virtual sphere under the cursor.
+    pub fn drag_camera(&mut self, from: Vec2, to: Vec2) {
+        let cell = self.viewport_cell(self.active);
+        let origin = Vec2::new(cell.x as f32, cell.y as f32);
+        let size = Vec2::new(cell.width.max(1) as f32, cell.height.max(1) as f32);
+        match self.viewport().camera.mode {
+            camera::CameraMode::Turntable => {
+                let delta = (to - from) / size;
+                self.move_camera(0.0, -delta.y * PI, -delta.x * PI);
+            }
+            camera::CameraMode::Trackball => {
+                let to_ndc = |p: Vec2| {
+                    let p = (p - origin) / size;
+                    Vec2::new(2.0 * p.x - 1.0, 1.0 - 2.0 * p.y)
+                };
+                let viewport = self.viewport_mut();
+                let rotation = viewport.camera.trackball_rotation(to_ndc(from), to_ndc(to));
+                viewport
+                    .camera_controller
+                    .rotate_camera(&mut viewport.camera, rotation);
+                self.write_camera_uniform();
+            }
+        }
+    }
+
+    /// Apply a command recognized by `input::Gestures` to the active viewport
+    ///
+    /// `Action::SaveState` depends on the host and is left to the caller.
+    pub fn apply_command(&mut self, command: Command) -> Result<(), Error> {
+        let cell = self.viewport_cell(self.active);
+        let size = Vec2::new(cell.width.max(1) as f32, cell.height.max(1) as f32);
+
+        match command {
+            Command::Rotate { from, to } => self.drag_camera(from, to),
+            Command::Pan(delta) => {
+                let delta = delta / size;
+                self.pan_camera(delta.x, delta.y);
+            }
+            Command::Dolly(distance) => self.move_camera(distance, 0.0, 0.0),
+            Command::Pinch { dolly, pan } => {
+                let pan = pan / size;
+                self.move_camera(dolly, 0.0, 0.0);
+                self.pan_camera(pan.x, pan.y);
+            }
+            Command::Action(action) => self.apply_action(action)?,
+        }
+        Ok(())
+    }
+
+    fn apply_action(&mut self, action: Action) -> Result<(), Error> {
+        match action {
+            Action::ResetCamera => self.reset_camera(),
+            Action::FitToSurface => {
+                let bounds = self.surface_bounds();
+                self.fit_to_bounds(&bounds);
+            }
+            Action::SetView(view) => self.set_view(view),
+            Action::ToggleProjection => self.set_projection(match self.projection() {
+                camera::Projection::Perspective => camera::Projection::Orthographic,
+                camera::Projection::Orthographic => camera::Projection::Perspective,
+            }),
+            Action::ToggleCameraMode => self.set_camera_mode(match self.camera_mode() {
+                camera::CameraMode::Turntable => camera::CameraMode::Trackball,
+                camera::CameraMode::Trackball => camera::CameraMode::Turntable,
+            }),
+            Action::ToggleColorbar => self.set_colorbar_visible(!self.is_colorbar_visible()),
+            Action::ToggleMultisampling => {
+                self.set_multisampling_enabled(!self.is_multisampling_enabled())
+            }
+            Action::SaveState => {}
+            Action::SetResolution(resolution) => {
+                self.set_grid_resolution(resolution, resolution)?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn camera_mode(&self) -> camera::CameraMode {
+        self.viewport().camera.mode
+    }
+
+    /// Switch between turntable and trackball rotation, stopping any motion in progress
+    pub fn set_camera_mode(&mut self, mode: camera::CameraMode) {
+        let viewport = self.viewport_mut();
+        viewport.camera_controller.stop();
+        viewport.camera.set_mode(mode);
+        self.write_camera_uniform();
+    }
+
+    pub fn is_inertia_enabled(&self) -> bool {
+        self.viewport().camera_controller.is_enabled()
+    }
+
+    /// Applies to every viewport, as do the friction and maximum speed
+    pub fn set_inertia_enabled(&mut self, enabled: bool) {
+        for viewport in &mut self.viewports {
+            viewport.camera_controller.set_enabled(enabled);
+        }
+    }
+
+    pub fn set_camera_friction(&mut self, friction: f32) {
+        for viewport in &mut self.viewports {
+            viewport.camera_controller.set_friction(friction);
+        }
+    }
+
+    pub fn set_camera_max_speed(&mut self, max_speed: f32) {
+        for viewport in &mut self.viewports {
+            viewport.camera_controller.set_max_speed(max_speed);
+        }
+    }
+
+    pub fn set_view(&mut self, view: camera::View) {
+        let viewport = self.viewport_mut();
+        viewport.camera_controller.stop();
+        viewport.camera.set_view(view);
+        self.write_camera_uniform();
+    }
+
+    pub fn reset_camera(&mut self) {
+        let viewport = self.viewport_mut();
+        viewport.camera_controller.stop();
+        viewport.camera.reset();
+        self.write_camera_uniform();
+    }
+
+    pub fn fit_to_bounds(&mut self, bounds: &camera::Aabb) {
+        let viewport = self.viewport_mut();
+        viewport.camera_controller.stop();
+        viewport.camera.fit_to_bounds(bounds);
+        self.write_camera_uniform();
+    }
+
+    /// Bounds of the active viewport's surface
+    ///
+    /// Heights fall back to the colour mapping's range until they have been read back.
+    pub fn surface_bounds(&mut self) -> camera::Aabb {
+        let viewport = &mut self.viewports[self.active];
+        viewport.surface_bounds(&self.device)
+    }
+
+    pub fn projection(&self) -> camera::Projection {
+        self.viewport().camera.projection
+    }
+
+    pub fn set_projection(&mut self, projection: camera::Projection) {
+        self.viewport_mut().camera.set_projection(projection);
+    }
+
+    /// Advance animations by `dt` seconds, returning whether another frame is needed
+    pub fn update(&mut self, dt: f32) -> bool {
+        let mut animating = false;
+        for viewport in &mut self.viewports {
+            animating |= viewport.camera_controller.update(&mut viewport.camera, dt);
+            animating |= viewport.camera.update(dt);
+        }
+        self.write_camera_uniform();
+        animating
+    }
+
+    /// Upload every camera, after making them follow the active one while they are linked
+    fn write_camera_uniform(&mut self) {
+        if self.cameras_linked {
+            let leader = self.viewport().camera.clone();
+            for viewport in &mut self.viewports {
+                viewport.camera.follow(&leader);
+            }
+        }
+        for viewport in &self.viewports {
+            viewport.write_camera_uniform(&self.queue);
+        }
     }
 
     pub fn is_multisampling_enabled(&self) -> bool {
@@ -497,17 +1895,44 @@ impl State {
         // Technically a resize for the render targets
         self.resize(self.config.width, self.config.height);
 
-        let render_pipeline = Self::create_render_pipeline(
+        [self.render_pipeline, self.transparent_pipeline] = self.surface_pipelines(sample_count);
+        self.marker_renderer
+            .set_sample_count(&self.device, sample_count);
+        self.curve_renderer
+            .set_sample_count(&self.device, sample_count);
+    }
+
+    pub fn is_colorbar_visible(&self) -> bool {
+        self.colorbar.is_visible()
+    }
+
+    pub fn set_colorbar_visible(&mut self, visible: bool) {
+        self.colorbar.set_visible(visible);
+    }
+
+    pub fn set_colorbar_corner(&mut self, corner: overlay::Corner) {
+        self.colorbar.set_corner(corner);
+        self.colorbar.update(
             &self.device,
-            &self.config,
-            Some(&self.render_pipeline_layout),
-            &self.render_shader_module,
-            Some("vs_main"),
-            Some("fs_main"),
-            sample_count,
+            &self.queue,
+            self.config.width,
+            self.config.height,
         );
+    }
 
-        self.render_pipeline = render_pipeline;
+    pub fn colorbar_ticks(&self) -> u32 {
+        self.colorbar.ticks()
+    }
+
+    /// Label `ticks` values on the colorbar, clamped to `2..=MAX_COLORBAR_TICKS`
+    pub fn set_colorbar_ticks(&mut self, ticks: u32) {
+        self.colorbar.set_ticks(ticks);
+        self.colorbar.update(
+            &self.device,
+            &self.queue,
+            self.config.width,
+            self.config.height,
+        );
     }
 }
 
@@ -532,69 +1957,3 @@ impl Vertex {
         }
     }
 }
-
-#[wasm_bindgen]
-pub struct JsApp {
-    inner: State,
-}
-
-#[wasm_bindgen]
-impl JsApp {
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.inner.resize(width, height);
-    }
-
-    pub fn move_camera(&mut self, distance: f32, zenith: f32, azimuth: f32) {
-        self.inner.move_camera(distance, zenith * PI, azimuth * PI);
-    }
-
-    pub fn render(&mut self) {
-        self.inner.render();
-    }
-
-    pub fn is_multisampling_enabled(&self) -> bool {
-        self.inner.is_multisampling_enabled()
-    }
-
-    pub fn set_multisampling_enabled(&mut self, enabled: bool) {
-        self.inner.set_multisampling_enabled(enabled);
-        self.inner.render();
-    }
-
-    pub fn set_grid_resolution(&mut self, width: u32, height: u32) {
-        self.inner.set_grid_resolution(width, height);
-        self.inner.render();
-    }
-}
-
-// Wasm bindgen currently does not support async constructors
-#[wasm_bindgen]
-pub async fn start_app(canvas: HtmlCanvasElement) -> JsApp {
-    console_log::init().expect("Could not initiate logging");
-    console_error_panic_hook::set_once();
-
-    let width = canvas.width();
-    let height = canvas.height();
-
-    log::info!("New app at resolution {width}, {height}");
-
-    // Create a wgpu instance
-    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
-
-    let surface = instance
-        .create_surface(wgpu::SurfaceTarget::Canvas(canvas))
-        .expect("Could not create surface");
-
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::None,
-            force_fallback_adapter: false,
-            compatible_surface: Some(&surface),
-        })
-        .await
-        .expect("Could not get appropriate adapter");
-
-    JsApp {
-        inner: State::new(width, height, adapter, surface).await,
-    }
-}
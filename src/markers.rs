@@ -0,0 +1,387 @@
+//! Scatter plot layer of markers at 3D points, drawn with a constant size on screen
+//!
+//! Markers are instanced quads sharing the surface's camera bind group and depth buffer, so
+//! measurements overlaid on a fitted surface are hidden where it passes in front of them.
+
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+/// Shape every marker of the layer is drawn as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MarkerShape {
+    #[default]
+    Circle,
+    Square,
+    /// Shaded circle with the depth of a sphere
+    Sphere,
+}
+
+impl MarkerShape {
+    /// Must match the SHAPE_* constants in markers.wgsl
+    fn index(self) -> u32 {
+        match self {
+            Self::Circle => 0,
+            Self::Square => 1,
+            Self::Sphere => 2,
+        }
+    }
+}
+
+impl FromStr for MarkerShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "circle" => Ok(Self::Circle),
+            "square" => Ok(Self::Square),
+            "sphere" => Ok(Self::Sphere),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A point of the scatter plot
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Marker {
+    pub position: [f32; 3],
+    /// Diameter in pixels
+    pub size: f32,
+    pub color: [f32; 3],
+}
+
+impl Marker {
+    pub const DEFAULT_SIZE: f32 = 8.0;
+    pub const DEFAULT_COLOR: [f32; 3] = [1.0, 0.3, 0.1];
+
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32, 2 => Float32x3];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: core::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+
+    /// Markers from flat arrays of x, y, z positions, r, g, b colours and sizes
+    ///
+    /// Colours and sizes may be empty for the defaults. Returns `None` if the lengths do not
+    /// match.
+    pub fn from_arrays(positions: &[f32], colors: &[f32], sizes: &[f32]) -> Option<Vec<Self>> {
+        let count = positions.len() / 3;
+        if !positions.len().is_multiple_of(3)
+            || !(colors.is_empty() || colors.len() == positions.len())
+            || !(sizes.is_empty() || sizes.len() == count)
+        {
+            return None;
+        }
+
+        Some(
+            (0..count)
+                .map(|i| Self {
+                    position: [positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]],
+                    size: sizes.get(i).copied().unwrap_or(Self::DEFAULT_SIZE),
+                    color: colors
+                        .get(3 * i..3 * i + 3)
+                        .map_or(Self::DEFAULT_COLOR, |c| [c[0], c[1], c[2]]),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MarkerUniform {
+    viewport: [f32; 2],
+    pixel_scale: f32,
+    shape: u32,
+}
+
+/// Pipeline shared by the marker layers of every viewport
+pub struct MarkerRenderer {
+    module: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl MarkerRenderer {
+    /// Draw layers with the camera bind group at group 0
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        sample_count: u32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::include_wgsl!("markers.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Marker bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Marker pipeline layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Self::build_pipeline(
+            device,
+            &module,
+            &pipeline_layout,
+            format,
+            depth_format,
+            depth_compare,
+            sample_count,
+        );
+
+        Self {
+            module,
+            pipeline_layout,
+            bind_group_layout,
+            format,
+            depth_format,
+            depth_compare,
+            pipeline,
+        }
+    }
+
+    fn build_pipeline(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Marker pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_main"),
+                buffers: &[Marker::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: true,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    /// Pipeline for render targets with another sample count than the renderer's own
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        Self::build_pipeline(
+            device,
+            &self.module,
+            &self.pipeline_layout,
+            self.format,
+            self.depth_format,
+            self.depth_compare,
+            sample_count,
+        )
+    }
+
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = self.create_pipeline(device, sample_count);
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+}
+
+/// Markers of one viewport
+pub struct MarkerLayer {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    uniform: MarkerUniform,
+    /// Kept to upload again after losing the device
+    markers: Vec<Marker>,
+    instance_buffer: Option<wgpu::Buffer>,
+}
+
+impl MarkerLayer {
+    pub fn new(device: &wgpu::Device, renderer: &MarkerRenderer) -> Self {
+        let uniform = MarkerUniform {
+            viewport: [1.0, 1.0],
+            pixel_scale: 1.0,
+            shape: MarkerShape::default().index(),
+        };
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Marker uniform buffer"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Marker bind group"),
+            layout: &renderer.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            uniform,
+            markers: Vec::new(),
+            instance_buffer: None,
+        }
+    }
+
+    /// Set the size of the render target
+    pub fn set_viewport(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.uniform.viewport = [width as f32, height as f32];
+        self.write_uniform(queue);
+    }
+
+    /// Draw the next submission into a `width` by `height` target with `pixel_scale` rendered
+    /// pixels per marker pixel, until `set_viewport` or `set_shape`
+    pub fn write_viewport(&self, queue: &wgpu::Queue, width: u32, height: u32, pixel_scale: f32) {
+        let uniform = MarkerUniform {
+            viewport: [width as f32, height as f32],
+            pixel_scale,
+            ..self.uniform
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn shape(&self) -> MarkerShape {
+        match self.uniform.shape {
+            1 => MarkerShape::Square,
+            2 => MarkerShape::Sphere,
+            _ => MarkerShape::Circle,
+        }
+    }
+
+    pub fn set_shape(&mut self, queue: &wgpu::Queue, shape: MarkerShape) {
+        self.uniform.shape = shape.index();
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Replace every marker, an empty slice clears the layer
+    pub fn set_markers(&mut self, device: &wgpu::Device, markers: &[Marker]) {
+        if let Some(buffer) = self.instance_buffer.take() {
+            buffer.destroy();
+        }
+        self.markers = markers.to_vec();
+        self.instance_buffer = (!markers.is_empty()).then(|| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Marker instance buffer"),
+                contents: bytemuck::cast_slice(markers),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+    }
+
+    /// Draw into a pass whose camera bind group is set, with `pipeline` matching its targets
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        let Some(instance_buffer) = &self.instance_buffer else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, instance_buffer.slice(..));
+        render_pass.draw(0..4, 0..self.markers.len() as u32);
+    }
+
+    pub fn destroy(&self) {
+        self.uniform_buffer.destroy();
+        if let Some(buffer) = &self.instance_buffer {
+            buffer.destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn builds_markers_from_arrays() {
+        let positions = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let markers = Marker::from_arrays(&positions, &[], &[4.0, 12.0]).unwrap();
+
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[1].position, [3.0, 4.0, 5.0]);
+        assert_eq!(markers[1].size, 12.0);
+        assert_eq!(markers[0].color, Marker::DEFAULT_COLOR);
+
+        let colors = [0.0, 0.5, 1.0, 1.0, 1.0, 1.0];
+        let markers = Marker::from_arrays(&positions, &colors, &[]).unwrap();
+        assert_eq!(markers[0].color, [0.0, 0.5, 1.0]);
+        assert_eq!(markers[0].size, Marker::DEFAULT_SIZE);
+
+        assert_eq!(Marker::from_arrays(&positions[..4], &[], &[]), None);
+        assert_eq!(Marker::from_arrays(&positions, &colors[..3], &[]), None);
+        assert_eq!(Marker::from_arrays(&positions, &[], &[1.0]), None);
+    }
+
+    #[test]
+    fn parses_shapes() {
+        assert_eq!("sphere".parse(), Ok(MarkerShape::Sphere));
+        assert_eq!("star".parse::<MarkerShape>(), Err(()));
+    }
+}
@@ -0,0 +1,97 @@
+// Instanced markers of a constant size on screen, depth tested against the surface
+
+// The matrices at the start of the camera uniform, see shader.wgsl for the rest
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+};
+
+struct MarkerUniform {
+    // Size of the render target in pixels
+    viewport: vec2f,
+    // Rendered pixels per marker pixel, above 1 when supersampling
+    pixel_scale: f32,
+    shape: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> markers: MarkerUniform;
+
+// Must match `MarkerShape`
+const SHAPE_CIRCLE = 0u;
+const SHAPE_SQUARE = 1u;
+const SHAPE_SPHERE = 2u;
+
+// Towards the upper left of the viewer, in view space
+const LIGHT = vec3f(-0.408, 0.408, 0.816);
+
+struct MarkerInput {
+    @location(0) position: vec3f,
+    // Diameter in pixels
+    @location(1) size: f32,
+    @location(2) color: vec3f,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    // -1 to 1 across the marker
+    @location(0) corner: vec2f,
+    @location(1) color: vec3f,
+    // Clip z and w of the centre, and how they change at the front of a sphere
+    @location(2) @interpolate(flat) depth: vec4f,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, marker: MarkerInput) -> VertexOutput {
+    // Triangle strip of the four corners
+    let corner = vec2f(f32(index & 1u), f32(index >> 1u)) * 2.0 - 1.0;
+
+    let view_position = camera.view * vec4f(marker.position, 1.0);
+    let center = camera.proj * view_position;
+    let half_size = 0.5 * marker.size * markers.pixel_scale;
+    let offset = corner * half_size * 2.0 / markers.viewport;
+
+    // Radius in view space of a sphere covering the marker, moved towards the viewer
+    let radius = half_size * 2.0 / markers.viewport.x * center.w / camera.proj[0][0];
+    let front = camera.proj * (view_position + vec4f(0.0, 0.0, radius, 0.0));
+
+    var out: VertexOutput;
+    out.clip_position = center + vec4f(offset * center.w, 0.0, 0.0);
+    out.corner = corner;
+    out.color = marker.color;
+    out.depth = vec4f(center.zw, front.zw - center.zw);
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4f,
+    @builtin(frag_depth) depth: f32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let r2 = dot(in.corner, in.corner);
+    if markers.shape != SHAPE_SQUARE && r2 > 1.0 {
+        discard;
+    }
+
+    // Flat markers face the viewer at the front of their bounding sphere, so that markers on
+    // the surface are not cut by it
+    var color = in.color;
+    var bulge = 1.0;
+    if markers.shape == SHAPE_SPHERE {
+        let normal = vec3f(in.corner, sqrt(1.0 - r2));
+        color *= 0.3 + 0.7 * max(dot(normal, LIGHT), 0.0);
+        bulge = normal.z;
+    }
+
+    let clip = in.depth.xy + in.depth.zw * bulge;
+    var out: FragmentOutput;
+    out.color = vec4f(color, 1.0);
+    out.depth = clip.x / clip.y;
+    return out;
+}
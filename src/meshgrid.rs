@@ -2,9 +2,70 @@
 use log::info;
 
 use bytemuck::bytes_of;
-use core::ops::RangeInclusive;
+use core::{fmt, ops::RangeInclusive};
 
-use wgpu::{self, include_wgsl};
+use wgpu::{
+    self, include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::scatter::{Interpolation, InterpolationSamples, ScatterError};
+
+#[cfg(feature = "readback")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "readback")]
+use crate::{error::Error, readback::BufferReadback};
+
+/// Values mapped to black and white by the greyscale colour mapping of evaluated and
+/// interpolated heights
+///
+/// Must match `COLOR_MIN` and `COLOR_MAX` in evaluator.wgsl and interpolate.wgsl.
+pub const COLOR_RANGE: RangeInclusive<f32> = -0.5..=2.0;
+
+/// Number of `f32` elements per vertex in the vertex buffer, `ELEMENT_SIZE` in the shaders
+pub const VERTEX_ELEMENTS: usize = 6;
+
+/// Side length of the square workgroups of gen_vertex.wgsl and gen_index.wgsl
+const GENERATOR_WORKGROUP_SIZE: u32 = 16;
+
+/// Workgroup size of the evaluator entry points
+const EVALUATOR_WORKGROUP_SIZE: u32 = 256;
+
+/// Bytes per index in the index buffer
+const INDEX_SIZE: u64 = 4;
+
+/// Why a grid resolution cannot be generated on the current device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolutionError {
+    /// Grids need at least 2 vertices along each axis to form a triangle
+    TooSmall((u32, u32)),
+    /// A vertex or index buffer would exceed the device's buffer size limits
+    BufferTooLarge { bytes: u64, max: u64 },
+    /// A compute dispatch would exceed `max_compute_workgroups_per_dimension`
+    TooManyWorkgroups { workgroups: u64, max: u32 },
+}
+
+impl fmt::Display for ResolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooSmall((width, height)) => write!(
+                f,
+                "Grid resolution {width}x{height} is too small, both dimensions must be at least 2"
+            ),
+            Self::BufferTooLarge { bytes, max } => write!(
+                f,
+                "Grid needs a {bytes} byte buffer but the device allows at most {max} bytes"
+            ),
+            Self::TooManyWorkgroups { workgroups, max } => write!(
+                f,
+                "Grid needs {workgroups} workgroups but the device allows at most {max} per dimension"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ResolutionError {}
 
 pub struct Generator {
     device: wgpu::Device,
@@ -22,6 +83,9 @@ pub struct GridBuffers {
     pub index_buffer: wgpu::Buffer,
     pub index_count: u32,
     pub index_format: wgpu::IndexFormat,
+    pub resolution: (u32, u32),
+    pub x_range: RangeInclusive<f32>,
+    pub y_range: RangeInclusive<f32>,
     evaluator_dispatch_count: u32,
     evaluator_bind_group: wgpu::BindGroup,
 }
@@ -33,6 +97,48 @@ impl GridBuffers {
     }
 }
 
+/// Buffer sizes and dispatch counts of a grid, in 64 bits so large grids do not overflow
+struct GridSizes {
+    vertex_bytes: u64,
+    index_count: u64,
+    index_bytes: u64,
+    generator_workgroups: (u64, u64),
+    evaluator_workgroups: u64,
+}
+
+impl GridSizes {
+    fn new((width, height): (u32, u32)) -> Self {
+        let (width, height) = (width as u64, height as u64);
+        // Saturate rather than wrap so absurd resolutions still fail validation
+        let vertex_count = width.saturating_mul(height);
+        let index_count = (width.saturating_sub(1) * height.saturating_sub(1)).saturating_mul(6);
+        let vertex_size = (VERTEX_ELEMENTS * core::mem::size_of::<f32>()) as u64;
+
+        Self {
+            vertex_bytes: vertex_count.saturating_mul(vertex_size),
+            index_count,
+            index_bytes: index_count.saturating_mul(INDEX_SIZE),
+            // The generator shaders walk the height along x and the width along y
+            generator_workgroups: (
+                height.div_ceil(GENERATOR_WORKGROUP_SIZE as u64),
+                width.div_ceil(GENERATOR_WORKGROUP_SIZE as u64),
+            ),
+            evaluator_workgroups: vertex_count.div_ceil(EVALUATOR_WORKGROUP_SIZE as u64),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InterpolatorUniform {
+    count: u32,
+    method: u32,
+    power: f32,
+    radius: f32,
+    mean: f32,
+    _padding: [u32; 3],
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct GeneratorUniform {
@@ -140,32 +246,76 @@ impl Generator {
         }
     }
 
+    /// Check a resolution against the device's limits before allocating anything
+    pub fn validate_resolution(&self, grid_resolution: (u32, u32)) -> Result<(), ResolutionError> {
+        let (width, height) = grid_resolution;
+        if width < 2 || height < 2 {
+            return Err(ResolutionError::TooSmall(grid_resolution));
+        }
+
+        let limits = self.device.limits();
+        let max_bytes = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        let max_workgroups = limits.max_compute_workgroups_per_dimension;
+
+        let sizes = GridSizes::new(grid_resolution);
+        for bytes in [sizes.vertex_bytes, sizes.index_bytes] {
+            if bytes > max_bytes {
+                return Err(ResolutionError::BufferTooLarge {
+                    bytes,
+                    max: max_bytes,
+                });
+            }
+        }
+
+        let workgroups = [
+            sizes.generator_workgroups.0,
+            sizes.generator_workgroups.1,
+            sizes.evaluator_workgroups,
+        ];
+        for workgroups in workgroups {
+            if workgroups > max_workgroups as u64 {
+                return Err(ResolutionError::TooManyWorkgroups {
+                    workgroups,
+                    max: max_workgroups,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Largest square resolution the device can generate
+    pub fn max_square_resolution(&self) -> u32 {
+        // Every limit grows monotonically with the resolution so bisect for the boundary
+        let (mut valid, mut invalid) = (2, u32::MAX);
+        while invalid - valid > 1 {
+            let mid = valid + (invalid - valid) / 2;
+            if self.validate_resolution((mid, mid)).is_ok() {
+                valid = mid;
+            } else {
+                invalid = mid;
+            }
+        }
+        valid
+    }
+
     pub fn generate_buffers(
         &self,
         grid_resolution: (u32, u32),
         x_range: RangeInclusive<f32>,
         y_range: RangeInclusive<f32>,
-    ) -> GridBuffers {
-        let grid_leftover = (grid_resolution.0 & 0xf, grid_resolution.1 & 0xf);
-        let grid_chunks = {
-            let width = if grid_leftover.0 > 0 {
-                (grid_resolution.0 >> 4) + 1
-            } else {
-                grid_resolution.0 >> 4
-            };
-            let height = if grid_leftover.1 > 0 {
-                (grid_resolution.1 >> 4) + 1
-            } else {
-                grid_resolution.1 >> 4
-            };
-            (width, height)
-        };
-
-        let vertex_count = grid_resolution.0 * grid_resolution.1;
-        let vertex_byte_count = vertex_count * 4 * 6;
-
-        let index_count = (grid_resolution.0 - 1) * (grid_resolution.1 - 1) * 6;
-        let index_byte_count = index_count * 4;
+    ) -> Result<GridBuffers, ResolutionError> {
+        self.validate_resolution(grid_resolution)?;
+
+        // Validation guarantees these fit the device's 32 bit limits
+        let sizes = GridSizes::new(grid_resolution);
+        let grid_chunks = (
+            sizes.generator_workgroups.0 as u32,
+            sizes.generator_workgroups.1 as u32,
+        );
+        let index_count = sizes.index_count as u32;
 
         let uniform_data = GeneratorUniform {
             resolution: [grid_resolution.0, grid_resolution.1],
@@ -178,7 +328,7 @@ impl Generator {
 
         let vertex_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("GridBuffer vertex buffer"),
-            size: vertex_byte_count as u64,
+            size: sizes.vertex_bytes,
             usage: wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::STORAGE
@@ -188,7 +338,7 @@ impl Generator {
 
         let index_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("GridBuffer index buffer"),
-            size: index_byte_count as u64,
+            size: sizes.index_bytes,
             usage: wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::STORAGE
@@ -257,20 +407,19 @@ impl Generator {
             }],
         });
 
-        let evaluator_dispatch_count = if vertex_count & 0xff > 0 {
-            (vertex_count >> 8) + 1
-        } else {
-            vertex_count >> 8
-        };
+        let evaluator_dispatch_count = sizes.evaluator_workgroups as u32;
 
-        GridBuffers {
+        Ok(GridBuffers {
             evaluator_bind_group,
             vertex_buffer,
             index_buffer,
             index_count,
             evaluator_dispatch_count,
             index_format: wgpu::IndexFormat::Uint32,
-        }
+            resolution: grid_resolution,
+            x_range,
+            y_range,
+        })
     }
 
     pub fn create_evaluator(
@@ -295,88 +444,89 @@ impl Generator {
         }
     }
 
-    #[cfg(feature = "readback")]
-    pub async fn print_vertices(&self, buffers: &GridBuffers) {
-        let n_staging_bytes = buffers.vertex_buffer.size();
-
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: n_staging_bytes,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    /// Evaluator resampling scattered points onto grids, see interpolate.wgsl
+    pub fn create_interpolator(&self) -> Interpolator {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            self.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Interpolator bind group layout"),
+                    entries: &[
+                        storage(0, false),
+                        storage(1, true),
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
 
-        let mut encoder = self
+        let pipeline_layout = self
             .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-        encoder.copy_buffer_to_buffer(
-            &buffers.vertex_buffer,
-            0,
-            &staging_buffer,
-            0,
-            n_staging_bytes,
-        );
-        self.queue.submit([encoder.finish()]);
-
-        info!("Mapping vertex buffer");
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Interpolator pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let module = self
+            .device
+            .create_shader_module(include_wgsl!("interpolate.wgsl"));
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Interpolator compute pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some("interpolate"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
 
-        let (tx, rx) = futures::channel::oneshot::channel();
-        staging_buffer.map_async(wgpu::MapMode::Read, 0..n_staging_bytes, move |res| {
-            let _ = tx.send(res);
-        });
-        rx.await
-            .expect("Could not get channel data")
-            .expect("Could not map buffer");
-        {
-            let mapped = staging_buffer.get_mapped_range(0..n_staging_bytes);
-            let uints: &[f32] = bytemuck::cast_slice(&mapped);
-            for (i, vtx) in uints.chunks(6).enumerate() {
-                info!("{i}: {:.2?}", vtx);
-            }
+        Interpolator {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            pipeline,
+            bind_group_layout,
         }
-        staging_buffer.unmap();
     }
 
     #[cfg(feature = "readback")]
-    pub async fn print_indices(&self, buffers: &GridBuffers) {
-        let n_staging_bytes = buffers.index_buffer.size();
-
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: n_staging_bytes,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    pub async fn print_vertices(&self, buffers: &GridBuffers) -> Result<(), Error> {
+        info!("Mapping vertex buffer");
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-        encoder.copy_buffer_to_buffer(
-            &buffers.index_buffer,
-            0,
-            &staging_buffer,
-            0,
-            n_staging_bytes,
-        );
-        self.queue.submit([encoder.finish()]);
+        let readback = BufferReadback::new(&self.device, &self.queue, &buffers.vertex_buffer);
+        let vertices: Vec<f32> = readback.read().await?;
+        for (i, vtx) in vertices.chunks(VERTEX_ELEMENTS).enumerate() {
+            info!("{i}: {:.2?}", vtx);
+        }
+        Ok(())
+    }
 
+    #[cfg(feature = "readback")]
+    pub async fn print_indices(&self, buffers: &GridBuffers) -> Result<(), Error> {
         info!("Mapping index buffer");
 
-        let (tx, rx) = futures::channel::oneshot::channel();
-        staging_buffer.map_async(wgpu::MapMode::Read, 0..n_staging_bytes, move |res| {
-            let _ = tx.send(res);
-        });
-        rx.await
-            .expect("Could not get channel data")
-            .expect("Could not map buffer");
-        {
-            let mapped = staging_buffer.get_mapped_range(0..n_staging_bytes);
-            let uints: &[u32] = bytemuck::cast_slice(&mapped);
-            for (i, idx) in uints.chunks(6).enumerate() {
-                info!("{i}: {:.2?}", idx);
-            }
+        let readback = BufferReadback::new(&self.device, &self.queue, &buffers.index_buffer);
+        let indices: Vec<u32> = readback.read().await?;
+        for (i, idx) in indices.chunks(6).enumerate() {
+            info!("{i}: {:.2?}", idx);
         }
-        staging_buffer.unmap();
+        Ok(())
     }
 }
 
@@ -410,3 +560,107 @@ impl Evaluator {
         self.queue.submit([encoder.finish()]);
     }
 }
+
+/// Evaluator that resamples scattered points instead of evaluating a function
+pub struct Interpolator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+/// Scattered points uploaded for interpolation, along with the samples to upload them again from
+pub struct InterpolatedPoints {
+    pub samples: InterpolationSamples,
+    point_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl InterpolatedPoints {
+    pub fn destroy(&self) {
+        self.point_buffer.destroy();
+        self.uniform_buffer.destroy();
+    }
+}
+
+impl Interpolator {
+    pub fn upload(
+        &self,
+        samples: InterpolationSamples,
+    ) -> Result<InterpolatedPoints, ScatterError> {
+        let points: &[f32] = bytemuck::cast_slice(&samples.points);
+        let bytes = core::mem::size_of_val(points) as u64;
+        let max = self.device.limits().max_storage_buffer_binding_size as u64;
+        if bytes > max {
+            return Err(ScatterError::BufferTooLarge { bytes, max });
+        }
+
+        let (method, power, radius) = match samples.interpolation {
+            Interpolation::InverseDistance { power, radius } => (0, power, radius),
+            Interpolation::GaussianRbf { radius } => (1, 0.0, radius),
+        };
+        let uniform = InterpolatorUniform {
+            count: samples.points.len() as u32,
+            method,
+            power,
+            radius,
+            mean: samples.mean,
+            _padding: [0; 3],
+        };
+
+        let point_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Interpolator point buffer"),
+            contents: bytemuck::cast_slice(points),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let uniform_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Interpolator uniform buffer"),
+            contents: bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        Ok(InterpolatedPoints {
+            samples,
+            point_buffer,
+            uniform_buffer,
+        })
+    }
+
+    pub fn interpolate(&self, points: &InterpolatedPoints, grid_buffers: &GridBuffers) {
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Interpolator bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: grid_buffers.vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: points.point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: points.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Interpolator encoder"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Interpolator compute pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            // Same workgroup size as the evaluator entry points
+            pass.dispatch_workgroups(grid_buffers.evaluator_dispatch_count, 1, 1);
+        }
+        self.queue.submit([encoder.finish()]);
+    }
+}
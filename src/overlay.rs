@@ -0,0 +1,413 @@
+use alloc::{format, string::String, vec::Vec};
+use core::str::FromStr;
+
+use crate::meshgrid;
+
+/// Screen corner the colorbar is anchored to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Self::TopLeft),
+            "top-right" => Ok(Self::TopRight),
+            "bottom-left" => Ok(Self::BottomLeft),
+            "bottom-right" => Ok(Self::BottomRight),
+            _ => Err(()),
+        }
+    }
+}
+
+// Must match the MODE_* constants in overlay.wgsl
+const MODE_SOLID: u32 = 0;
+const MODE_GRADIENT: u32 = 1;
+const MODE_GLYPH: u32 = 2;
+const MODE_PANEL: u32 = 3;
+
+const GLYPH_WIDTH: f32 = 3.0;
+const GLYPH_HEIGHT: f32 = 5.0;
+
+/// 3x5 bitmap font, one row of three bits per line starting from the top
+fn glyph(c: char) -> Option<u32> {
+    let bits = match c {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        '.' => 0b000_000_000_000_010,
+        '-' => 0b000_000_111_000_000,
+        _ => return None,
+    };
+    Some(bits)
+}
+
+/// Labels of `ticks` evenly spaced values across the colour range, `ticks` being at least 2
+///
+/// Uses the fewest decimals that represent every tick exactly, falling back to two significant
+/// digits of the tick spacing for awkward ranges.
+fn labels(ticks: u32) -> Vec<String> {
+    let (min, max) = (*meshgrid::COLOR_RANGE.start(), *meshgrid::COLOR_RANGE.end());
+    let step = (max - min) / (ticks - 1) as f32;
+
+    let is_round = |value: f32, scale: f32| {
+        let scaled = value * scale;
+        (scaled - libm::roundf(scaled)).abs() < 1e-3
+    };
+    let decimals = (0..=3)
+        .find(|&d| {
+            let scale = libm::powf(10.0, d as f32);
+            is_round(min, scale) && is_round(step, scale)
+        })
+        .unwrap_or_else(|| {
+            let magnitude = libm::floorf(libm::log10f(step.abs().max(f32::MIN_POSITIVE)));
+            (1.0 - magnitude).clamp(0.0, 4.0) as usize
+        });
+
+    (0..ticks)
+        .map(|i| {
+            let value = min + step * i as f32;
+            let label = format!("{value:.decimals$}");
+            // Avoid printing "-0.0" for values that round to zero
+            if label.bytes().all(|b| matches!(b, b'-' | b'0' | b'.')) {
+                label.trim_start_matches('-').into()
+            } else {
+                label
+            }
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    mode: u32,
+    glyph: u32,
+}
+
+impl OverlayVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32, 3 => Uint32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use core::mem::size_of;
+
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Accumulates quads in pixel coordinates and converts them to NDC
+struct QuadBuilder {
+    /// Pixel rectangle of the target mapped to normalized device coordinates
+    region: [f32; 4],
+    vertices: Vec<OverlayVertex>,
+}
+
+impl QuadBuilder {
+    /// Push an axis aligned quad with its top-left corner at `x`, `y` in pixels
+    ///
+    /// The quad's `uv` runs from (0, 0) at the top-left to (1, 1) at the bottom-right, except
+    /// for gradients where `v` is flipped so that it increases upwards.
+    fn quad(&mut self, x: f32, y: f32, w: f32, h: f32, mode: u32, glyph: u32) {
+        let [rx, ry, rw, rh] = self.region;
+        let to_ndc = |px: f32, py: f32| [2.0 * (px - rx) / rw - 1.0, 1.0 - 2.0 * (py - ry) / rh];
+        let (v_top, v_bottom) = if mode == MODE_GRADIENT {
+            (1.0, 0.0)
+        } else {
+            (0.0, 1.0)
+        };
+
+        let tl = OverlayVertex {
+            position: to_ndc(x, y),
+            uv: [0.0, v_top],
+            mode,
+            glyph,
+        };
+        let tr = OverlayVertex {
+            position: to_ndc(x + w, y),
+            uv: [1.0, v_top],
+            mode,
+            glyph,
+        };
+        let bl = OverlayVertex {
+            position: to_ndc(x, y + h),
+            uv: [0.0, v_bottom],
+            mode,
+            glyph,
+        };
+        let br = OverlayVertex {
+            position: to_ndc(x + w, y + h),
+            uv: [1.0, v_bottom],
+            mode,
+            glyph,
+        };
+
+        self.vertices.extend_from_slice(&[tl, bl, tr, tr, bl, br]);
+    }
+}
+
+/// Screen-space legend for the colour mapping applied by the evaluator
+pub struct Colorbar {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_count: u32,
+    ticks: u32,
+    corner: Corner,
+    visible: bool,
+}
+
+impl Colorbar {
+    pub const DEFAULT_TICKS: u32 = 5;
+    /// Most ticks, which keeps the labels a glyph height apart on the shortest bar
+    pub const MAX_TICKS: u32 = 11;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let module = device.create_shader_module(wgpu::include_wgsl!("overlay.wgsl"));
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        // The overlay is drawn after the multisampled pass has been resolved so it is always
+        // single sampled and never depth tested
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                buffers: &[OverlayVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            render_pipeline,
+            vertex_buffer: None,
+            vertex_count: 0,
+            ticks: Self::DEFAULT_TICKS,
+            corner: Corner::TopRight,
+            visible: true,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn corner(&self) -> Corner {
+        self.corner
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    pub fn set_corner(&mut self, corner: Corner) {
+        self.corner = corner;
+    }
+
+    /// Set the number of labelled values including the minimum and maximum, clamped to
+    /// `2..=MAX_TICKS`
+    pub fn set_ticks(&mut self, ticks: u32) {
+        self.ticks = ticks.clamp(2, Self::MAX_TICKS);
+    }
+
+    /// Rebuild the overlay geometry for a render target of the given size
+    ///
+    /// Must be called whenever the target is resized or the colorbar settings change.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.update_region(device, queue, width, height, [0, 0, width, height]);
+    }
+
+    /// Rebuild the overlay geometry for the `[x, y, width, height]` pixel region of an image of
+    /// the given size, drawn into a target covering only that region
+    pub fn update_region(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        region: [u32; 4],
+    ) {
+        use wgpu::util::DeviceExt;
+
+        let (width, height) = (width as f32, height as f32);
+
+        // Size of one font pixel, scaled with the target so the legend stays legible on HiDPI
+        let unit = libm::floorf(width.min(height) / 256.0).max(1.0);
+
+        let labels = labels(self.ticks);
+        let label_chars = labels.iter().map(|l| l.len()).max().unwrap_or(0) as f32;
+
+        let margin = 4.0 * unit;
+        let padding = 3.0 * unit;
+        let bar_width = 4.0 * unit;
+        let bar_height = libm::floorf(height * 0.4).max(GLYPH_HEIGHT * unit * self.ticks as f32);
+        let tick_length = 2.0 * unit;
+        let label_gap = 2.0 * unit;
+        let advance = (GLYPH_WIDTH + 1.0) * unit;
+
+        let panel_width =
+            padding * 2.0 + bar_width + tick_length + label_gap + label_chars * advance - unit;
+        let panel_height = padding * 2.0 + bar_height + GLYPH_HEIGHT * unit;
+
+        let panel_x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin,
+            Corner::TopRight | Corner::BottomRight => width - margin - panel_width,
+        };
+        let panel_y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => margin,
+            Corner::BottomLeft | Corner::BottomRight => height - margin - panel_height,
+        };
+
+        let mut builder = QuadBuilder {
+            region: region.map(|v| v as f32),
+            vertices: Vec::new(),
+        };
+
+        builder.quad(panel_x, panel_y, panel_width, panel_height, MODE_PANEL, 0);
+
+        // Labels are vertically centred on their ticks so leave half a glyph above the bar
+        let bar_x = panel_x + padding;
+        let bar_y = panel_y + padding + GLYPH_HEIGHT * unit * 0.5;
+        builder.quad(bar_x, bar_y, bar_width, bar_height, MODE_GRADIENT, 0);
+
+        for (i, label) in labels.iter().enumerate() {
+            let t = i as f32 / (self.ticks - 1) as f32;
+            let tick_y = libm::floorf(bar_y + bar_height * (1.0 - t));
+
+            builder.quad(
+                bar_x + bar_width,
+                tick_y - unit * 0.5,
+                tick_length,
+                unit,
+                MODE_SOLID,
+                0,
+            );
+
+            let label_x = bar_x + bar_width + tick_length + label_gap;
+            let label_y = libm::floorf(tick_y - GLYPH_HEIGHT * unit * 0.5);
+            for (j, c) in label.chars().enumerate() {
+                if let Some(bits) = glyph(c) {
+                    builder.quad(
+                        label_x + j as f32 * advance,
+                        label_y,
+                        GLYPH_WIDTH * unit,
+                        GLYPH_HEIGHT * unit,
+                        MODE_GLYPH,
+                        bits,
+                    );
+                }
+            }
+        }
+
+        let contents: &[u8] = bytemuck::cast_slice(&builder.vertices);
+        match &self.vertex_buffer {
+            Some(buffer) if buffer.size() == contents.len() as u64 => {
+                queue.write_buffer(buffer, 0, contents);
+            }
+            _ => {
+                if let Some(buffer) = self.vertex_buffer.take() {
+                    buffer.destroy();
+                }
+                self.vertex_buffer = Some(device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Colorbar vertex buffer"),
+                        contents,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    },
+                ));
+            }
+        }
+        self.vertex_count = builder.vertices.len() as u32;
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if !self.visible {
+            return;
+        }
+        if let Some(vertex_buffer) = &self.vertex_buffer {
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.draw(0..self.vertex_count, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    #[test]
+    fn labels_span_the_colour_range() {
+        assert_eq!(labels(2), ["-0.5", "2.0"]);
+        assert_eq!(labels(6), ["-0.5", "0.0", "0.5", "1.0", "1.5", "2.0"]);
+        assert_eq!(
+            labels(Colorbar::DEFAULT_TICKS),
+            ["-0.500", "0.125", "0.750", "1.375", "2.000"]
+        );
+    }
+
+    #[test]
+    fn labels_round_awkward_steps() {
+        // Thirds of 2.5 have no exact decimals, two significant digits of 0.83 are shown
+        assert_eq!(labels(4), ["-0.50", "0.33", "1.17", "2.00"]);
+    }
+
+    #[test]
+    fn most_ticks_have_distinct_labels() {
+        let labels = labels(Colorbar::MAX_TICKS);
+        assert_eq!(labels.len(), Colorbar::MAX_TICKS as usize);
+        assert_eq!([&labels[2], &labels[5]], ["0.00", "0.75"]);
+        assert!(labels.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(
+            labels
+                .iter()
+                .flat_map(|label| label.chars())
+                .all(|c| glyph(c).is_some())
+        );
+    }
+}
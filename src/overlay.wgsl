@@ -0,0 +1,65 @@
+// Screen-space overlay shader
+//
+// Vertices are already in normalized device coordinates so no camera is bound.
+
+struct VertexInput {
+    @location(0) position: vec2f,
+    @location(1) uv: vec2f,
+    @location(2) mode: u32,
+    @location(3) glyph: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) @interpolate(flat) mode: u32,
+    @location(2) @interpolate(flat) glyph: u32,
+};
+
+const MODE_SOLID: u32 = 0;
+const MODE_GRADIENT: u32 = 1;
+const MODE_GLYPH: u32 = 2;
+const MODE_PANEL: u32 = 3;
+
+// Glyphs are 3x5 bitmaps packed row-major into the low 15 bits, top-left in the high bit
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+// Must match the colour mapping in evaluator.wgsl
+fn colormap(t: f32) -> vec3f {
+    return vec3f(clamp(t, 0.0, 1.0));
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4f(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    out.mode = in.mode;
+    out.glyph = in.glyph;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    switch in.mode {
+        case MODE_GRADIENT: {
+            return vec4f(colormap(in.uv.y), 1.0);
+        }
+        case MODE_GLYPH: {
+            let cell = min(vec2u(in.uv * vec2f(f32(GLYPH_WIDTH), f32(GLYPH_HEIGHT))),
+                           vec2u(GLYPH_WIDTH - 1, GLYPH_HEIGHT - 1));
+            let bit = GLYPH_WIDTH * GLYPH_HEIGHT - 1 - (cell.y * GLYPH_WIDTH + cell.x);
+            if ((in.glyph >> bit) & 1) == 0 {
+                discard;
+            }
+            return vec4f(1.0);
+        }
+        case MODE_PANEL: {
+            return vec4f(0.0, 0.0, 0.0, 0.6);
+        }
+        default: {
+            return vec4f(1.0);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use glam::{Vec2, Vec3};
+
+use crate::meshgrid;
+
+/// CPU copy of the evaluated heights of a meshgrid used for ray casting
+pub struct HeightField {
+    resolution: (u32, u32),
+    x_range: RangeInclusive<f32>,
+    y_range: RangeInclusive<f32>,
+    heights: Vec<f32>,
+    z_range: RangeInclusive<f32>,
+}
+
+impl HeightField {
+    /// Maximum number of bisection steps used to refine a hit
+    const REFINE_STEPS: u32 = 20;
+
+    /// Build a height field from the raw contents of a `GridBuffers::vertex_buffer`
+    pub fn from_vertices(
+        resolution: (u32, u32),
+        x_range: RangeInclusive<f32>,
+        y_range: RangeInclusive<f32>,
+        vertices: &[f32],
+    ) -> Self {
+        let heights: Vec<f32> = vertices
+            .chunks_exact(meshgrid::VERTEX_ELEMENTS)
+            .map(|vtx| vtx[2])
+            .collect();
+
+        let (z_min, z_max) = heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &z| {
+                (lo.min(z), hi.max(z))
+            });
+
+        Self {
+            resolution,
+            x_range,
+            y_range,
+            heights,
+            z_range: z_min..=z_max,
+        }
+    }
+
+    /// Lowest and highest evaluated heights
+    pub fn z_range(&self) -> RangeInclusive<f32> {
+        self.z_range.clone()
+    }
+
+    /// Bilinearly interpolated height at `p`, clamped to the grid's domain
+    pub fn height_at(&self, p: Vec2) -> f32 {
+        let (rows, cols) = self.resolution;
+        let to_grid = |v: f32, range: &RangeInclusive<f32>, n: u32| {
+            let t = (v - range.start()) / (range.end() - range.start());
+            (t * (n - 1) as f32).clamp(0.0, (n - 1) as f32)
+        };
+
+        // Rows advance along X and columns along Y, matching gen_vertex.wgsl
+        let u = to_grid(p.x, &self.x_range, rows);
+        let v = to_grid(p.y, &self.y_range, cols);
+
+        let r0 = (libm::floorf(u) as u32).min(rows - 2);
+        let c0 = (libm::floorf(v) as u32).min(cols - 2);
+        let (fu, fv) = (u - r0 as f32, v - c0 as f32);
+
+        let at = |r: u32, c: u32| self.heights[(r * cols + c) as usize];
+        let z0 = at(r0, c0) + (at(r0, c0 + 1) - at(r0, c0)) * fv;
+        let z1 = at(r0 + 1, c0) + (at(r0 + 1, c0 + 1) - at(r0 + 1, c0)) * fv;
+        z0 + (z1 - z0) * fu
+    }
+
+    /// Find the first point where the ray `origin + t * direction` crosses the surface, passing
+    /// through crossings that are not `visible`
+    ///
+    /// The ray is marched at half the grid spacing and the crossing refined by bisection.
+    pub fn intersect(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        visible: impl Fn(Vec3) -> bool,
+    ) -> Option<Vec3> {
+        let (rows, cols) = self.resolution;
+        if rows < 2 || cols < 2 {
+            return None;
+        }
+
+        let direction = direction.normalize_or_zero();
+        let min = Vec3::new(
+            *self.x_range.start(),
+            *self.y_range.start(),
+            *self.z_range.start(),
+        );
+        let max = Vec3::new(
+            *self.x_range.end(),
+            *self.y_range.end(),
+            *self.z_range.end(),
+        );
+        let (t_enter, t_exit) = ray_aabb(origin, direction, min, max)?;
+
+        let spacing =
+            ((max.x - min.x) / (rows - 1) as f32).min((max.y - min.y) / (cols - 1) as f32);
+        let step = (spacing * 0.5).max(f32::EPSILON);
+        let steps = libm::ceilf((t_exit - t_enter) / step) as u32;
+
+        let above = |t: f32| {
+            let p = origin + direction * t;
+            p.z - self.height_at(p.truncate())
+        };
+
+        let mut t_prev = t_enter;
+        let mut f_prev = above(t_prev);
+        for i in 1..=steps.max(1) {
+            let t = (t_enter + step * i as f32).min(t_exit);
+            let f = above(t);
+            if f_prev == 0.0 || f_prev.signum() != f.signum() {
+                // Bisect between the last two samples
+                let (mut lo, mut hi, f_lo) = (t_prev, t, f_prev);
+                for _ in 0..Self::REFINE_STEPS {
+                    let mid = 0.5 * (lo + hi);
+                    if above(mid).signum() == f_lo.signum() {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                let p = origin + direction * (0.5 * (lo + hi));
+                let hit = Vec3::new(p.x, p.y, self.height_at(p.truncate()));
+                if visible(hit) {
+                    return Some(hit);
+                }
+            }
+            t_prev = t;
+            f_prev = f;
+        }
+
+        None
+    }
+}
+
+/// Slab test returning the entry and exit distances of a ray through a box
+///
+/// Entry is clamped to zero so rays starting inside the box begin at their origin.
+fn ray_aabb(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<(f32, f32)> {
+    let inv = direction.recip();
+    let t0 = (min - origin) * inv;
+    let t1 = (max - origin) * inv;
+
+    // NaN arises from 0 * inf when the ray is parallel to and on a slab, min/max discard it
+    let t_enter = t0.min(t1).max_element().max(0.0);
+    let t_exit = t0.max(t1).min_element();
+
+    (t_enter <= t_exit).then_some((t_enter, t_exit))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    use super::*;
+
+    #[test]
+    fn rays_hit_the_grid_cell_below() {
+        // A tilted plane z = x + 2y, 5 rows along X over 0..4 and 3 columns along Y over 0..2
+        let vertices: Vec<f32> = (0..5 * 3)
+            .flat_map(|i| {
+                let (x, y) = ((i / 3) as f32, (i % 3) as f32);
+                [x, y, x + 2.0 * y, 0.0, 0.0, 0.0]
+            })
+            .collect();
+        let field = HeightField::from_vertices((5, 3), 0.0..=4.0, 0.0..=2.0, &vertices);
+        assert_eq!(field.z_range(), 0.0..=8.0);
+        assert_eq!(field.height_at(Vec2::new(2.5, 1.25)), 5.0);
+
+        // Straight down into the cell between rows 2 and 3 and columns 1 and 2
+        let hit = field
+            .intersect(Vec3::new(2.5, 1.25, 10.0), Vec3::NEG_Z, |_| true)
+            .unwrap();
+        assert!(hit.abs_diff_eq(Vec3::new(2.5, 1.25, 5.0), 1e-4));
+
+        // Slanted, landing where the ray meets the plane
+        let origin = Vec3::new(-1.0, 0.5, 6.0);
+        let direction = Vec3::new(1.0, 0.0, -1.0);
+        let hit = field.intersect(origin, direction, |_| true).unwrap();
+        assert!(hit.abs_diff_eq(Vec3::new(2.0, 0.5, 3.0), 1e-4));
+
+        // Away from the surface, and past the domain
+        assert_eq!(
+            field.intersect(Vec3::new(2.0, 1.0, 10.0), Vec3::Z, |_| true),
+            None
+        );
+        let beside = Vec3::new(6.0, 1.0, 10.0);
+        assert_eq!(field.intersect(beside, Vec3::NEG_Z, |_| true), None);
+    }
+
+    #[test]
+    fn rays_pass_through_hidden_crossings() {
+        // A peak at the origin
+        let n = 41;
+        let vertices: Vec<f32> = (0..n * n)
+            .flat_map(|i| {
+                let x = -1.0 + 2.0 * (i / n) as f32 / (n - 1) as f32;
+                let y = -1.0 + 2.0 * (i % n) as f32 / (n - 1) as f32;
+                let z = 2.0 * libm::expf(-10.0 * (x * x + y * y));
+                [x, y, z, 0.0, 0.0, 0.0]
+            })
+            .collect();
+        let field = HeightField::from_vertices((n, n), -1.0..=1.0, -1.0..=1.0, &vertices);
+
+        // Through the peak, whose near side is cut away
+        let (origin, direction) = (Vec3::new(2.0, 0.0, 0.3), Vec3::new(-1.0, 0.0, -0.01));
+        let near = field.intersect(origin, direction, |_| true).unwrap();
+        assert!(near.x > 0.0);
+        let far = field.intersect(origin, direction, |p| p.x < 0.0).unwrap();
+        assert!(far.x < 0.0);
+        assert!((far.z - field.height_at(far.truncate())).abs() < 1e-3);
+        assert_eq!(field.intersect(origin, direction, |_| false), None);
+    }
+}
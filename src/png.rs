@@ -0,0 +1,158 @@
+//! Minimal PNG encoder for rendered frames
+//!
+//! Pixels are stored in uncompressed deflate blocks, which keeps the encoder tiny at the cost of
+//! file size.
+
+use alloc::vec::Vec;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Truecolour without alpha, frames are opaque
+const COLOR_TYPE_RGB: u8 = 2;
+const MAX_STORED_BLOCK: usize = u16::MAX as usize;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Encode tightly packed 8 bit RGB rows, top row first
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 3;
+    assert_eq!(
+        rgb.len(),
+        stride * height as usize,
+        "Pixel data does not match size"
+    );
+
+    // Every scanline starts with its filter type, none here
+    let mut scanlines = Vec::with_capacity((stride + 1) * height as usize);
+    for row in 0..height as usize {
+        scanlines.push(0);
+        scanlines.extend_from_slice(&rgb[row * stride..][..stride]);
+    }
+
+    let mut header = [0; 13];
+    header[0..4].copy_from_slice(&width.to_be_bytes());
+    header[4..8].copy_from_slice(&height.to_be_bytes());
+    // Bit depth, colour type, then default compression, filtering and no interlacing
+    header[8] = 8;
+    header[9] = COLOR_TYPE_RGB;
+
+    let mut png = Vec::with_capacity(scanlines.len() + scanlines.len() / MAX_STORED_BLOCK * 5 + 64);
+    png.extend_from_slice(&SIGNATURE);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap `data` in a zlib stream of stored deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window and no preset dictionary, chosen so the header is a multiple of 31
+    let mut stream = Vec::with_capacity(data.len() + data.len() / MAX_STORED_BLOCK * 5 + 11);
+    stream.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK).collect();
+    if blocks.is_empty() {
+        blocks.push(&[]);
+    }
+    let last = blocks.len() - 1;
+    for (i, block) in blocks.into_iter().enumerate() {
+        let len = block.len() as u16;
+        stream.push((i == last) as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    // Largest run of bytes that cannot overflow the sums before reducing them
+    const RUN: usize = 5552;
+
+    let (mut a, mut b) = (1, 0);
+    for run in bytes.chunks(RUN) {
+        for &byte in run {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn stored_blocks_split_long_data() {
+        let data = alloc::vec![7; MAX_STORED_BLOCK + 1];
+        let stream = zlib_stored(&data);
+
+        assert_eq!(stream.len(), 2 + 2 * 5 + data.len() + 4);
+        // First block is not final and full, the second one is final with a single byte
+        assert_eq!(&stream[2..7], &[0, 0xff, 0xff, 0, 0]);
+        let second = 2 + 5 + MAX_STORED_BLOCK;
+        assert_eq!(&stream[second..second + 5], &[1, 1, 0, 0xfe, 0xff]);
+    }
+
+    #[test]
+    fn encodes_header_and_scanlines() {
+        let png = encode_rgb(2, 1, &[255, 0, 0, 0, 255, 0]);
+
+        assert_eq!(&png[..8], &SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(&png[24..26], &[8, COLOR_TYPE_RGB]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        // Scanline with its filter byte inside the single stored block
+        let idat = 8 + 25;
+        assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+        assert_eq!(&png[idat + 15..idat + 22], &[0, 255, 0, 0, 0, 255, 0]);
+    }
+}
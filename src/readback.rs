@@ -0,0 +1,116 @@
+//! Copies of GPU resources read back on the CPU
+//!
+//! Copies are submitted when a readback is created and only mapped when it is read, so the
+//! source may be changed or dropped meanwhile and no borrow needs to be held across the await.
+
+use alloc::vec::Vec;
+
+use crate::Error;
+
+/// Copy of a buffer waiting to be mapped
+pub struct BufferReadback {
+    device: wgpu::Device,
+    staging_buffer: wgpu::Buffer,
+}
+
+impl BufferReadback {
+    /// Queue a copy of the whole of `source`, which needs `COPY_SRC` usage
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, source: &wgpu::Buffer) -> Self {
+        let staging_buffer = create_staging_buffer(device, source.size());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(source, 0, &staging_buffer, 0, source.size());
+        queue.submit([encoder.finish()]);
+
+        Self {
+            device: device.clone(),
+            staging_buffer,
+        }
+    }
+
+    pub async fn read<T: bytemuck::Pod>(self) -> Result<Vec<T>, Error> {
+        map_read(&self.device, &self.staging_buffer).await?;
+        let data = bytemuck::cast_slice(&self.staging_buffer.get_mapped_range(..)).to_vec();
+        self.staging_buffer.destroy();
+        Ok(data)
+    }
+}
+
+/// Copy of a texture with 4 bytes per pixel waiting to be mapped
+pub struct TextureReadback {
+    device: wgpu::Device,
+    staging_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_row_bytes: u32,
+}
+
+impl TextureReadback {
+    /// Queue a copy of `texture`, which needs `COPY_SRC` usage
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Self {
+        let (width, height) = (texture.width(), texture.height());
+        // Buffer copies need rows aligned to 256 bytes
+        let padded_row_bytes = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let staging_buffer = create_staging_buffer(device, padded_row_bytes as u64 * height as u64);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        queue.submit([encoder.finish()]);
+
+        Self {
+            device: device.clone(),
+            staging_buffer,
+            width,
+            height,
+            padded_row_bytes,
+        }
+    }
+
+    /// Read the pixels as tightly packed rows, top row first
+    pub async fn read(self) -> Result<Vec<u8>, Error> {
+        map_read(&self.device, &self.staging_buffer).await?;
+
+        let row_bytes = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let mapped = self.staging_buffer.get_mapped_range(..);
+            for row in mapped.chunks_exact(self.padded_row_bytes as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.staging_buffer.destroy();
+        Ok(pixels)
+    }
+}
+
+fn create_staging_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback staging buffer"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+async fn map_read(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Result<(), Error> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    buffer.map_async(wgpu::MapMode::Read, .., move |res| {
+        let _ = tx.send(res);
+    });
+    // Native backends only map when polled, browsers map on their own
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+    // A dropped callback means the mapping never completed
+    rx.await.unwrap_or(Err(wgpu::BufferAsyncError))?;
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use alloc::rc::{Rc, Weak};
+use core::cell::RefCell;
+
+use futures::StreamExt;
+use wasm_bindgen::JsValue;
+
+use crate::State;
+
+/// JS function notified after a device loss, see `JsApp::set_device_lost_callback`
+pub type DeviceLostCallback = Rc<RefCell<Option<js_sys::Function>>>;
+
+/// Wait for the device to be lost and recreate it, for as long as the state is alive
+///
+/// Only holds a weak reference so the state is dropped with its `JsApp`, which also drops the
+/// device and ends the wait.
+pub async fn watch_device_loss(state: Weak<RefCell<State>>, callback: DeviceLostCallback) {
+    loop {
+        let Some(mut device_lost) = state
+            .upgrade()
+            .and_then(|state| state.borrow_mut().take_device_lost_receiver())
+        else {
+            return;
+        };
+        let Some(message) = device_lost.next().await else {
+            return;
+        };
+        drop(device_lost);
+
+        log::warn!("Device lost: {message}");
+        let Some(strong) = state.upgrade() else {
+            return;
+        };
+
+        let recovered = match State::recover_device(&strong).await {
+            Ok(()) => {
+                log::info!("Device recreated");
+                if let Err(err) = strong.borrow_mut().render() {
+                    log::error!("{err}");
+                }
+                true
+            }
+            Err(err) => {
+                log::error!("Could not recreate device: {err}");
+                false
+            }
+        };
+        drop(strong);
+
+        if let Some(callback) = callback.borrow().as_ref() {
+            let _ = callback.call2(
+                &JsValue::NULL,
+                &JsValue::from_str(&message),
+                &JsValue::from_bool(recovered),
+            );
+        }
+
+        if !recovered {
+            return;
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Surfaces from irregular (x, y, z) samples rather than a sampled function
+//!
+//! Points are either triangulated by X and Y on the CPU and uploaded in the same vertex layout as
+//! `GridBuffers`, so they are drawn, exported and framed like the grid, or interpolated onto the
+//! grid itself on the GPU so everything built on the grid applies to them.
+
+use alloc::vec::Vec;
+use core::{fmt, ops::RangeInclusive};
+
+use glam::Vec3;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{camera::Aabb, delaunay, meshgrid};
+
+/// Why scattered points cannot be shown
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScatterError {
+    /// Fewer than 3 distinct points off a common line, which enclose no area
+    NoTriangles,
+    /// The vertex or index buffer would exceed the device's buffer size limit
+    BufferTooLarge { bytes: u64, max: u64 },
+    /// No point has finite coordinates
+    NoPoints,
+    /// The interpolation method is limited to `max` points, see `Interpolation::max_points`
+    TooManyPoints { count: usize, max: usize },
+    /// The interpolation's power or radius is out of range
+    InvalidInterpolation(Interpolation),
+    /// The Gaussian RBF system cannot be solved, usually because the radius is too large for
+    /// the spacing of the points
+    Singular,
+}
+
+impl fmt::Display for ScatterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTriangles => write!(
+                f,
+                "Points cannot be triangulated, at least 3 distinct points not on a line are needed"
+            ),
+            Self::BufferTooLarge { bytes, max } => write!(
+                f,
+                "Points need a {bytes} byte buffer but the device allows at most {max} bytes"
+            ),
+            Self::NoPoints => write!(f, "No points with finite coordinates"),
+            Self::TooManyPoints { count, max } => write!(
+                f,
+                "Cannot interpolate {count} points, at most {max} are supported by this method"
+            ),
+            Self::InvalidInterpolation(interpolation) => {
+                write!(f, "Invalid interpolation parameters {interpolation:?}")
+            }
+            Self::Singular => write!(
+                f,
+                "Gaussian RBF weights cannot be solved for, try a smaller radius"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ScatterError {}
+
+/// A line of point data that is not a number triple
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePointsError {
+    /// 1-based
+    pub line: usize,
+}
+
+impl fmt::Display for ParsePointsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Line {} does not start with x, y and z values",
+            self.line
+        )
+    }
+}
+
+impl core::error::Error for ParsePointsError {}
+
+/// Read points from XYZ or CSV text
+///
+/// Each line holds x, y and z separated by commas, semicolons or whitespace, further columns are
+/// ignored. Blank lines, lines starting with `#` and a header line before the first point are
+/// skipped.
+pub fn parse_points(text: &str) -> Result<Vec<[f32; 3]>, ParsePointsError> {
+    let mut points = Vec::new();
+    let mut header = true;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut values = line
+            .split([',', ';', ' ', '\t'])
+            .filter(|value| !value.is_empty())
+            .map(str::parse::<f32>);
+        match [values.next(), values.next(), values.next()] {
+            [Some(Ok(x)), Some(Ok(y)), Some(Ok(z))] => points.push([x, y, z]),
+            _ if header => {}
+            _ => return Err(ParsePointsError { line: i + 1 }),
+        }
+        header = false;
+    }
+    Ok(points)
+}
+
+/// How scattered points are resampled onto the grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Average of the points weighted by their distance to the power of `-power`
+    ///
+    /// Only points within `radius` count, or all of them if it is 0. Places without points in
+    /// range get the mean height.
+    InverseDistance { power: f32, radius: f32 },
+    /// Sum of Gaussians of width `radius` around the mean height, passing exactly through every
+    /// point
+    GaussianRbf { radius: f32 },
+}
+
+impl Interpolation {
+    /// Most points Gaussian RBF interpolation accepts, the weights take cubic time to solve
+    pub const MAX_RBF_POINTS: usize = 2048;
+    /// Most points inverse distance weighting accepts, every grid vertex visits each of them in
+    /// a single dispatch that has to finish before the driver's timeout
+    pub const MAX_IDW_POINTS: usize = 1 << 16;
+
+    /// Most points this method accepts
+    pub fn max_points(self) -> usize {
+        match self {
+            Self::InverseDistance { .. } => Self::MAX_IDW_POINTS,
+            Self::GaussianRbf { .. } => Self::MAX_RBF_POINTS,
+        }
+    }
+
+    fn is_valid(self) -> bool {
+        match self {
+            Self::InverseDistance { power, radius } => {
+                power.is_finite() && power > 0.0 && radius.is_finite() && radius >= 0.0
+            }
+            Self::GaussianRbf { radius } => radius.is_finite() && radius > 0.0,
+        }
+    }
+}
+
+/// Scattered points ready to be interpolated onto the grid
+#[derive(Clone)]
+pub struct InterpolationSamples {
+    /// x, y, z and RBF weight
+    pub points: Vec<[f32; 4]>,
+    pub interpolation: Interpolation,
+    /// Mean height
+    pub mean: f32,
+    pub x_range: RangeInclusive<f32>,
+    pub y_range: RangeInclusive<f32>,
+}
+
+impl InterpolationSamples {
+    /// Drop points with non-finite coordinates and solve for the RBF weights if needed
+    pub fn new(points: &[[f32; 3]], interpolation: Interpolation) -> Result<Self, ScatterError> {
+        if !interpolation.is_valid() {
+            return Err(ScatterError::InvalidInterpolation(interpolation));
+        }
+
+        let finite: Vec<[f32; 3]> = points
+            .iter()
+            .copied()
+            .filter(|point| point.iter().all(|v| v.is_finite()))
+            .collect();
+        let Some(&[x, y, _]) = finite.first() else {
+            return Err(ScatterError::NoPoints);
+        };
+
+        let (mut x_range, mut y_range) = ([x, x], [y, y]);
+        let mut sum = 0.0;
+        for &[x, y, z] in &finite {
+            x_range = [x_range[0].min(x), x_range[1].max(x)];
+            y_range = [y_range[0].min(y), y_range[1].max(y)];
+            sum += z as f64;
+        }
+        let mean = (sum / finite.len() as f64) as f32;
+
+        let max = interpolation.max_points();
+        if finite.len() > max {
+            return Err(ScatterError::TooManyPoints {
+                count: finite.len(),
+                max,
+            });
+        }
+        let weights = match interpolation {
+            Interpolation::InverseDistance { .. } => alloc::vec![0.0; finite.len()],
+            Interpolation::GaussianRbf { radius } => {
+                rbf_weights(&finite, radius, mean).ok_or(ScatterError::Singular)?
+            }
+        };
+
+        Ok(Self {
+            points: finite
+                .iter()
+                .zip(weights)
+                .map(|(&[x, y, z], w)| [x, y, z, w])
+                .collect(),
+            interpolation,
+            mean,
+            x_range: x_range[0]..=x_range[1],
+            y_range: y_range[0]..=y_range[1],
+        })
+    }
+}
+
+/// Weights of Gaussians centred on `points` that sum to each point's height above `mean`
+///
+/// Solves the symmetric positive definite system by Cholesky decomposition, with a little
+/// regularisation so duplicate points do not make it singular.
+fn rbf_weights(points: &[[f32; 3]], radius: f32, mean: f32) -> Option<Vec<f32>> {
+    const REGULARISATION: f64 = 1e-9;
+
+    let n = points.len();
+    let scale = 1.0 / (radius as f64 * radius as f64);
+    let kernel = |i: usize, j: usize| {
+        let dx = points[i][0] as f64 - points[j][0] as f64;
+        let dy = points[i][1] as f64 - points[j][1] as f64;
+        libm::exp(-(dx * dx + dy * dy) * scale)
+    };
+
+    // Lower triangle of L, row-major
+    let mut l = alloc::vec![0.0f64; n * n];
+    for i in 0..n {
+        for j in 0..=i {
+            let dot: f64 = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum();
+            if i == j {
+                let pivot = 1.0 + REGULARISATION - dot;
+                if pivot <= 0.0 || !pivot.is_finite() {
+                    return None;
+                }
+                l[i * n + i] = libm::sqrt(pivot);
+            } else {
+                l[i * n + j] = (kernel(i, j) - dot) / l[j * n + j];
+            }
+        }
+    }
+
+    // Forward then back substitution
+    let mut w: Vec<f64> = points.iter().map(|p| (p[2] - mean) as f64).collect();
+    for i in 0..n {
+        let dot: f64 = (0..i).map(|k| l[i * n + k] * w[k]).sum();
+        w[i] = (w[i] - dot) / l[i * n + i];
+    }
+    for i in (0..n).rev() {
+        let dot: f64 = (i + 1..n).map(|k| l[k * n + i] * w[k]).sum();
+        w[i] = (w[i] - dot) / l[i * n + i];
+    }
+
+    Some(w.into_iter().map(|w| w as f32).collect())
+}
+
+/// Triangulated points in the layout of the vertex and index buffers
+#[derive(Clone)]
+pub struct ScatterMesh {
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    bounds: Aabb,
+}
+
+impl ScatterMesh {
+    /// Triangulate `points` and colour them like the evaluator colours the grid
+    ///
+    /// Points with a non-finite coordinate are left out.
+    pub fn new(points: &[[f32; 3]]) -> Result<Self, ScatterError> {
+        let planar: Vec<[f64; 2]> = points
+            .iter()
+            .map(|&[x, y, z]| {
+                if z.is_finite() {
+                    [x as f64, y as f64]
+                } else {
+                    [f64::NAN; 2]
+                }
+            })
+            .collect();
+        let indices: Vec<u32> = delaunay::triangulate(&planar).into_flattened();
+        if indices.is_empty() {
+            return Err(ScatterError::NoTriangles);
+        }
+
+        let (color_min, color_max) = (*meshgrid::COLOR_RANGE.start(), *meshgrid::COLOR_RANGE.end());
+        let mut bounds = Aabb {
+            min: Vec3::INFINITY,
+            max: Vec3::NEG_INFINITY,
+        };
+        let mut vertices = Vec::with_capacity(points.len() * meshgrid::VERTEX_ELEMENTS);
+        for &point in points {
+            let color = (point[2] - color_min) / (color_max - color_min);
+            vertices.extend_from_slice(&point);
+            vertices.extend_from_slice(&[color; 3]);
+        }
+        for &index in &indices {
+            let point = Vec3::from(points[index as usize]);
+            bounds.min = bounds.min.min(point);
+            bounds.max = bounds.max.max(point);
+        }
+
+        Ok(Self {
+            vertices,
+            indices,
+            bounds,
+        })
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Bounds of the triangulated points
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+/// Scattered points uploaded for drawing, along with the mesh to upload them again from
+pub struct ScatterBuffers {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    pub mesh: ScatterMesh,
+}
+
+impl ScatterBuffers {
+    pub fn new(device: &wgpu::Device, mesh: ScatterMesh) -> Result<Self, ScatterError> {
+        let max = device.limits().max_buffer_size;
+        for bytes in [mesh.vertices.len() * 4, mesh.indices.len() * 4] {
+            if bytes as u64 > max {
+                return Err(ScatterError::BufferTooLarge {
+                    bytes: bytes as u64,
+                    max,
+                });
+            }
+        }
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Scatter vertex buffer"),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Scatter index buffer"),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+            mesh,
+        })
+    }
+
+    pub fn destroy(&self) {
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn parses_xyz_and_csv() {
+        let text = "# survey\nx,y,z\n0,0,1\n1.5, 0, 2\n\n0\t1\t-3 extra\n";
+        assert_eq!(
+            parse_points(text),
+            Ok(alloc::vec![
+                [0.0, 0.0, 1.0],
+                [1.5, 0.0, 2.0],
+                [0.0, 1.0, -3.0]
+            ])
+        );
+        assert_eq!(
+            parse_points("0 0 1\n1 1\n"),
+            Err(ParsePointsError { line: 2 })
+        );
+    }
+
+    #[test]
+    fn rbf_passes_through_the_points() {
+        let points = [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 2.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 1.5],
+            [0.5, 0.5, -1.0],
+        ];
+        let samples =
+            InterpolationSamples::new(&points, Interpolation::GaussianRbf { radius: 0.7 }).unwrap();
+        assert_eq!(samples.mean, 0.7);
+
+        for &[x, y, z] in &points {
+            let value: f32 = samples.mean
+                + samples
+                    .points
+                    .iter()
+                    .map(|&[px, py, _, w]| {
+                        let d2 = (x - px) * (x - px) + (y - py) * (y - py);
+                        w * libm::expf(-d2 / (0.7 * 0.7))
+                    })
+                    .sum::<f32>();
+            assert!((value - z).abs() < 1e-4, "{value} != {z}");
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_interpolation() {
+        let idw = Interpolation::InverseDistance {
+            power: 2.0,
+            radius: 0.0,
+        };
+        assert_eq!(
+            InterpolationSamples::new(&[[f32::NAN, 0.0, 0.0]], idw).err(),
+            Some(ScatterError::NoPoints)
+        );
+
+        let negative = Interpolation::GaussianRbf { radius: -1.0 };
+        assert_eq!(
+            InterpolationSamples::new(&[[0.0; 3]], negative).err(),
+            Some(ScatterError::InvalidInterpolation(negative))
+        );
+
+        let samples = InterpolationSamples::new(&[[0.0, 1.0, 2.0], [3.0, -1.0, 4.0]], idw).unwrap();
+        assert_eq!(samples.x_range, 0.0..=3.0);
+        assert_eq!(samples.y_range, -1.0..=1.0);
+    }
+
+    #[test]
+    fn limits_the_point_count() {
+        let idw = Interpolation::InverseDistance {
+            power: 2.0,
+            radius: 0.0,
+        };
+        let rbf = Interpolation::GaussianRbf { radius: 1.0 };
+        let line =
+            |count: usize| -> Vec<[f32; 3]> { (0..count).map(|i| [i as f32, 0.0, 0.0]).collect() };
+
+        for (interpolation, max) in [
+            (idw, Interpolation::MAX_IDW_POINTS),
+            (rbf, Interpolation::MAX_RBF_POINTS),
+        ] {
+            assert_eq!(
+                InterpolationSamples::new(&line(max + 1), interpolation).err(),
+                Some(ScatterError::TooManyPoints {
+                    count: max + 1,
+                    max
+                })
+            );
+        }
+        // Non-finite points are dropped before counting
+        let mut points = line(Interpolation::MAX_IDW_POINTS);
+        points.push([f32::NAN; 3]);
+        assert!(InterpolationSamples::new(&points, idw).is_ok());
+    }
+
+    #[test]
+    fn mesh_matches_the_grid_layout() {
+        let points = [
+            [0.0, 0.0, -0.5],
+            [1.0, 0.0, 2.0],
+            [0.0, 1.0, 0.75],
+            [1.0, 1.0, f32::NAN],
+        ];
+        let mesh = ScatterMesh::new(&points).unwrap();
+
+        assert_eq!(mesh.triangle_count(), 1);
+        assert_eq!(
+            mesh.vertices.len(),
+            points.len() * meshgrid::VERTEX_ELEMENTS
+        );
+        assert_eq!(&mesh.vertices[6..12], &[1.0, 0.0, 2.0, 1.0, 1.0, 1.0]);
+        assert_eq!(mesh.bounds().max, Vec3::new(1.0, 1.0, 2.0));
+
+        assert_eq!(
+            ScatterMesh::new(&[[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [2.0, 2.0, 0.0]]).err(),
+            Some(ScatterError::NoTriangles)
+        );
+    }
+}
@@ -1,12 +1,32 @@
 // Vertex shader
 
+// Must match MAX_CLIP_PLANES in clipping.rs
+const MAX_CLIP_PLANES = 6u;
+
+struct ClipUniform {
+    // Normal in xyz and offset in w, cutting away where dot(normal, p) > offset
+    planes: array<vec4<f32>, MAX_CLIP_PLANES>,
+    cap_color: vec3<f32>,
+    plane_count: u32,
+};
+
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    clipping: ClipUniform,
 };
 
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct SurfaceUniform {
+    opacity: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> surface: SurfaceUniform;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
@@ -15,6 +35,9 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    // Distance in front of the camera, weighting translucent fragments
+    @location(1) depth: f32,
+    @location(2) world_position: vec3<f32>,
 };
 
 @vertex
@@ -24,6 +47,8 @@ fn vs_main(
     var out: VertexOutput;
     out.color = model.color;
     out.clip_position = camera.view_proj * vec4f(model.position, 1);
+    out.depth = -(camera.view * vec4f(model.position, 1)).z;
+    out.world_position = model.position;
     return out;
 }
 
@@ -34,7 +59,62 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+fn is_clipped(p: vec3<f32>) -> bool {
+    for (var i = 0u; i < camera.clipping.plane_count; i++) {
+        let plane = camera.clipping.planes[i];
+        if dot(plane.xyz, p) > plane.w {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Where the view ray through `p` starts, the eye or the camera plane of a parallel projection
+fn view_ray_origin(p: vec3<f32>) -> vec3<f32> {
+    let q = (camera.view * vec4f(p, 1)).xyz;
+    // Clip w is 1 in a parallel projection, blended projections land in between
+    let w = (camera.proj * vec4f(q, 1)).w;
+    let origin = vec3f(q.xy * camera.proj[3][3] / w, 0);
+    let rotation = mat3x3f(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    return transpose(rotation) * (origin - camera.view[3].xyz);
+}
+
+// Discards what the clipping planes cut away and caps the inside seen through the cut
+fn surface_color(in: VertexOutput, front_facing: bool) -> vec3<f32> {
+    if is_clipped(in.world_position) {
+        discard;
+    }
+    // The fragment is kept, so its view ray passes through the cut if it starts beyond a plane
+    if !front_facing && is_clipped(view_ray_origin(in.world_position)) {
+        return camera.clipping.cap_color;
+    }
+    return in.color;
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4(in.color, 1);
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    return vec4(surface_color(in, front_facing), 1);
+}
+
+struct TransparentOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) reveal: f32,
+};
+
+// Weighted blended order-independent transparency, see transparency.rs
+@fragment
+fn fs_transparent(
+    in: VertexOutput,
+    @builtin(front_facing) front_facing: bool,
+) -> TransparentOutput {
+    let color = surface_color(in, front_facing);
+    let alpha = surface.opacity;
+    // Nearer fragments weigh more, equation 7 of McGuire and Bavoil
+    let z = in.depth;
+    let weight = clamp(10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 1e-2, 3e3);
+
+    var out: TransparentOutput;
+    out.accum = vec4(color * alpha, alpha) * weight;
+    out.reveal = alpha;
+    return out;
 }
@@ -0,0 +1,261 @@
+//! Weighted blended order-independent transparency for translucent surfaces
+//!
+//! Translucent surfaces are drawn after everything opaque, depth tested against it without
+//! writing depth, into two targets: the weighted sum of their premultiplied colours and the
+//! product of their transparencies, the revealage. Neither depends on the order fragments arrive
+//! in. A fullscreen pass then blends the weighted average colour over the frame by one minus the
+//! revealage. See McGuire and Bavoil, "Weighted Blended Order-Independent Transparency", 2013.
+//!
+//! With multisampling the targets share the multisampled depth buffer and are resolved before
+//! being composited onto the resolved frame.
+
+pub const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const REVEAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Colour targets of the translucent surface pipeline, in the order of its fragment outputs
+pub fn color_targets() -> [Option<wgpu::ColorTargetState>; 2] {
+    let add = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    // Multiplies the revealage by one minus each fragment's opacity
+    let reveal = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    };
+    [
+        Some(wgpu::ColorTargetState {
+            format: ACCUM_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: add,
+                alpha: add,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        }),
+        Some(wgpu::ColorTargetState {
+            format: REVEAL_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: reveal,
+                alpha: reveal,
+            }),
+            write_mask: wgpu::ColorWrites::RED,
+        }),
+    ]
+}
+
+/// Blends gathered translucent surfaces over a resolved frame
+pub struct Compositor {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Compositor {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let module = device.create_shader_module(wgpu::include_wgsl!("composite.wgsl"));
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Composite bind group layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Composite pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Like the overlay, drawn after the multisampled pass has been resolved
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Composite pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Blend the surfaces gathered in `targets` over `output`, which must be single sampled
+    pub fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        targets: &OitTargets,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &targets.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Texture and the view it is rendered or sampled through
+type Target = (wgpu::Texture, wgpu::TextureView);
+
+/// Accumulation and revealage targets matching a frame's size and sample count
+pub struct OitTargets {
+    accum: Target,
+    reveal: Target,
+    /// Single sampled targets the multisampled ones are resolved into
+    resolved: Option<[Target; 2]>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl OitTargets {
+    pub fn new(
+        device: &wgpu::Device,
+        compositor: &Compositor,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let create = |label, format, sample_count| -> Target {
+            let usage = if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            };
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+
+        let accum = create("Accumulation texture", ACCUM_FORMAT, sample_count);
+        let reveal = create("Revealage texture", REVEAL_FORMAT, sample_count);
+        let resolved = (sample_count > 1).then(|| {
+            [
+                create("Resolved accumulation texture", ACCUM_FORMAT, 1),
+                create("Resolved revealage texture", REVEAL_FORMAT, 1),
+            ]
+        });
+
+        let [sampled_accum, sampled_reveal] = match &resolved {
+            Some([accum, reveal]) => [&accum.1, &reveal.1],
+            None => [&accum.1, &reveal.1],
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Composite bind group"),
+            layout: &compositor.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(sampled_accum),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(sampled_reveal),
+                },
+            ],
+        });
+
+        Self {
+            accum,
+            reveal,
+            resolved,
+            bind_group,
+        }
+    }
+
+    /// Attachments of the translucent pass, cleared to no colour and full revealage
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 2] {
+        fn attachment<'a>(
+            target: &'a Target,
+            resolve_target: Option<&'a Target>,
+            clear: wgpu::Color,
+        ) -> Option<wgpu::RenderPassColorAttachment<'a>> {
+            Some(wgpu::RenderPassColorAttachment {
+                view: &target.1,
+                depth_slice: None,
+                resolve_target: resolve_target.map(|target| &target.1),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        }
+
+        let resolved = self.resolved.as_ref();
+        [
+            attachment(
+                &self.accum,
+                resolved.map(|[accum, _]| accum),
+                wgpu::Color::TRANSPARENT,
+            ),
+            attachment(
+                &self.reveal,
+                resolved.map(|[_, reveal]| reveal),
+                wgpu::Color::WHITE,
+            ),
+        ]
+    }
+
+    pub fn destroy(&self) {
+        self.accum.0.destroy();
+        self.reveal.0.destroy();
+        for (texture, _) in self.resolved.iter().flatten() {
+            texture.destroy();
+        }
+    }
+}
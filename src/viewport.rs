@@ -0,0 +1,401 @@
+//! Cells of the render target, each with its own camera and plots
+//!
+//! The target is split into a grid of viewports drawn in the same render pass, each clipped to
+//! its cell with a viewport and scissor rectangle. Pipelines are shared, while every viewport has
+//! its own camera uniform, surface, markers and curves.
+
+use glam::{Mat4, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{
+    CameraUniform, FrameTargets,
+    camera::{self, Camera},
+    capture::Tile,
+    clipping::Clipping,
+    controller::CameraController,
+    curves, markers, meshgrid, picking, scatter,
+};
+
+/// Most rows or columns the target can be split into
+pub const MAX_GRID_SIZE: u32 = 8;
+
+/// Region of viewport `index`, counted row by row from the top left, in a `width` by `height`
+/// target split into `rows` by `cols` cells
+///
+/// Cells share the remainder of uneven splits, so together they cover the target exactly once.
+pub fn cell(index: usize, (rows, cols): (u32, u32), width: u32, height: u32) -> Tile {
+    let (row, col) = (index as u32 / cols, index as u32 % cols);
+    let split = |size: u32, i: u32, n: u32| (u64::from(size) * u64::from(i) / u64::from(n)) as u32;
+    let (x, y) = (split(width, col, cols), split(height, row, rows));
+    Tile {
+        x,
+        y,
+        width: split(width, col + 1, cols) - x,
+        height: split(height, row + 1, rows) - y,
+    }
+}
+
+/// Vertex and index buffers of the displayed surface
+pub struct SurfaceMesh<'a> {
+    pub vertex_buffer: &'a wgpu::Buffer,
+    pub index_buffer: &'a wgpu::Buffer,
+    pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
+}
+
+/// Per-surface settings the surface shader reads from group 1
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SurfaceUniform {
+    opacity: f32,
+    _padding: [f32; 3],
+}
+
+/// In-flight copy of the evaluated vertex buffer used to refresh the picking height field
+struct HeightReadback {
+    staging_buffer: wgpu::Buffer,
+    receiver: futures::channel::oneshot::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    resolution: (u32, u32),
+    x_range: core::ops::RangeInclusive<f32>,
+    y_range: core::ops::RangeInclusive<f32>,
+}
+
+pub struct Viewport {
+    /// Tells this viewport apart from any other that was or will be at its index
+    pub generation: u64,
+    pub camera: Camera,
+    pub camera_controller: CameraController,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    /// Uploaded with the camera matrices
+    clipping: Clipping,
+    surface_buffer: wgpu::Buffer,
+    surface_bind_group: wgpu::BindGroup,
+    surface: SurfaceUniform,
+    pub meshgrid_buffers: meshgrid::GridBuffers,
+    /// Evaluated onto the grid in place of the function while set
+    pub interpolated: Option<meshgrid::InterpolatedPoints>,
+    /// Shown in place of the grid while set
+    pub scattered: Option<scatter::ScatterBuffers>,
+    pub markers: markers::MarkerLayer,
+    pub curves: curves::CurveLayer,
+    height_field: Option<picking::HeightField>,
+    height_readback: Option<HeightReadback>,
+}
+
+impl Viewport {
+    /// Show `meshgrid_buffers`, which must already be evaluated, through `camera`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        generation: u64,
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        surface_bind_group_layout: &wgpu::BindGroupLayout,
+        marker_renderer: &markers::MarkerRenderer,
+        curve_renderer: &curves::CurveRenderer,
+        meshgrid_buffers: meshgrid::GridBuffers,
+        camera: Camera,
+        camera_controller: CameraController,
+    ) -> Self {
+        let clipping = Clipping::default();
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera buffer"),
+            contents: bytemuck::bytes_of(&CameraUniform::new(&camera, &clipping)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera bind group"),
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(camera_buffer.as_entire_buffer_binding()),
+            }],
+        });
+
+        let surface = SurfaceUniform {
+            opacity: 1.0,
+            _padding: [0.0; 3],
+        };
+        let surface_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Surface uniform buffer"),
+            contents: bytemuck::bytes_of(&surface),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let surface_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Surface bind group"),
+            layout: surface_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: surface_buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            generation,
+            camera,
+            camera_controller,
+            camera_buffer,
+            camera_bind_group,
+            clipping,
+            surface_buffer,
+            surface_bind_group,
+            surface,
+            meshgrid_buffers,
+            interpolated: None,
+            scattered: None,
+            markers: markers::MarkerLayer::new(device, marker_renderer),
+            curves: curves::CurveLayer::new(device, curve_renderer),
+            height_field: None,
+            height_readback: None,
+        }
+    }
+
+    /// Fit the camera and layers to `cell` of the render target
+    pub fn set_size(&mut self, queue: &wgpu::Queue, cell: &Tile) {
+        let (width, height) = (cell.width, cell.height);
+        self.camera.aspect = width.max(1) as f32 / height.max(1) as f32;
+        self.markers.set_viewport(queue, width, height);
+        self.curves.set_viewport(queue, cell);
+        self.write_camera_uniform(queue);
+    }
+
+    pub fn write_camera_uniform(&self, queue: &wgpu::Queue) {
+        self.write_camera_matrices(queue, self.camera.view(), self.camera.proj());
+    }
+
+    /// Draw the next submission with other matrices than the camera's, until
+    /// `write_camera_uniform`
+    pub fn write_camera_matrices(&self, queue: &wgpu::Queue, view: Mat4, proj: Mat4) {
+        let camera_uniform = CameraUniform::from_matrices(view, proj, &self.clipping);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera_uniform));
+    }
+
+    pub fn clipping(&self) -> &Clipping {
+        &self.clipping
+    }
+
+    /// Replace the clipping planes and cap colour, uploaded with the camera matrices
+    pub fn set_clipping(&mut self, queue: &wgpu::Queue, clipping: Clipping) {
+        self.clipping = clipping;
+        self.write_camera_uniform(queue);
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.surface.opacity
+    }
+
+    pub fn set_opacity(&mut self, queue: &wgpu::Queue, opacity: f32) {
+        self.surface.opacity = opacity;
+        queue.write_buffer(&self.surface_buffer, 0, bytemuck::bytes_of(&self.surface));
+    }
+
+    /// Whether the surface is drawn in the translucent pass rather than with everything opaque
+    pub fn is_translucent(&self) -> bool {
+        self.surface.opacity < 1.0
+    }
+
+    pub fn surface_mesh(&self) -> SurfaceMesh<'_> {
+        match &self.scattered {
+            Some(scattered) => SurfaceMesh {
+                vertex_buffer: &scattered.vertex_buffer,
+                index_buffer: &scattered.index_buffer,
+                index_count: scattered.index_count,
+                index_format: wgpu::IndexFormat::Uint32,
+            },
+            None => SurfaceMesh {
+                vertex_buffer: &self.meshgrid_buffers.vertex_buffer,
+                index_buffer: &self.meshgrid_buffers.index_buffer,
+                index_count: self.meshgrid_buffers.index_count,
+                index_format: self.meshgrid_buffers.index_format,
+            },
+        }
+    }
+
+    /// Draw the opaque surface, markers and curves into the pass's current viewport
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, frame: &FrameTargets) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        if !self.is_translucent() {
+            self.draw_surface(render_pass, frame.pipeline);
+        }
+
+        self.markers.draw(render_pass, frame.marker_pipeline);
+        self.curves.draw(render_pass, frame.curve_pipelines);
+    }
+
+    /// Draw the translucent surface into the pass's current viewport, see `transparency`
+    pub fn draw_translucent(&self, render_pass: &mut wgpu::RenderPass, frame: &FrameTargets) {
+        if self.is_translucent() {
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            self.draw_surface(render_pass, frame.transparent_pipeline);
+        }
+    }
+
+    fn draw_surface(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.surface_bind_group, &[]);
+        let mesh = self.surface_mesh();
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+        render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+    }
+
+    /// Start copying the evaluated heights back to the CPU for picking
+    ///
+    /// Replaces any readback still in flight since its grid is no longer displayed.
+    pub fn request_height_readback(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Some(previous) = self.height_readback.take() {
+            previous.staging_buffer.destroy();
+        }
+        self.height_field = None;
+
+        let buffers = &self.meshgrid_buffers;
+        let n_staging_bytes = buffers.vertex_buffer.size();
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Height readback buffer"),
+            size: n_staging_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Height readback encoder"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &buffers.vertex_buffer,
+            0,
+            &staging_buffer,
+            0,
+            n_staging_bytes,
+        );
+        queue.submit([encoder.finish()]);
+
+        let (tx, rx) = futures::channel::oneshot::channel();
+        staging_buffer.map_async(wgpu::MapMode::Read, .., move |res| {
+            let _ = tx.send(res);
+        });
+
+        self.height_readback = Some(HeightReadback {
+            staging_buffer,
+            receiver: rx,
+            resolution: buffers.resolution,
+            x_range: buffers.x_range.clone(),
+            y_range: buffers.y_range.clone(),
+        });
+    }
+
+    /// Move a completed height readback into the picking height field without blocking
+    fn poll_height_readback(&mut self, device: &wgpu::Device) {
+        let Some(readback) = self.height_readback.as_mut() else {
+            return;
+        };
+
+        // Browsers map on their own, native backends only when polled
+        let _ = device.poll(wgpu::PollType::Poll);
+
+        match readback.receiver.try_recv() {
+            // Still mapping
+            Ok(None) => {}
+            Ok(Some(Ok(()))) => {
+                let readback = self.height_readback.take().unwrap();
+                {
+                    let mapped = readback.staging_buffer.get_mapped_range(..);
+                    self.height_field = Some(picking::HeightField::from_vertices(
+                        readback.resolution,
+                        readback.x_range,
+                        readback.y_range,
+                        bytemuck::cast_slice(&mapped),
+                    ));
+                }
+                readback.staging_buffer.destroy();
+            }
+            Ok(Some(Err(err))) => {
+                log::warn!("Could not map height readback buffer: {err}");
+                self.height_readback = None;
+            }
+            Err(_) => {
+                self.height_readback = None;
+            }
+        }
+    }
+
+    /// Height field of the grid, once its heights have been read back
+    pub fn height_field(&mut self, device: &wgpu::Device) -> Option<&picking::HeightField> {
+        self.poll_height_readback(device);
+        self.height_field.as_ref()
+    }
+
+    /// Bounds of the displayed surface
+    ///
+    /// Heights fall back to the colour mapping's range until they have been read back.
+    pub fn surface_bounds(&mut self, device: &wgpu::Device) -> camera::Aabb {
+        if let Some(scattered) = &self.scattered {
+            return scattered.mesh.bounds();
+        }
+        let z_range = self
+            .height_field(device)
+            .map_or(meshgrid::COLOR_RANGE, |height_field| height_field.z_range());
+
+        let buffers = &self.meshgrid_buffers;
+        camera::Aabb {
+            min: Vec3::new(
+                *buffers.x_range.start(),
+                *buffers.y_range.start(),
+                *z_range.start(),
+            ),
+            max: Vec3::new(
+                *buffers.x_range.end(),
+                *buffers.y_range.end(),
+                *z_range.end(),
+            ),
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        self.camera_buffer.destroy();
+        self.surface_buffer.destroy();
+        self.meshgrid_buffers.destroy();
+        if let Some(interpolated) = self.interpolated.take() {
+            interpolated.destroy();
+        }
+        if let Some(scattered) = self.scattered.take() {
+            scattered.destroy();
+        }
+        if let Some(readback) = self.height_readback.take() {
+            readback.staging_buffer.destroy();
+        }
+        self.markers.destroy();
+        self.curves.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn cells_cover_the_target_once() {
+        let cells: alloc::vec::Vec<Tile> = (0..6).map(|i| cell(i, (2, 3), 100, 51)).collect();
+
+        assert_eq!(
+            cells.iter().map(|c| c.width * c.height).sum::<u32>(),
+            100 * 51
+        );
+        assert_eq!(
+            cells[4],
+            Tile {
+                x: 33,
+                y: 25,
+                width: 33,
+                height: 26
+            }
+        );
+        assert_eq!(
+            cell(0, (1, 1), 640, 480).projection(640, 480),
+            Mat4::IDENTITY
+        );
+    }
+}
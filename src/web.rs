@@ -0,0 +1,663 @@
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, f32::consts::PI};
+
+use glam::{Vec2, Vec3};
+use wasm_bindgen::prelude::*;
+use web_sys::HtmlCanvasElement;
+
+use crate::{
+    Error, State, bookmark, camera, clipping, curves, event_listeners::EventListeners,
+    frame_loop::FrameLoop, input::Gestures, markers, recovery, scatter,
+};
+
+#[wasm_bindgen]
+pub struct JsApp {
+    inner: Rc<RefCell<State>>,
+    frame_loop: FrameLoop,
+    gestures: Rc<RefCell<Gestures>>,
+    device_lost_callback: recovery::DeviceLostCallback,
+    _event_listeners: EventListeners,
+}
+
+#[wasm_bindgen]
+impl JsApp {
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.inner.borrow_mut().resize(width, height);
+    }
+
+    pub fn move_camera(&mut self, distance: f32, zenith: f32, azimuth: f32) {
+        self.inner
+            .borrow_mut()
+            .move_camera(distance, zenith * PI, azimuth * PI);
+        self.frame_loop.request();
+    }
+
+    /// Move the orbit target parallel to the screen by fractions of the canvas size
+    pub fn pan_camera(&mut self, dx: f32, dy: f32) {
+        self.inner.borrow_mut().pan_camera(dx, dy);
+        self.frame_loop.request();
+    }
+
+    /// Rotate the camera for a pointer drag between two points in canvas pixels
+    ///
+    /// Uses the turntable or trackball mapping depending on the camera mode.
+    pub fn drag_camera(&mut self, from_x: f32, from_y: f32, to_x: f32, to_y: f32) {
+        self.inner
+            .borrow_mut()
+            .drag_camera(Vec2::new(from_x, from_y), Vec2::new(to_x, to_y));
+        self.frame_loop.request();
+    }
+
+    /// Rotate the camera as a `"turntable"` keeping Z up or a free `"trackball"`
+    pub fn set_camera_mode(&mut self, mode: &str) -> Result<(), JsError> {
+        let mode = mode
+            .parse()
+            .map_err(|_| JsError::new("Unknown camera mode"))?;
+        self.inner.borrow_mut().set_camera_mode(mode);
+        self.frame_loop.request();
+        Ok(())
+    }
+
+    pub fn is_inertia_enabled(&self) -> bool {
+        self.inner.borrow().is_inertia_enabled()
+    }
+
+    /// Enable or disable camera inertia, when disabled moves are applied immediately
+    pub fn set_inertia_enabled(&mut self, enabled: bool) {
+        self.inner.borrow_mut().set_inertia_enabled(enabled);
+    }
+
+    /// Set how quickly camera motion dies down, as a damping rate per second
+    pub fn set_camera_friction(&mut self, friction: f32) {
+        self.inner.borrow_mut().set_camera_friction(friction);
+    }
+
+    /// Limit camera speed, in radians, log distance or canvas fractions per second
+    pub fn set_camera_max_speed(&mut self, max_speed: f32) {
+        self.inner.borrow_mut().set_camera_max_speed(max_speed);
+    }
+
+    pub fn render(&mut self) -> Result<(), JsError> {
+        self.inner.borrow_mut().render()?;
+        Ok(())
+    }
+
+    /// Call `callback(message, recovered)` after the GPU device was lost and recreated
+    ///
+    /// `recovered` is false if no new device could be created, the app then stops drawing.
+    pub fn set_device_lost_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.device_lost_callback.replace(callback);
+    }
+
+    /// Look from `"top"`, `"bottom"`, `"front"`, `"back"`, `"left"`, `"right"` or `"isometric"`
+    pub fn set_view(&mut self, view: &str) -> Result<(), JsError> {
+        let view = view.parse().map_err(|_| JsError::new("Unknown view"))?;
+        self.inner.borrow_mut().set_view(view);
+        self.frame_loop.request();
+        Ok(())
+    }
+
+    /// Return the camera to its initial view
+    pub fn reset_camera(&mut self) {
+        self.inner.borrow_mut().reset_camera();
+        self.frame_loop.request();
+    }
+
+    /// Center and dolly the camera so the box fits the canvas from the current direction
+    pub fn fit_to_bounds(
+        &mut self,
+        min_x: f32,
+        min_y: f32,
+        min_z: f32,
+        max_x: f32,
+        max_y: f32,
+        max_z: f32,
+    ) {
+        let bounds = camera::Aabb {
+            min: Vec3::new(min_x, min_y, min_z),
+            max: Vec3::new(max_x, max_y, max_z),
+        };
+        self.inner.borrow_mut().fit_to_bounds(&bounds);
+        self.frame_loop.request();
+    }
+
+    /// Center and dolly the camera so the whole surface fits the canvas
+    pub fn fit_to_surface(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+        let bounds = inner.surface_bounds();
+        inner.fit_to_bounds(&bounds);
+        drop(inner);
+        self.frame_loop.request();
+    }
+
+    /// Switch to `"perspective"` or `"ortho"` projection with a short animated transition
+    pub fn set_projection(&mut self, projection: &str) -> Result<(), JsError> {
+        let projection = projection
+            .parse()
+            .map_err(|_| JsError::new("Unknown projection"))?;
+        self.inner.borrow_mut().set_projection(projection);
+        self.frame_loop.request();
+        Ok(())
+    }
+
+    pub fn is_multisampling_enabled(&self) -> bool {
+        self.inner.borrow().is_multisampling_enabled()
+    }
+
+    pub fn set_multisampling_enabled(&mut self, enabled: bool) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_multisampling_enabled(enabled);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Sample the function on a `width` by `height` grid
+    ///
+    /// Fails without changing the grid if either dimension is below 2 or the grid does not fit
+    /// the GPU's limits, see `max_grid_resolution`.
+    pub fn set_grid_resolution(&mut self, width: u32, height: u32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_grid_resolution(width, height)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Largest square grid resolution the GPU supports
+    pub fn max_grid_resolution(&self) -> u32 {
+        self.inner.borrow().max_grid_resolution()
+    }
+
+    /// Sample the function over `x_min..=x_max` and `y_min..=y_max`
+    pub fn set_domain(
+        &mut self,
+        x_min: f32,
+        x_max: f32,
+        y_min: f32,
+        y_max: f32,
+    ) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_domain(x_min..=x_max, y_min..=y_max)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Show a surface triangulated from scattered samples, a flat array of x, y, z triples
+    ///
+    /// Replaces the sampled function until `clear_points`. Fails if the points enclose no area.
+    pub fn set_points(&mut self, xyz: &[f32]) -> Result<(), JsError> {
+        let points = triples(xyz)?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_scattered_points(&points)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Like `set_points` with the contents of an XYZ or CSV file
+    pub fn set_points_from_text(&mut self, text: &str) -> Result<(), JsError> {
+        let points = scatter::parse_points(text)?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_scattered_points(&points)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Resample scattered samples, a flat array of x, y, z triples, onto the grid
+    ///
+    /// `method` is `"idw"` for inverse distance weighting with `power` over the points within
+    /// `radius`, or all of them if it is 0, or `"rbf"` for Gaussian radial basis functions of
+    /// width `radius`. The domain is fitted to the points and the function replaced until
+    /// `clear_points`. At most 65536 points are interpolated with `"idw"` and 2048 with `"rbf"`.
+    pub fn interpolate_points(
+        &mut self,
+        xyz: &[f32],
+        method: &str,
+        radius: f32,
+        power: f32,
+    ) -> Result<(), JsError> {
+        let interpolation = interpolation(method, radius, power)?;
+        let points = triples(xyz)?;
+        let mut inner = self.inner.borrow_mut();
+        inner.interpolate_scattered_points(&points, interpolation)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Like `interpolate_points` with the contents of an XYZ or CSV file
+    pub fn interpolate_points_from_text(
+        &mut self,
+        text: &str,
+        method: &str,
+        radius: f32,
+        power: f32,
+    ) -> Result<(), JsError> {
+        let interpolation = interpolation(method, radius, power)?;
+        let points = scatter::parse_points(text)?;
+        let mut inner = self.inner.borrow_mut();
+        inner.interpolate_scattered_points(&points, interpolation)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Show the sampled function again
+    pub fn clear_points(&mut self) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.clear_scattered_points();
+        inner.render()?;
+        Ok(())
+    }
+
+    pub fn surface_opacity(&self) -> f32 {
+        self.inner.borrow().surface_opacity()
+    }
+
+    /// Show what lies beneath the active viewport's surface, from 0 for invisible to 1 for
+    /// opaque
+    pub fn set_surface_opacity(&mut self, opacity: f32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_surface_opacity(opacity);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Clipping planes of the active viewport as a flat array of nx, ny, nz, offset quadruples
+    pub fn clip_planes(&self) -> Vec<f32> {
+        self.inner
+            .borrow()
+            .clip_planes()
+            .iter()
+            .flat_map(|plane| {
+                let normal = plane.normal();
+                [normal.x, normal.y, normal.z, plane.offset()]
+            })
+            .collect()
+    }
+
+    /// Cut away the active viewport's surface where `nx * x + ny * y + nz * z > offset`, for
+    /// each nx, ny, nz, offset quadruple of `planes`
+    ///
+    /// The inside of the surface shows in the cap colour where it is cut open. An empty array
+    /// removes the planes.
+    pub fn set_clip_planes(&mut self, planes: &[f32]) -> Result<(), JsError> {
+        let planes = clipping::ClipPlane::from_array(planes).ok_or_else(|| {
+            JsError::new("Expected nx, ny, nz, offset quadruples with finite, nonzero normals")
+        })?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_clip_planes(&planes)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    pub fn set_clip_cap_color(&mut self, r: f32, g: f32, b: f32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_clip_cap_color([r, g, b]);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Overlay markers at `positions`, a flat array of x, y, z triples
+    ///
+    /// `colors` holds an r, g, b triple per marker and `sizes` a diameter in pixels per marker,
+    /// either may be empty for the defaults. Markers are hidden where the surface passes in
+    /// front of them.
+    pub fn set_markers(
+        &mut self,
+        positions: &[f32],
+        colors: &[f32],
+        sizes: &[f32],
+    ) -> Result<(), JsError> {
+        let markers = markers::Marker::from_arrays(positions, colors, sizes).ok_or_else(|| {
+            JsError::new("Expected x, y, z and r, g, b triples and a size per marker")
+        })?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_markers(&markers);
+        inner.render()?;
+        Ok(())
+    }
+
+    pub fn clear_markers(&mut self) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.clear_markers();
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Draw markers as `"circle"`, `"square"` or shaded `"sphere"`
+    pub fn set_marker_shape(&mut self, shape: &str) -> Result<(), JsError> {
+        let shape = shape
+            .parse()
+            .map_err(|_| JsError::new("Unknown marker shape"))?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_marker_shape(shape);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Draw a polyline through `xyz`, a flat array of x, y, z triples ordered by t
+    ///
+    /// `kind` is `"line"` with `width` in pixels or `"tube"` with `width` in world units, 0 for
+    /// the default. `colors` is empty for the default colour, one r, g, b triple for a solid
+    /// colour, or two to blend from the start of the curve to its end. Returns the curve's id.
+    /// Lines have at most 1048576 points and tubes at most 16384.
+    pub fn add_curve(
+        &mut self,
+        xyz: &[f32],
+        kind: &str,
+        width: f32,
+        colors: &[f32],
+    ) -> Result<u32, JsError> {
+        let style = curve_style(kind, width, colors)?;
+        let points = triples(xyz)?;
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.add_curve(&points, style)?;
+        inner.render()?;
+        Ok(id.0)
+    }
+
+    /// Draw a curve sampled at `samples` points with t from `t_start` to `t_end`
+    ///
+    /// `expression` is WGSL evaluating to the `vec3f` point at `t: f32`, such as
+    /// `"vec3f(cos(t), sin(t), 0.1 * t)"`. The style is as for `add_curve`. Resolves to the
+    /// curve's id, or rejects with the compiler's message if the expression is invalid.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_parametric_curve(
+        &mut self,
+        expression: String,
+        t_start: f32,
+        t_end: f32,
+        samples: u32,
+        kind: &str,
+        width: f32,
+        colors: &[f32],
+    ) -> Result<js_sys::Promise, JsError> {
+        let style = curve_style(kind, width, colors)?;
+        let curve = curves::ParametricCurve {
+            expression,
+            t_range: t_start..=t_end,
+            samples,
+        };
+        let inner = self.inner.clone();
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let id = State::add_parametric_curve(&inner, curve, style)
+                .await
+                .map_err(JsError::from)?;
+            inner.borrow_mut().render().map_err(JsError::from)?;
+            Ok(id.0.into())
+        }))
+    }
+
+    pub fn remove_curve(&mut self, id: u32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.remove_curve(curves::CurveId(id));
+        inner.render()?;
+        Ok(())
+    }
+
+    pub fn clear_curves(&mut self) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.clear_curves();
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Split the canvas into `rows` by `cols` viewports, each with its own camera and plots
+    ///
+    /// Viewports are counted row by row from the top left. Camera and plot methods apply to the
+    /// active viewport, which pointer and wheel input switch to the viewport under the cursor.
+    pub fn set_viewport_grid(&mut self, rows: u32, cols: u32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_viewport_grid(rows, cols)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    pub fn active_viewport(&self) -> usize {
+        self.inner.borrow().active_viewport()
+    }
+
+    pub fn set_active_viewport(&mut self, index: usize) -> Result<(), JsError> {
+        if !self.inner.borrow_mut().set_active_viewport(index) {
+            return Err(JsError::new("No viewport with this index"));
+        }
+        Ok(())
+    }
+
+    pub fn are_cameras_linked(&self) -> bool {
+        self.inner.borrow().are_cameras_linked()
+    }
+
+    /// Make every viewport's camera follow the active one
+    pub fn set_cameras_linked(&mut self, linked: bool) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_cameras_linked(linked);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Serialize the camera and plot configuration to a URL-safe string
+    pub fn get_state(&self) -> String {
+        self.inner.borrow().bookmark().to_string()
+    }
+
+    /// Restore a string from `get_state`, including ones saved by older versions
+    pub fn set_state(&mut self, state: &str) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        let bookmark = bookmark::Bookmark::parse_with_defaults(state, inner.bookmark())?;
+        inner.apply_bookmark(&bookmark)?;
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Render the current view as a `width` by `height` PNG, resolving to a `Uint8Array`
+    ///
+    /// The size is independent of the canvas, large images are rendered in tiles. `samples` per
+    /// pixel up to 4 use multisampling, more are supersampled, e.g. 16 or 64 for publication
+    /// figures. At most 8192 by 8192 pixels are rendered, counting supersampled ones.
+    pub fn capture(
+        &mut self,
+        width: u32,
+        height: u32,
+        samples: u32,
+    ) -> Result<js_sys::Promise, JsError> {
+        let capture = self.inner.borrow_mut().capture(width, height, samples)?;
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let bytes = capture.await.map_err(JsError::from)?;
+            Ok(js_sys::Uint8Array::from(bytes.as_slice()).into())
+        }))
+    }
+
+    /// Export the sampled values as `"csv"`, `"csv-matrix"`, `"npy"` or raw `"f32"`
+    ///
+    /// Resolves to a `Uint8Array` with the file contents. Rows follow X and columns follow Y.
+    pub fn export_grid(&self, format: &str) -> Result<js_sys::Promise, JsError> {
+        let format = format
+            .parse()
+            .map_err(|_| JsError::new("Unknown grid format"))?;
+        let export = self.inner.borrow().export_grid(format);
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let bytes = export.await.map_err(JsError::from)?;
+            Ok(js_sys::Uint8Array::from(bytes.as_slice()).into())
+        }))
+    }
+
+    /// Export the surface as `"obj"`, `"stl"`, `"stl-ascii"`, `"ply"` or `"glb"`
+    ///
+    /// Resolves to a `Uint8Array` with the file contents.
+    pub fn export_mesh(&self, format: &str) -> Result<js_sys::Promise, JsError> {
+        let format = format
+            .parse()
+            .map_err(|_| JsError::new("Unknown mesh format"))?;
+        let export = self.inner.borrow().export_mesh(format);
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let bytes = export.await.map_err(JsError::from)?;
+            Ok(js_sys::Uint8Array::from(bytes.as_slice()).into())
+        }))
+    }
+
+    /// Surface coordinates `[x, y, z]` under the canvas pixel `px`, `py`
+    ///
+    /// Pixels are in the canvas' drawing buffer resolution, not CSS pixels. Returns `undefined`
+    /// when the pixel misses the surface or shortly after the grid changed while its heights are
+    /// still being read back.
+    pub fn pick(&mut self, px: f32, py: f32) -> Option<Vec<f32>> {
+        self.inner.borrow_mut().pick(px, py).map(Vec::from)
+    }
+
+    /// Bind a key, named as in `KeyboardEvent.key`, to an action such as `"fit-to-surface"`,
+    /// `"view-top"` or `"resolution-255"`
+    pub fn bind_key(&mut self, key: &str, action: &str) -> Result<(), JsError> {
+        let action = action.parse().map_err(|_| JsError::new("Unknown action"))?;
+        self.gestures.borrow_mut().bindings.bind_key(key, action);
+        Ok(())
+    }
+
+    pub fn unbind_key(&mut self, key: &str) {
+        self.gestures.borrow_mut().bindings.unbind_key(key);
+    }
+
+    /// Set what dragging with the `"primary"`, `"middle"` or `"secondary"` button or
+    /// `"shift-primary"` does, one of `"rotate"`, `"pan"` or `"none"`
+    pub fn bind_drag(&mut self, button: &str, action: &str) -> Result<(), JsError> {
+        let action = action
+            .parse()
+            .map_err(|_| JsError::new("Unknown drag action"))?;
+        let mut gestures = self.gestures.borrow_mut();
+        let bindings = &mut gestures.bindings;
+        let slot = match button {
+            "primary" => &mut bindings.primary,
+            "middle" => &mut bindings.middle,
+            "secondary" => &mut bindings.secondary,
+            "shift-primary" => &mut bindings.shift_primary,
+            _ => return Err(JsError::new("Unknown button")),
+        };
+        *slot = action;
+        Ok(())
+    }
+
+    /// Set how far the camera dollies per pixel scrolled
+    pub fn set_wheel_speed(&mut self, speed: f32) {
+        self.gestures.borrow_mut().bindings.wheel_speed = speed;
+    }
+
+    pub fn is_colorbar_visible(&self) -> bool {
+        self.inner.borrow().is_colorbar_visible()
+    }
+
+    pub fn set_colorbar_visible(&mut self, visible: bool) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_colorbar_visible(visible);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Anchor the colorbar to `"top-left"`, `"top-right"`, `"bottom-left"` or `"bottom-right"`
+    pub fn set_colorbar_corner(&mut self, corner: &str) -> Result<(), JsError> {
+        let corner = corner
+            .parse()
+            .map_err(|_| JsError::new("Unknown colorbar corner"))?;
+        let mut inner = self.inner.borrow_mut();
+        inner.set_colorbar_corner(corner);
+        inner.render()?;
+        Ok(())
+    }
+
+    /// Set the number of labelled values on the colorbar including its minimum and maximum,
+    /// from 2 to 11
+    pub fn set_colorbar_ticks(&mut self, ticks: u32) -> Result<(), JsError> {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_colorbar_ticks(ticks);
+        inner.render()?;
+        Ok(())
+    }
+}
+
+/// Group a flat array of coordinates into points
+fn triples(xyz: &[f32]) -> Result<Vec<[f32; 3]>, JsError> {
+    if !xyz.len().is_multiple_of(3) {
+        return Err(JsError::new("Points must be x, y, z triples"));
+    }
+    Ok(xyz.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect())
+}
+
+fn interpolation(method: &str, radius: f32, power: f32) -> Result<scatter::Interpolation, JsError> {
+    match method {
+        "idw" => Ok(scatter::Interpolation::InverseDistance { power, radius }),
+        "rbf" => Ok(scatter::Interpolation::GaussianRbf { radius }),
+        _ => Err(JsError::new("Unknown interpolation method")),
+    }
+}
+
+fn curve_style(kind: &str, width: f32, colors: &[f32]) -> Result<curves::CurveStyle, JsError> {
+    let kind = kind
+        .parse()
+        .map_err(|_| JsError::new("Unknown curve kind"))?;
+    let mut style = curves::CurveStyle::new(kind);
+    if width != 0.0 {
+        style.width = width;
+    }
+    match *colors {
+        [] => {}
+        [r, g, b] => style.color = curves::CurveColor::Solid([r, g, b]),
+        [r0, g0, b0, r1, g1, b1] => {
+            style.color = curves::CurveColor::Gradient {
+                start: [r0, g0, b0],
+                end: [r1, g1, b1],
+            }
+        }
+        _ => {
+            return Err(JsError::new(
+                "Curve colours must be one or two r, g, b triples",
+            ));
+        }
+    }
+    Ok(style)
+}
+
+// Wasm bindgen currently does not support async constructors
+#[wasm_bindgen]
+pub async fn start_app(canvas: HtmlCanvasElement) -> Result<JsApp, JsError> {
+    // Only the first app can install the logger, later ones keep logging through it
+    let _ = console_log::init();
+    console_error_panic_hook::set_once();
+
+    let width = canvas.width();
+    let height = canvas.height();
+
+    log::info!("New app at resolution {width}, {height}");
+
+    // Create a wgpu instance
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+
+    let surface = instance
+        .create_surface(wgpu::SurfaceTarget::Canvas(canvas.clone()))
+        .map_err(Error::from)?;
+
+    let inner = Rc::new(RefCell::new(
+        State::new(width, height, instance, surface).await?,
+    ));
+    let frame_loop = FrameLoop::new(inner.clone());
+    let gestures = Rc::new(RefCell::new(Gestures::default()));
+    let event_listeners =
+        EventListeners::new(canvas, inner.clone(), frame_loop.clone(), gestures.clone());
+
+    let device_lost_callback = recovery::DeviceLostCallback::default();
+    wasm_bindgen_futures::spawn_local(recovery::watch_device_loss(
+        Rc::downgrade(&inner),
+        device_lost_callback.clone(),
+    ));
+
+    Ok(JsApp {
+        inner,
+        frame_loop,
+        gestures,
+        device_lost_callback,
+        _event_listeners: event_listeners,
+    })
+}
@@ -0,0 +1,701 @@
+//! Offscreen rendering on a software adapter, run with
+//! `cargo test --features headless --target x86_64-unknown-linux-gnu`
+#![cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+
+use std::cell::RefCell;
+
+use glam::{Vec2, Vec3};
+
+use wasmgpu::{
+    Error, ResolutionError, State,
+    bookmark::Bookmark,
+    camera::View,
+    clipping::{ClipPlane, MAX_CLIP_PLANES},
+    curves::{CurveColor, CurveError, CurveKind, CurveStyle, MAX_TUBE_POINTS, ParametricCurve},
+    export::{GridFormat, MeshFormat},
+    markers::{Marker, MarkerShape},
+    scatter::Interpolation,
+};
+
+/// State rendering to a `width` by `height` texture on a software adapter, or `None` to skip the
+/// test where there is none
+fn offscreen(width: u32, height: u32) -> Option<State> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    match pollster::block_on(State::new_offscreen(width, height, instance, true)) {
+        Ok(state) => Some(state),
+        Err(Error::RequestAdapter(err)) => {
+            eprintln!("Skipping, no software adapter: {err}");
+            None
+        }
+        Err(err) => panic!("{err}"),
+    }
+}
+
+/// Pixels of a PNG written by `State::render_png` or `State::capture`
+struct Image {
+    width: usize,
+    rgb: Vec<u8>,
+}
+
+impl Image {
+    /// Decode the uncompressed deflate blocks and unfiltered scanlines the encoder writes
+    fn decode(png: &[u8]) -> Self {
+        let be32 = |bytes: &[u8]| u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        let (mut width, mut zlib) = (0, Vec::new());
+        let mut chunks = &png[8..];
+        while !chunks.is_empty() {
+            let (len, kind) = (be32(chunks), &chunks[4..8]);
+            let data = &chunks[8..8 + len];
+            match kind {
+                b"IHDR" => width = be32(data),
+                b"IDAT" => zlib.extend_from_slice(data),
+                _ => {}
+            }
+            chunks = &chunks[12 + len..];
+        }
+
+        let mut scanlines = Vec::new();
+        let mut blocks = &zlib[2..];
+        loop {
+            let last = blocks[0] & 1 == 1;
+            let len = u16::from_le_bytes([blocks[1], blocks[2]]) as usize;
+            scanlines.extend_from_slice(&blocks[5..5 + len]);
+            blocks = &blocks[5 + len..];
+            if last {
+                break;
+            }
+        }
+
+        let rgb = scanlines
+            .chunks_exact(1 + 3 * width)
+            .flat_map(|scanline| &scanline[1..])
+            .copied()
+            .collect();
+        Self { width, rgb }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = 3 * (y * self.width + x);
+        [self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]]
+    }
+
+    /// Pixels as `(x, y, rgb)`, row by row from the top left
+    fn pixels(&self) -> impl Iterator<Item = (usize, usize, [u8; 3])> + '_ {
+        self.rgb
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, rgb)| (i % self.width, i / self.width, [rgb[0], rgb[1], rgb[2]]))
+    }
+
+    /// Number of pixels within `tolerance` of `color` in every channel
+    fn count(&self, color: [u8; 3], tolerance: u8) -> usize {
+        self.pixels()
+            .filter(|&(_, _, rgb)| close(rgb, color, tolerance))
+            .count()
+    }
+}
+
+fn close(a: [u8; 3], b: [u8; 3], tolerance: u8) -> bool {
+    a.iter().zip(&b).all(|(a, b)| a.abs_diff(*b) <= tolerance)
+}
+
+fn render(state: &mut State) -> Image {
+    Image::decode(&pollster::block_on(state.render_png()).unwrap())
+}
+
+#[test]
+fn renders_png_without_a_surface() {
+    let Some(mut state) = offscreen(64, 48) else {
+        return;
+    };
+
+    let png = pollster::block_on(state.render_png()).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // Width and height in the header
+    assert_eq!(&png[16..24], &[0, 0, 0, 64, 0, 0, 0, 48]);
+
+    // Rows are padded on the GPU, resizing to an unaligned width must still read back cleanly
+    state.resize(33, 17);
+    state.set_multisampling_enabled(true);
+    let png = pollster::block_on(state.render_png()).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 0, 33, 0, 0, 0, 17]);
+
+    // Tick counts are kept to what fits the colorbar
+    state.set_colorbar_ticks(1000);
+    assert_eq!(state.colorbar_ticks(), State::MAX_COLORBAR_TICKS);
+    state.set_colorbar_ticks(0);
+    assert_eq!(state.colorbar_ticks(), 2);
+    pollster::block_on(state.render_png()).unwrap();
+}
+
+#[test]
+fn clamps_bookmarked_cameras() {
+    let Some(mut state) = offscreen(64, 48) else {
+        return;
+    };
+
+    let bookmark = Bookmark {
+        distance: 1e9,
+        fovy: 10.0,
+        ..state.bookmark()
+    };
+    state.apply_bookmark(&bookmark).unwrap();
+    let applied = state.bookmark();
+    assert!(applied.distance <= 15.0);
+    assert!(applied.fovy > 0.0 && applied.fovy < std::f32::consts::PI);
+    pollster::block_on(state.render_png()).unwrap();
+}
+
+#[test]
+fn exports_the_evaluated_grid() {
+    let Some(mut state) = offscreen(16, 16) else {
+        return;
+    };
+    state.set_grid_resolution(3, 2).unwrap();
+
+    let obj = pollster::block_on(state.export_mesh(MeshFormat::Obj)).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 6);
+    assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), 4);
+
+    let stl = pollster::block_on(state.export_mesh(MeshFormat::StlBinary)).unwrap();
+    assert_eq!(stl.len(), 84 + 4 * 50);
+
+    let csv = pollster::block_on(state.export_grid(GridFormat::CsvMatrix)).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 1 + 3);
+    assert!(csv.lines().all(|line| line.split(',').count() == 1 + 2));
+
+    let raw = pollster::block_on(state.export_grid(GridFormat::RawF32)).unwrap();
+    assert_eq!(raw.len(), 6 * 4);
+}
+
+#[test]
+fn rejects_grids_beyond_the_device_limits() {
+    let Some(mut state) = offscreen(64, 48) else {
+        return;
+    };
+    let resolution = state.grid_resolution();
+
+    for (width, height) in [(1, 100), (100, 0)] {
+        assert!(matches!(
+            state.set_grid_resolution(width, height),
+            Err(Error::Resolution(ResolutionError::TooSmall(size))) if size == (width, height)
+        ));
+    }
+    // Sizes are computed in 64 bits, so this neither wraps around nor passes
+    assert!(matches!(
+        state.set_grid_resolution(u32::MAX, u32::MAX),
+        Err(Error::Resolution(ResolutionError::BufferTooLarge { .. }))
+    ));
+    // A thin grid fits in memory but needs more than the usual 65535 workgroups of 16 rows
+    assert!(matches!(
+        state.set_grid_resolution(2, 16 * 65535 + 1),
+        Err(Error::Resolution(ResolutionError::TooManyWorkgroups { workgroups, max }))
+            if workgroups > u64::from(max)
+    ));
+    assert_eq!(state.grid_resolution(), resolution);
+
+    let max = state.max_grid_resolution();
+    assert!(state.set_grid_resolution(max + 1, max + 1).is_err());
+    state.set_grid_resolution(max, max).unwrap();
+    assert_eq!(state.grid_resolution(), (max, max));
+}
+
+#[test]
+fn generates_non_square_grids() {
+    let Some(mut state) = offscreen(16, 16) else {
+        return;
+    };
+    // Wider and taller than one workgroup of 16 in either direction
+    let (rows, cols) = (40, 20);
+    state.set_grid_resolution(rows, cols).unwrap();
+    state.set_domain(-2.0..=2.0, -1.0..=1.0).unwrap();
+    let expected: Vec<(f32, f32)> = (0..rows)
+        .flat_map(|r| {
+            (0..cols).map(move |c| {
+                let x = -2.0 + 4.0 * r as f32 / (rows - 1) as f32;
+                let y = -1.0 + 2.0 * c as f32 / (cols - 1) as f32;
+                (x, y)
+            })
+        })
+        .collect();
+    let assert_coordinates = |xy: Vec<(f32, f32)>| {
+        assert_eq!(xy.len(), expected.len());
+        for (&(x, y), &(ex, ey)) in xy.iter().zip(&expected) {
+            assert!(
+                (x - ex).abs() < 1e-5 && (y - ey).abs() < 1e-5,
+                "{x},{y} != {ex},{ey}"
+            );
+        }
+    };
+    let parse =
+        |line: &str| -> Vec<f32> { line.split([',', ' ']).map(|v| v.parse().unwrap()).collect() };
+
+    let csv = pollster::block_on(state.export_grid(GridFormat::CsvLong)).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_coordinates(
+        csv.lines()
+            .skip(1)
+            .map(|line| {
+                let values = parse(line);
+                (values[0], values[1])
+            })
+            .collect(),
+    );
+
+    // Every vertex of the mesh, not just the first row and column
+    let obj = pollster::block_on(state.export_mesh(MeshFormat::Obj)).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_coordinates(
+        obj.lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let values = parse(line);
+                (values[0], values[1])
+            })
+            .collect(),
+    );
+    let faces = obj.lines().filter(|line| line.starts_with("f ")).count();
+    assert_eq!(faces, 2 * (rows as usize - 1) * (cols as usize - 1));
+}
+
+#[test]
+fn draws_and_exports_scattered_points() {
+    let Some(mut state) = offscreen(32, 32) else {
+        return;
+    };
+    let points = [
+        [0.0, 0.0, 0.0],
+        [2.0, 0.0, 1.0],
+        [2.0, 2.0, 0.5],
+        [0.0, 2.0, 1.5],
+        [1.0, 1.0, 3.0],
+    ];
+    state.set_scattered_points(&points).unwrap();
+    assert_eq!(state.surface_bounds().max.z, 3.0);
+    state.render().unwrap();
+
+    let obj = pollster::block_on(state.export_mesh(MeshFormat::Obj)).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 5);
+    assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), 4);
+
+    state.clear_scattered_points();
+    assert!(!state.has_scattered_points());
+}
+
+#[test]
+fn interpolates_scattered_points_onto_the_grid() {
+    let Some(mut state) = offscreen(32, 32) else {
+        return;
+    };
+    state.set_grid_resolution(3, 3).unwrap();
+    // Every point falls on a vertex of the 3 by 3 grid over their bounds
+    let points = [
+        [0.0, 0.0, 1.0],
+        [2.0, 0.0, 0.5],
+        [0.0, 2.0, -0.5],
+        [2.0, 2.0, 2.0],
+        [1.0, 1.0, 3.0],
+    ];
+    let heights = |state: &mut State| -> Vec<f32> {
+        let raw = pollster::block_on(state.export_grid(GridFormat::RawF32)).unwrap();
+        raw.chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    };
+
+    for interpolation in [
+        Interpolation::InverseDistance {
+            power: 2.0,
+            radius: 0.0,
+        },
+        Interpolation::GaussianRbf { radius: 1.0 },
+    ] {
+        state
+            .interpolate_scattered_points(&points, interpolation)
+            .unwrap();
+        let heights = heights(&mut state);
+        for (i, z) in [(0, 1.0), (2, -0.5), (4, 3.0), (6, 0.5), (8, 2.0)] {
+            assert!(
+                (heights[i] - z).abs() < 1e-3,
+                "{interpolation:?}: {heights:?}"
+            );
+        }
+    }
+
+    // No point is within range of the middle of an edge, which falls back to the mean
+    let local = Interpolation::InverseDistance {
+        power: 1.0,
+        radius: 0.9,
+    };
+    state.interpolate_scattered_points(&points, local).unwrap();
+    assert!((heights(&mut state)[1] - 1.2).abs() < 1e-6);
+
+    state.clear_scattered_points();
+    assert!(!state.has_scattered_points());
+}
+
+#[test]
+fn draws_markers_over_the_surface() {
+    let Some(mut state) = offscreen(64, 48) else {
+        return;
+    };
+
+    let positions = [0.0, 0.0, 0.5, 1.0, -1.0, 0.0, -1.0, 1.0, 2.0];
+    let markers = Marker::from_arrays(&positions, &[], &[4.0, 8.0, 16.0]).unwrap();
+    state.set_markers(&markers);
+    for shape in ["circle", "square", "sphere"] {
+        let shape: MarkerShape = shape.parse().unwrap();
+        state.set_marker_shape(shape);
+        assert_eq!(state.marker_shape(), shape);
+        let png = pollster::block_on(state.render_png()).unwrap();
+        assert_eq!(&png[16..24], &[0, 0, 0, 64, 0, 0, 0, 48]);
+    }
+
+    // Supersampled tiles draw markers with another sample count and pixel scale
+    let capture = state.capture(400, 300, 16).unwrap();
+    let png = pollster::block_on(capture).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 1, 144, 0, 0, 1, 44]);
+
+    state.set_multisampling_enabled(true);
+    state.resize(400, 300);
+    pollster::block_on(state.render_png()).unwrap();
+    state.clear_markers();
+    pollster::block_on(state.render_png()).unwrap();
+}
+
+#[test]
+fn hides_markers_behind_the_surface() {
+    let Some(mut state) = offscreen(160, 120) else {
+        return;
+    };
+    state.set_colorbar_visible(false);
+    let (green, blue) = ([0, 255, 0], [0, 0, 255]);
+
+    // Above the peak and under it
+    let positions = [0.0, 0.0, 3.0, 0.0, 0.0, -1.0];
+    let colors = [0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    let markers = Marker::from_arrays(&positions, &colors, &[4.0, 4.0]).unwrap();
+    state.set_markers(&markers);
+    state.set_marker_shape(MarkerShape::Square);
+    let covered = render(&mut state);
+
+    // Where each marker lands, seen with the surface cut away
+    state
+        .set_clip_planes(&[ClipPlane::new(Vec3::Z, -10.0).unwrap()])
+        .unwrap();
+    let uncovered = render(&mut state);
+    let at = |color| -> Vec<(usize, usize)> {
+        uncovered
+            .pixels()
+            .filter(|&(_, _, rgb)| close(rgb, color, 8))
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    };
+    let (above, below) = (at(green), at(blue));
+    assert!(!above.is_empty() && !below.is_empty());
+
+    assert!(
+        above
+            .iter()
+            .all(|&(x, y)| close(covered.pixel(x, y), green, 8))
+    );
+    assert!(
+        below
+            .iter()
+            .all(|&(x, y)| !close(covered.pixel(x, y), blue, 64))
+    );
+    assert_eq!(covered.count(blue, 64), 0);
+}
+
+#[test]
+fn draws_curves_as_lines_and_tubes() {
+    let Some(mut state) = offscreen(160, 120) else {
+        return;
+    };
+    state.set_colorbar_visible(false);
+    let state = RefCell::new(state);
+
+    let helix = ParametricCurve {
+        expression: "vec3f(2.5 * cos(t), 2.5 * sin(t), 0.1 * t)".into(),
+        t_range: 0.0..=20.0,
+        samples: 400,
+    };
+    let mut tube = CurveStyle::new(CurveKind::Tube);
+    tube.width = 0.2;
+    tube.color = CurveColor::Gradient {
+        start: [0.0, 0.0, 1.0],
+        end: [1.0, 0.0, 0.0],
+    };
+    let id = pollster::block_on(State::add_parametric_curve(&state, helix.clone(), tube)).unwrap();
+
+    let mut state_ref = state.borrow_mut();
+    let mut line = CurveStyle::new(CurveKind::Line);
+    line.width = 4.0;
+    line.color = CurveColor::Solid([0.0, 1.0, 0.0]);
+    let diagonal = [[-3.0, -3.0, 0.0], [0.0, 0.0, 3.0], [3.0, 3.0, 0.0]];
+    state_ref.add_curve(&diagonal, line).unwrap();
+
+    // The surface is grey, only the shaded tube is red or blue without green
+    let tube_pixels = |image: &Image| {
+        image
+            .pixels()
+            .filter(|&(_, _, [r, g, b])| g <= 8 && r.max(b) >= 64)
+            .count()
+    };
+    let image = render(&mut state_ref);
+    assert!(image.count([0, 255, 0], 8) > 0);
+    assert!(tube_pixels(&image) > 0);
+    let capture = state_ref.capture(400, 300, 16).unwrap();
+    pollster::block_on(capture).unwrap();
+
+    assert!(matches!(
+        state_ref.add_curve(&diagonal[..1], line),
+        Err(Error::Curve(CurveError::TooFewPoints))
+    ));
+    assert!(state_ref.remove_curve(id));
+    assert!(!state_ref.remove_curve(id));
+    let image = render(&mut state_ref);
+    assert!(image.count([0, 255, 0], 8) > 0);
+    assert_eq!(tube_pixels(&image), 0);
+    drop(state_ref);
+
+    let invalid = ParametricCurve {
+        expression: "vec3f(t, undefined_name)".into(),
+        ..helix.clone()
+    };
+    let result = pollster::block_on(State::add_parametric_curve(&state, invalid, line));
+    assert!(matches!(
+        result,
+        Err(Error::Curve(CurveError::Expression(_)))
+    ));
+    let dense = ParametricCurve {
+        samples: MAX_TUBE_POINTS as u32 + 1,
+        ..helix
+    };
+    let result = pollster::block_on(State::add_parametric_curve(&state, dense, tube));
+    assert!(matches!(
+        result,
+        Err(Error::Curve(CurveError::TooManyPoints { max, .. })) if max == MAX_TUBE_POINTS
+    ));
+    state.borrow_mut().clear_curves();
+    pollster::block_on(state.borrow_mut().render_png()).unwrap();
+}
+
+#[test]
+fn draws_lines_in_offset_viewports() {
+    let Some(mut state) = offscreen(160, 120) else {
+        return;
+    };
+    state.set_colorbar_visible(false);
+    state.set_viewport_grid(1, 2).unwrap();
+    assert!(state.set_active_viewport(1));
+    let mut line = CurveStyle::new(CurveKind::Line);
+    line.width = 4.0;
+    line.color = CurveColor::Solid([0.0, 1.0, 0.0]);
+    state
+        .add_curve(&[[-3.0, -3.0, 0.0], [0.0, 0.0, 3.0], [3.0, 3.0, 0.0]], line)
+        .unwrap();
+
+    // Only the right cell has the line, drawn both on screen and in captures
+    let green = [0, 255, 0];
+    let image = render(&mut state);
+    let capture = Image::decode(&pollster::block_on(state.capture(160, 120, 1).unwrap()).unwrap());
+    for image in [image, capture] {
+        let (left, right): (Vec<_>, Vec<_>) = image
+            .pixels()
+            .filter(|&(_, _, rgb)| close(rgb, green, 8))
+            .partition(|&(x, _, _)| x < 80);
+        assert!(left.is_empty());
+        assert!(right.len() > 50, "{} line pixels", right.len());
+    }
+}
+
+#[test]
+fn splits_the_target_into_viewports() {
+    let Some(mut state) = offscreen(64, 48) else {
+        return;
+    };
+    assert!(matches!(
+        state.set_viewport_grid(0, 2),
+        Err(Error::ViewportGrid((0, 2)))
+    ));
+    state.set_viewport_grid(2, 2).unwrap();
+
+    // Input goes to the viewport under the cursor
+    assert_eq!(state.viewport_at(50.0, 40.0), Some(3));
+    state.focus_viewport_at(Vec2::new(50.0, 40.0));
+    assert_eq!(state.active_viewport(), 3);
+    let markers = Marker::from_arrays(&[0.0, 0.0, 1.0], &[], &[]).unwrap();
+    state.set_markers(&markers);
+    state.set_inertia_enabled(false);
+    state.drag_camera(Vec2::new(40.0, 30.0), Vec2::new(50.0, 30.0));
+    let moved = state.bookmark();
+    assert!(state.set_active_viewport(0));
+    assert_ne!(state.bookmark().azimuth, moved.azimuth);
+
+    // Linked cameras follow the active one
+    state.set_cameras_linked(true);
+    state.move_camera(0.0, 0.1, 0.0);
+    let leader = state.bookmark();
+    assert!(state.set_active_viewport(2));
+    assert_eq!(state.bookmark(), leader);
+    assert!(!state.set_active_viewport(4));
+
+    state.set_multisampling_enabled(true);
+    pollster::block_on(state.render_png()).unwrap();
+    // Tiles cut through the cells of the viewports
+    let width = state.max_texture_size() + 3;
+    let capture = state.capture(width, 2, 1).unwrap();
+    pollster::block_on(capture).unwrap();
+    let capture = state.capture(40, 30, 16).unwrap();
+    let png = pollster::block_on(capture).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 0, 40, 0, 0, 0, 30]);
+
+    state.set_viewport_grid(1, 2).unwrap();
+    assert_eq!(state.active_viewport(), 1);
+    pollster::block_on(state.render_png()).unwrap();
+}
+
+#[test]
+fn captures_tiles_beyond_the_texture_limit() {
+    let Some(mut state) = offscreen(32, 32) else {
+        return;
+    };
+    let width = state.max_texture_size() + 3;
+
+    let capture = state.capture(width, 2, 1).unwrap();
+    let png = pollster::block_on(capture).unwrap();
+    assert_eq!(&png[16..20], &width.to_be_bytes());
+
+    let capture = state.capture(40, 30, 16).unwrap();
+    let png = pollster::block_on(capture).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 0, 40, 0, 0, 0, 30]);
+
+    assert!(matches!(state.capture(0, 30, 1), Err(Error::ImageSize(_))));
+    assert!(matches!(
+        state.capture(8192, 8193, 1),
+        Err(Error::ImageSize((8192, 8193)))
+    ));
+    // Supersampling by 2 along each axis quadruples the rendered pixels
+    assert!(matches!(
+        state.capture(4097, 4096, 16),
+        Err(Error::ImageSize(_))
+    ));
+}
+
+#[test]
+fn blends_translucent_surfaces() {
+    let Some(mut state) = offscreen(160, 120) else {
+        return;
+    };
+    state.set_colorbar_visible(false);
+    state.set_viewport_grid(1, 2).unwrap();
+    let blue = [0, 0, 255];
+    let markers = Marker::from_arrays(&[0.0, 0.0, -1.0], &[0.0, 0.0, 1.0], &[8.0]).unwrap();
+    state.set_markers(&markers);
+    let opaque = render(&mut state);
+
+    state.set_surface_opacity(f32::NAN);
+    assert_eq!(state.surface_opacity(), 1.0);
+
+    // Find the marker under the surface by making the surface invisible
+    state.set_surface_opacity(0.0);
+    let bare = render(&mut state);
+    let marker: Vec<_> = bare
+        .pixels()
+        .filter(|&(_, _, rgb)| close(rgb, blue, 8))
+        .collect();
+    assert!(!marker.is_empty());
+    let (x, y, _) = marker[marker.len() / 2];
+
+    state.set_surface_opacity(0.5);
+    assert_eq!(state.surface_opacity(), 0.5);
+    let translucent = render(&mut state);
+    let [surface, behind, blended] = [&opaque, &bare, &translucent].map(|image| image.pixel(x, y));
+    for channel in 0..3 {
+        let (lo, hi) = (
+            surface[channel].min(behind[channel]),
+            surface[channel].max(behind[channel]),
+        );
+        assert!(
+            (lo..=hi).contains(&blended[channel]),
+            "{blended:?} outside {surface:?} and {behind:?}"
+        );
+    }
+    // Hidden when opaque, and neither surface nor marker alone when translucent
+    assert!(!close(surface, blue, 64));
+    assert!(!close(blended, surface, 32) && !close(blended, behind, 32));
+
+    // Accumulation targets follow the sample count and size
+    state.set_multisampling_enabled(true);
+    let multisampled = render(&mut state);
+    assert!(close(multisampled.pixel(x, y), blended, 8));
+    state.resize(80, 60);
+    pollster::block_on(state.render_png()).unwrap();
+    let capture = state.capture(40, 30, 16).unwrap();
+    pollster::block_on(capture).unwrap();
+
+    state.set_surface_opacity(1.0);
+    pollster::block_on(state.render_png()).unwrap();
+}
+
+#[test]
+fn clips_the_surface() {
+    let Some(mut state) = offscreen(160, 120) else {
+        return;
+    };
+    state.set_colorbar_visible(false);
+    let top = ClipPlane::new(Vec3::Z, 0.25).unwrap();
+    assert!(matches!(
+        state.set_clip_planes(&[top; MAX_CLIP_PLANES + 1]),
+        Err(Error::ClipPlanes(count)) if count == MAX_CLIP_PLANES + 1
+    ));
+    assert!(state.clip_planes().is_empty());
+
+    // Pixels covering the part of the surface the plane cuts away
+    render(&mut state);
+    let mut cut = Vec::new();
+    for (x, y) in (0..120).flat_map(|y| (0..160).map(move |x| (x, y))) {
+        let hit = state.pick(x as f32 + 0.5, y as f32 + 0.5);
+        if hit.is_some_and(|hit| hit[2] > 0.3) {
+            cut.push((x, y));
+        }
+    }
+    assert!(!cut.is_empty());
+
+    let green = [0, 255, 0];
+    state.set_clip_cap_color([0.0, 1.0, 0.0]);
+    state.set_clip_planes(&[top]).unwrap();
+    assert_eq!(state.clip_planes(), &[top]);
+    let clipped = render(&mut state);
+    let (mut through, mut open) = (Vec::new(), Vec::new());
+    for &(x, y) in &cut {
+        match state.pick(x as f32 + 0.5, y as f32 + 0.5) {
+            Some(hit) => through.push((x, y, hit)),
+            None => open.push((x, y)),
+        }
+    }
+
+    // The ray through the cut lands on the inside of the surface below the plane
+    let &(x, y, hit) = through.first().expect("no pixel sees through the cut");
+    assert!(hit[2] <= 0.25);
+    let capped = through
+        .iter()
+        .filter(|&&(x, y, _)| close(clipped.pixel(x, y), green, 8))
+        .count();
+    assert!(capped > 0, "{:?} at {x}, {y}", clipped.pixel(x, y));
+    // Where nothing is left behind the cut, the background shows
+    let (x, y) = open[open.len() / 2];
+    assert!(close(clipped.pixel(x, y), [0, 0, 0], 8));
+    // The underside seen from below the plane is not seen through the cut
+    state.set_view(View::Bottom);
+    assert_eq!(render(&mut state).count(green, 8), 0);
+
+    state.set_surface_opacity(0.5);
+    pollster::block_on(state.render_png()).unwrap();
+    state.set_clip_planes(&[]).unwrap();
+    pollster::block_on(state.render_png()).unwrap();
+}